@@ -0,0 +1,12 @@
+CREATE TABLE users_without_role (
+    id TEXT NOT NULL PRIMARY KEY,
+    display_name TEXT NOT NULL,
+    contact_info TEXT NOT NULL,
+    password_hash TEXT NOT NULL
+);
+
+INSERT INTO users_without_role (id, display_name, contact_info, password_hash)
+    SELECT id, display_name, contact_info, password_hash FROM users;
+
+DROP TABLE users;
+ALTER TABLE users_without_role RENAME TO users;
\ No newline at end of file
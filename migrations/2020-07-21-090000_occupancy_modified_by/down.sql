@@ -0,0 +1,21 @@
+CREATE TABLE occupancies_without_modified_by (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "start" TIMESTAMP NOT NULL,
+    "end" TIMESTAMP NOT NULL,
+    user_id TEXT NOT NULL,
+    user_name TEXT NOT NULL,
+    user_contact TEXT NOT NULL,
+    room TEXT NOT NULL REFERENCES rooms(id)
+);
+
+INSERT INTO occupancies_without_modified_by (id, "start", "end", user_id, user_name, user_contact, room)
+    SELECT id, "start", "end", user_id, user_name, user_contact, room FROM occupancies;
+
+DROP TABLE occupancies;
+ALTER TABLE occupancies_without_modified_by RENAME TO occupancies;
+
+CREATE INDEX idx_occ_start  ON occupancies("start");
+CREATE INDEX idx_occ_end  ON occupancies("end");
+CREATE INDEX idx_occ_room ON occupancies(room);
+CREATE INDEX idx_occ_user_id ON occupancies(user_id);
+CREATE INDEX idx_occ_user_name ON occupancies(user_name);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE occupancies ADD COLUMN modified_by TEXT;
\ No newline at end of file
@@ -0,0 +1,18 @@
+CREATE TABLE audit_log (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    changed_at TIMESTAMP NOT NULL,
+    actor TEXT NOT NULL,
+    "action" TEXT NOT NULL,
+    occupancy_id INTEGER NOT NULL,
+    room TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    old_start TIMESTAMP,
+    old_end TIMESTAMP,
+    new_start TIMESTAMP,
+    new_end TIMESTAMP
+);
+
+CREATE INDEX idx_audit_changed_at ON audit_log(changed_at);
+CREATE INDEX idx_audit_actor ON audit_log(actor);
+CREATE INDEX idx_audit_room ON audit_log(room);
+CREATE INDEX idx_audit_user_id ON audit_log(user_id);
\ No newline at end of file
@@ -0,0 +1,16 @@
+CREATE TABLE series (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    room TEXT NOT NULL REFERENCES rooms(id),
+    user_id TEXT NOT NULL,
+    first_start TIMESTAMP NOT NULL,
+    first_end TIMESTAMP NOT NULL,
+    weekdays TEXT NOT NULL,
+    until DATE NOT NULL
+);
+
+CREATE INDEX idx_series_room ON series(room);
+CREATE INDEX idx_series_user_id ON series(user_id);
+
+ALTER TABLE occupancies ADD COLUMN series_id INTEGER REFERENCES series(id);
+
+CREATE INDEX idx_occ_series_id ON occupancies(series_id);
\ No newline at end of file
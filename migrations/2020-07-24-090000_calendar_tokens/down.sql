@@ -0,0 +1 @@
+DROP TABLE calendar_tokens;
\ No newline at end of file
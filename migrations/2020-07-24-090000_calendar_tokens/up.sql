@@ -0,0 +1,7 @@
+CREATE TABLE calendar_tokens (
+    token TEXT NOT NULL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_calendar_tokens_user_id ON calendar_tokens(user_id);
\ No newline at end of file
@@ -0,0 +1,13 @@
+CREATE TABLE calendar_tokens_without_role (
+    token TEXT NOT NULL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+INSERT INTO calendar_tokens_without_role (token, user_id, created_at)
+    SELECT token, user_id, created_at FROM calendar_tokens;
+
+DROP TABLE calendar_tokens;
+ALTER TABLE calendar_tokens_without_role RENAME TO calendar_tokens;
+
+CREATE INDEX idx_calendar_tokens_user_id ON calendar_tokens(user_id);
\ No newline at end of file
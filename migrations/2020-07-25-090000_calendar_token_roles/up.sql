@@ -0,0 +1 @@
+ALTER TABLE calendar_tokens ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
\ No newline at end of file
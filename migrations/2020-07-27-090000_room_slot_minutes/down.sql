@@ -0,0 +1,11 @@
+CREATE TABLE rooms_without_slot_minutes (
+    id TEXT NOT NULL PRIMARY KEY,
+    max_occupancy INTEGER NOT NULL,
+    timezone TEXT
+);
+
+INSERT INTO rooms_without_slot_minutes (id, max_occupancy, timezone)
+    SELECT id, max_occupancy, timezone FROM rooms;
+
+DROP TABLE rooms;
+ALTER TABLE rooms_without_slot_minutes RENAME TO rooms;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE rooms ADD COLUMN slot_minutes INTEGER;
\ No newline at end of file
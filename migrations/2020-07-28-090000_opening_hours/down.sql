@@ -0,0 +1,2 @@
+DROP TABLE closures;
+DROP TABLE opening_hours;
\ No newline at end of file
@@ -0,0 +1,17 @@
+CREATE TABLE opening_hours (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    room TEXT NOT NULL REFERENCES rooms(id),
+    weekday TEXT NOT NULL,
+    opens TIME NOT NULL,
+    closes TIME NOT NULL
+);
+
+CREATE INDEX idx_opening_hours_room ON opening_hours(room);
+
+CREATE TABLE closures (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    room TEXT NOT NULL REFERENCES rooms(id),
+    day DATE NOT NULL,
+    reason TEXT,
+    UNIQUE(room, day)
+);
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE holidays (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    room TEXT REFERENCES rooms(id),
+    day DATE NOT NULL,
+    name TEXT NOT NULL
+);
+
+CREATE INDEX idx_holidays_day ON holidays(day);
\ No newline at end of file
@@ -0,0 +1,12 @@
+CREATE TABLE rooms_without_quotas (
+    id TEXT NOT NULL PRIMARY KEY,
+    max_occupancy INTEGER NOT NULL,
+    timezone TEXT,
+    slot_minutes INTEGER
+);
+
+INSERT INTO rooms_without_quotas (id, max_occupancy, timezone, slot_minutes)
+    SELECT id, max_occupancy, timezone, slot_minutes FROM rooms;
+
+DROP TABLE rooms;
+ALTER TABLE rooms_without_quotas RENAME TO rooms;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE rooms ADD COLUMN max_hours_per_week INTEGER;
+ALTER TABLE rooms ADD COLUMN max_future_bookings INTEGER;
+ALTER TABLE rooms ADD COLUMN max_days_ahead INTEGER;
\ No newline at end of file
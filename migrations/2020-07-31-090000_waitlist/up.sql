@@ -0,0 +1,24 @@
+CREATE TABLE waitlist (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    room TEXT NOT NULL REFERENCES rooms(id),
+    user_id TEXT NOT NULL,
+    user_name TEXT NOT NULL,
+    user_contact TEXT NOT NULL,
+    "start" TIMESTAMP NOT NULL,
+    "end" TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_waitlist_room ON waitlist(room);
+CREATE INDEX idx_waitlist_user_id ON waitlist(user_id);
+
+CREATE TABLE notifications (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_notifications_user_id ON notifications(user_id);
\ No newline at end of file
@@ -0,0 +1,16 @@
+CREATE TABLE notifications_without_delivery (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+INSERT INTO notifications_without_delivery (id, user_id, recipient, subject, body, created_at)
+    SELECT id, user_id, recipient, subject, body, created_at FROM notifications;
+
+DROP TABLE notifications;
+ALTER TABLE notifications_without_delivery RENAME TO notifications;
+
+CREATE INDEX idx_notifications_user_id ON notifications(user_id);
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE notifications ADD COLUMN sent_at TIMESTAMP;
+ALTER TABLE notifications ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE notifications ADD COLUMN last_error TEXT;
+
+CREATE INDEX idx_notifications_sent_at ON notifications(sent_at);
\ No newline at end of file
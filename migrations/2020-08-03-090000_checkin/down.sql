@@ -0,0 +1,40 @@
+CREATE TABLE occupancies_without_checkin (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "start" TIMESTAMP NOT NULL,
+    "end" TIMESTAMP NOT NULL,
+    user_id TEXT NOT NULL,
+    user_name TEXT NOT NULL,
+    user_contact TEXT NOT NULL,
+    room TEXT NOT NULL REFERENCES rooms(id),
+    modified_by TEXT,
+    series_id INTEGER REFERENCES series(id)
+);
+
+INSERT INTO occupancies_without_checkin (id, "start", "end", user_id, user_name, user_contact, room, modified_by, series_id)
+    SELECT id, "start", "end", user_id, user_name, user_contact, room, modified_by, series_id FROM occupancies;
+
+DROP TABLE occupancies;
+ALTER TABLE occupancies_without_checkin RENAME TO occupancies;
+
+CREATE INDEX idx_occ_start  ON occupancies("start");
+CREATE INDEX idx_occ_end  ON occupancies("end");
+CREATE INDEX idx_occ_room ON occupancies(room);
+CREATE INDEX idx_occ_user_id ON occupancies(user_id);
+CREATE INDEX idx_occ_user_name ON occupancies(user_name);
+CREATE INDEX idx_occ_series_id ON occupancies(series_id);
+
+CREATE TABLE rooms_without_checkin (
+    id TEXT NOT NULL PRIMARY KEY,
+    max_occupancy INTEGER NOT NULL,
+    timezone TEXT,
+    slot_minutes INTEGER,
+    max_hours_per_week INTEGER,
+    max_future_bookings INTEGER,
+    max_days_ahead INTEGER
+);
+
+INSERT INTO rooms_without_checkin (id, max_occupancy, timezone, slot_minutes, max_hours_per_week, max_future_bookings, max_days_ahead)
+    SELECT id, max_occupancy, timezone, slot_minutes, max_hours_per_week, max_future_bookings, max_days_ahead FROM rooms;
+
+DROP TABLE rooms;
+ALTER TABLE rooms_without_checkin RENAME TO rooms;
\ No newline at end of file
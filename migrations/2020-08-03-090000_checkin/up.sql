@@ -0,0 +1,2 @@
+ALTER TABLE occupancies ADD COLUMN checked_in_at TIMESTAMP;
+ALTER TABLE rooms ADD COLUMN checkin_token TEXT;
\ No newline at end of file
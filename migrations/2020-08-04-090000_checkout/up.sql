@@ -0,0 +1 @@
+ALTER TABLE occupancies ADD COLUMN checked_out_at TIMESTAMP;
\ No newline at end of file
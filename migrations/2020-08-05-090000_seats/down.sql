@@ -0,0 +1,28 @@
+CREATE TABLE occupancies_without_seats (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "start" TIMESTAMP NOT NULL,
+    "end" TIMESTAMP NOT NULL,
+    user_id TEXT NOT NULL,
+    user_name TEXT NOT NULL,
+    user_contact TEXT NOT NULL,
+    room TEXT NOT NULL REFERENCES rooms(id),
+    modified_by TEXT,
+    series_id INTEGER REFERENCES series(id),
+    checked_in_at TIMESTAMP,
+    checked_out_at TIMESTAMP
+);
+
+INSERT INTO occupancies_without_seats (id, "start", "end", user_id, user_name, user_contact, room, modified_by, series_id, checked_in_at, checked_out_at)
+    SELECT id, "start", "end", user_id, user_name, user_contact, room, modified_by, series_id, checked_in_at, checked_out_at FROM occupancies;
+
+DROP TABLE occupancies;
+ALTER TABLE occupancies_without_seats RENAME TO occupancies;
+
+CREATE INDEX idx_occ_start  ON occupancies("start");
+CREATE INDEX idx_occ_end  ON occupancies("end");
+CREATE INDEX idx_occ_room ON occupancies(room);
+CREATE INDEX idx_occ_user_id ON occupancies(user_id);
+CREATE INDEX idx_occ_user_name ON occupancies(user_name);
+CREATE INDEX idx_occ_series_id ON occupancies(series_id);
+
+DROP TABLE seats;
\ No newline at end of file
@@ -0,0 +1,12 @@
+CREATE TABLE seats (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    room TEXT NOT NULL REFERENCES rooms(id),
+    label TEXT NOT NULL,
+    attributes TEXT NOT NULL DEFAULT ''
+);
+
+CREATE INDEX idx_seats_room ON seats(room);
+
+ALTER TABLE occupancies ADD COLUMN seat_id INTEGER REFERENCES seats(id);
+
+CREATE INDEX idx_occ_seat_id ON occupancies(seat_id);
\ No newline at end of file
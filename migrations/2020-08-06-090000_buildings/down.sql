@@ -0,0 +1,19 @@
+CREATE TABLE rooms_without_floors (
+    id TEXT NOT NULL PRIMARY KEY,
+    max_occupancy INTEGER NOT NULL,
+    timezone TEXT,
+    slot_minutes INTEGER,
+    max_hours_per_week INTEGER,
+    max_future_bookings INTEGER,
+    max_days_ahead INTEGER,
+    checkin_token TEXT
+);
+
+INSERT INTO rooms_without_floors (id, max_occupancy, timezone, slot_minutes, max_hours_per_week, max_future_bookings, max_days_ahead, checkin_token)
+    SELECT id, max_occupancy, timezone, slot_minutes, max_hours_per_week, max_future_bookings, max_days_ahead, checkin_token FROM rooms;
+
+DROP TABLE rooms;
+ALTER TABLE rooms_without_floors RENAME TO rooms;
+
+DROP TABLE floors;
+DROP TABLE buildings;
\ No newline at end of file
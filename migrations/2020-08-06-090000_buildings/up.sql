@@ -0,0 +1,16 @@
+CREATE TABLE buildings (
+    id TEXT NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    max_occupancy INTEGER
+);
+
+CREATE TABLE floors (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    building TEXT NOT NULL REFERENCES buildings(id),
+    name TEXT NOT NULL,
+    level INTEGER NOT NULL
+);
+
+CREATE INDEX idx_floors_building ON floors(building);
+
+ALTER TABLE rooms ADD COLUMN floor_id INTEGER REFERENCES floors(id);
\ No newline at end of file
@@ -0,0 +1,17 @@
+CREATE TABLE rooms_without_attributes (
+    id TEXT NOT NULL PRIMARY KEY,
+    max_occupancy INTEGER NOT NULL,
+    timezone TEXT,
+    slot_minutes INTEGER,
+    max_hours_per_week INTEGER,
+    max_future_bookings INTEGER,
+    max_days_ahead INTEGER,
+    checkin_token TEXT,
+    floor_id INTEGER REFERENCES floors(id)
+);
+
+INSERT INTO rooms_without_attributes (id, max_occupancy, timezone, slot_minutes, max_hours_per_week, max_future_bookings, max_days_ahead, checkin_token, floor_id)
+    SELECT id, max_occupancy, timezone, slot_minutes, max_hours_per_week, max_future_bookings, max_days_ahead, checkin_token, floor_id FROM rooms;
+
+DROP TABLE rooms;
+ALTER TABLE rooms_without_attributes RENAME TO rooms;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE rooms ADD COLUMN attributes TEXT NOT NULL DEFAULT '';
\ No newline at end of file
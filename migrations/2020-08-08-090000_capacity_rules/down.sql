@@ -0,0 +1 @@
+DROP TABLE capacity_rules;
\ No newline at end of file
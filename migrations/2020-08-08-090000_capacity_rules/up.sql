@@ -0,0 +1,12 @@
+CREATE TABLE capacity_rules (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    room TEXT NOT NULL REFERENCES rooms(id),
+    max_occupancy INTEGER NOT NULL,
+    valid_from DATE,
+    valid_to DATE,
+    weekdays TEXT NOT NULL DEFAULT '',
+    from_time TIME,
+    to_time TIME
+);
+
+CREATE INDEX idx_capacity_rules_room ON capacity_rules(room);
\ No newline at end of file
@@ -1,23 +1,41 @@
 use crate::errors::ServiceError;
 use crate::{
+    audit::{self, AuditFilter},
+    buildings, capacity,
     config::Settings,
-    extractors::ClaimsFromAuth,
-    models::{NewOccupancy, Occupancy, Room, User},
-    DbPool,
+    export,
+    extractors::{AdminFromAuth, ClaimsFromAuth, RoomManagerFromAuth},
+    holidays, ical,
+    models::{
+        end_time_on, last_insert_rowid, split_attributes, Building, CalendarToken, CapacityRule,
+        Closure, Floor, Holiday, NewCapacityRule, NewClosure, NewFloor, NewOccupancy,
+        NewOpeningHours, NewSeat, NewSeries, NewWaitlistEntry, Occupancy, OpeningHours, Role, Room,
+        RoomChanges, Seat, Series, User, WaitlistEntry,
+    },
+    notifications::{self, OccupancyChange},
+    quotas, retention, seats, waitlist, webhooks, DbPool,
 };
 use actix_web::{web, HttpResponse};
-use chrono::{DateTime, Duration, DurationRound, TimeZone};
+use chrono::{
+    DateTime, Datelike, Duration, DurationRound, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike, Weekday,
+};
 use diesel::prelude::*;
 use hmac::{Hmac, Mac};
 use jwt::SignWithKey;
 use ldap3::{LdapConnAsync, Scope, SearchEntry};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use sha2::Sha256;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub name: String,
     pub contact_info: String,
+    /// The role determines which actions are allowed for the user
+    #[serde(default)]
+    pub role: Role,
     /// Expiration date as unix timestamp in seconds since epoch and UTC
     pub exp: Option<i64>,
 }
@@ -32,6 +50,7 @@ fn create_signed_token(
     sub: &str,
     name: &str,
     contact_info: &str,
+    role: Role,
     settings: &Settings,
 ) -> Result<String, ServiceError> {
     // Create the JWT token
@@ -63,12 +82,25 @@ fn create_signed_token(
         exp: exp,
         name: name.to_string(),
         contact_info: contact_info.to_string(),
+        role,
     };
     // Create the actual token
     let token_str = claims.sign_with_key(&key)?;
     Ok(token_str)
 }
 
+/// Derive the role of an LDAP user from the configured group membership.
+fn role_from_ldap_groups(groups: &[String], settings: &Settings) -> Role {
+    let is_member = |configured: &[String]| groups.iter().any(|g| configured.contains(g));
+    if is_member(&settings.ldap.admin_groups) {
+        Role::Admin
+    } else if is_member(&settings.ldap.room_manager_groups) {
+        Role::RoomManager
+    } else {
+        Role::User
+    }
+}
+
 pub async fn login(
     login_data: web::Json<LoginData>,
     db_pool: web::Data<DbPool>,
@@ -86,10 +118,13 @@ pub async fn login(
             // Compare provided password with actual hash
             let verified = bcrypt::verify(&login_data.password, actual_hash)?;
             if verified {
+                let user_role: Role =
+                    u.role.parse().map_err(ServiceError::InternalServerError)?;
                 let token_str = create_signed_token(
                     &u.id,
                     &u.display_name,
                     &u.contact_info,
+                    user_role,
                     settings.as_ref(),
                 )?;
                 return Ok(HttpResponse::Ok()
@@ -105,7 +140,11 @@ pub async fn login(
 
         ldap3::drive!(conn);
 
-        let user_query = format!("uid={},{}", &login_data.user_id, settings.ldap.organization);
+        let user_query = format!(
+            "uid={},{}",
+            ldap3::dn_escape(login_data.user_id.as_str()),
+            settings.ldap.organization
+        );
         let result = ldap.simple_bind(&user_query, &login_data.password).await?;
         if result.rc == 0 {
             // Gather additional information from LDAP, only accept users that are also matching the filter from the configuration
@@ -114,7 +153,11 @@ pub async fn login(
                     &user_query,
                     Scope::Subtree,
                     &settings.ldap.filter,
-                    vec!["cn", "publicEMailAddress"],
+                    vec![
+                        "cn",
+                        "publicEMailAddress",
+                        settings.ldap.group_attribute.as_str(),
+                    ],
                 )
                 .await?
                 .success()?;
@@ -126,10 +169,28 @@ pub async fn login(
                     user_attributes.attrs.get("publicEMailAddress"),
                 ) {
                     if !cn.is_empty() && !email.is_empty() {
+                        let groups = user_attributes
+                            .attrs
+                            .get(&settings.ldap.group_attribute)
+                            .map(|g| g.as_slice())
+                            .unwrap_or_default();
+                        let user_role = role_from_ldap_groups(groups, &settings);
+                        // The role of LDAP users can change at any time, so keep the role
+                        // stored with their calendar token up to date
+                        {
+                            use crate::schema::calendar_tokens::dsl;
+                            let conn = db_pool.get()?;
+                            diesel::update(
+                                dsl::calendar_tokens.filter(dsl::user_id.eq(&login_data.user_id)),
+                            )
+                            .set(dsl::role.eq(user_role.to_string()))
+                            .execute(&conn)?;
+                        }
                         let token_str = create_signed_token(
                             &login_data.user_id,
                             &cn[0],
                             &email[0],
+                            user_role,
                             &settings.as_ref(),
                         )?;
                         return Ok(HttpResponse::Ok()
@@ -143,165 +204,552 @@ pub async fn login(
     Ok(HttpResponse::Unauthorized().finish())
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct TimeRange {
     pub start: String,
     pub end: String,
 }
 
+/// The time range of a new occupancy and optionally the seat to book.
+#[derive(Deserialize)]
+pub struct OccupancyProperties {
+    pub start: String,
+    pub end: String,
+    /// ID of a seat of the room, if a specific seat should be booked
+    pub seat: Option<i32>,
+    /// IDs of the users to book the room for, only the current user if empty
+    #[serde(default)]
+    pub participants: Vec<String>,
+}
+
+/// Opening hours of a room on a weekday, with times in the timezone of the room.
+#[derive(Serialize, Deserialize)]
+pub struct OpeningHoursProperties {
+    /// Weekday as two-letter code, e.g. "MO"
+    pub weekday: String,
+    /// Local time in the format "HH:MM"
+    pub opens: String,
+    /// Local time in the format "HH:MM", "24:00" if the room closes at midnight
+    pub closes: String,
+}
+
+/// Parse a local time in the format "HH:MM".
+fn parse_time(t: &str) -> Result<NaiveTime, ServiceError> {
+    NaiveTime::parse_from_str(t, "%H:%M").map_err(|_| {
+        ServiceError::BadRequest(format!("Invalid time \"{}\", use the format HH:MM.", t))
+    })
+}
+
+/// Parse the local end time of a time range in the format "HH:MM".
+///
+/// Midnight at the end of the day can be given as "24:00" and is stored as 00:00.
+fn parse_end_time(t: &str) -> Result<NaiveTime, ServiceError> {
+    if t == "24:00" {
+        Ok(NaiveTime::from_hms(0, 0, 0))
+    } else {
+        parse_time(t)
+    }
+}
+
+/// Format the local end time of a time range, midnight is shown as "24:00".
+fn format_end_time(t: NaiveTime) -> String {
+    if t == NaiveTime::from_hms(0, 0, 0) {
+        "24:00".to_string()
+    } else {
+        t.format("%H:%M").to_string()
+    }
+}
+
+impl OpeningHoursProperties {
+    fn parse(&self, room: &str) -> Result<NewOpeningHours, ServiceError> {
+        let weekday = parse_weekday(&self.weekday)?;
+        let opens = parse_time(&self.opens)?;
+        let closes = parse_end_time(&self.closes)?;
+        if closes != NaiveTime::from_hms(0, 0, 0) && closes <= opens {
+            return Err(ServiceError::BadRequest(format!(
+                "Opening hours on {} close ({}) before they open ({}).",
+                weekday_code(weekday),
+                self.closes,
+                self.opens
+            )));
+        }
+        Ok(NewOpeningHours {
+            room: room.to_string(),
+            weekday: weekday_code(weekday).to_string(),
+            opens,
+            closes,
+        })
+    }
+}
+
+impl From<OpeningHours> for OpeningHoursProperties {
+    fn from(hours: OpeningHours) -> Self {
+        OpeningHoursProperties {
+            weekday: hours.weekday,
+            opens: hours.opens.format("%H:%M").to_string(),
+            closes: format_end_time(hours.closes),
+        }
+    }
+}
+
+/// A room together with the information when it can be booked.
+#[derive(Serialize)]
+pub struct RoomDescription {
+    #[serde(flatten)]
+    pub room: Room,
+    /// If empty, the room is open at any time
+    pub opening_hours: Vec<OpeningHoursProperties>,
+    /// Closures from today on
+    pub closures: Vec<Closure>,
+    /// Holidays of this room or of all rooms from today on
+    pub holidays: Vec<Holiday>,
+    /// If true, users need the check-in token of the room to check in
+    pub checkin_token_required: bool,
+    /// ID of the building the room is in, if it is assigned to a floor
+    pub building: Option<String>,
+    /// Maximum occupancy at the current time, taking the capacity rules into account
+    pub current_max_occupancy: i32,
+    /// Capacity rules that are currently valid or become valid in the future
+    pub capacity_rules: Vec<CapacityRuleDescription>,
+}
+
+#[derive(Deserialize)]
+pub struct RoomFilter {
+    /// Only include rooms in this building
+    pub building: Option<String>,
+    /// Only include rooms on this floor
+    pub floor: Option<i32>,
+}
+
 pub async fn all_rooms(
+    filter: web::Query<RoomFilter>,
     db_pool: web::Data<DbPool>,
     _claims: ClaimsFromAuth,
 ) -> Result<HttpResponse, ServiceError> {
     let conn = db_pool.get()?;
 
     // Get the general room capacity
+    use crate::schema::buildings::dsl as b_dsl;
+    use crate::schema::capacity_rules;
+    use crate::schema::closures;
+    use crate::schema::floors::dsl as f_dsl;
+    use crate::schema::holidays::dsl as h_dsl;
+    use crate::schema::opening_hours;
     use crate::schema::rooms;
-    let rooms: Vec<Room> = rooms::dsl::rooms.order(rooms::dsl::id).load(&conn)?;
+    let mut query = rooms::dsl::rooms.order(rooms::dsl::id).into_boxed();
+    if let Some(building) = &filter.building {
+        query = query
+            .filter(rooms::dsl::id.eq_any(buildings::find_rooms_of_building(&conn, building)?));
+    }
+    if let Some(floor) = filter.floor {
+        query = query.filter(rooms::dsl::floor_id.eq(floor));
+    }
+    let rooms: Vec<Room> = query.load(&conn)?;
+    let room_ids: Vec<&str> = rooms.iter().map(|r| r.id.as_str()).collect();
 
-    Ok(HttpResponse::Ok().json(rooms))
+    // Start one day earlier, because the local date of a room can be behind the UTC date
+    let today = chrono::Utc::now().naive_utc().date().pred();
+
+    // Load the related information of all rooms at once instead of querying it for each room
+    let mut hours = group_by_room(
+        opening_hours::dsl::opening_hours
+            .filter(opening_hours::dsl::room.eq_any(&room_ids))
+            .order(opening_hours::dsl::id)
+            .load::<OpeningHours>(&conn)?,
+        |h| &h.room,
+    );
+    let mut closures = group_by_room(
+        closures::dsl::closures
+            .filter(closures::dsl::room.eq_any(&room_ids))
+            .filter(closures::dsl::day.ge(today))
+            .order(closures::dsl::day)
+            .load::<Closure>(&conn)?,
+        |c| &c.room,
+    );
+    let mut capacity_rules = group_by_room(
+        capacity_rules::dsl::capacity_rules
+            .filter(capacity_rules::dsl::room.eq_any(&room_ids))
+            .order((capacity_rules::dsl::valid_from, capacity_rules::dsl::id))
+            .load::<CapacityRule>(&conn)?,
+        |r| &r.room,
+    );
+    let all_holidays: Vec<Holiday> = h_dsl::holidays
+        .filter(h_dsl::room.eq_any(&room_ids).or(h_dsl::room.is_null()))
+        .filter(h_dsl::day.ge(today))
+        .order(h_dsl::day)
+        .load(&conn)?;
+    let floor_ids: Vec<i32> = rooms.iter().filter_map(|r| r.floor_id).collect();
+    let building_of_floor: BTreeMap<i32, String> = b_dsl::buildings
+        .inner_join(f_dsl::floors)
+        .filter(f_dsl::id.eq_any(floor_ids))
+        .select((f_dsl::id, b_dsl::id))
+        .load(&conn)?
+        .into_iter()
+        .collect();
+
+    let now = chrono::Utc::now().naive_utc();
+    let mut result = Vec::with_capacity(rooms.len());
+    for room in rooms {
+        let hours = hours.remove(&room.id).unwrap_or_default();
+        let rules = capacity_rules.remove(&room.id).unwrap_or_default();
+        let current_max_occupancy = capacity::limit_at(&room, &rules, now);
+        let holidays = all_holidays
+            .iter()
+            .filter(|h| h.room.iter().all(|r| *r == room.id))
+            .cloned()
+            .collect();
+        result.push(RoomDescription {
+            checkin_token_required: room.checkin_token.is_some(),
+            building: room
+                .floor_id
+                .and_then(|f| building_of_floor.get(&f).cloned()),
+            current_max_occupancy,
+            capacity_rules: rules
+                .into_iter()
+                .filter(|r| r.valid_to.iter().all(|to| *to >= today))
+                .map(|r| r.into())
+                .collect(),
+            opening_hours: hours.into_iter().map(|h| h.into()).collect(),
+            closures: closures.remove(&room.id).unwrap_or_default(),
+            holidays,
+            room,
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+/// Group the items by the ID of their room, keeping their order.
+fn group_by_room<T>(items: Vec<T>, room: impl Fn(&T) -> &String) -> BTreeMap<String, Vec<T>> {
+    let mut result: BTreeMap<String, Vec<T>> = BTreeMap::new();
+    for item in items {
+        result.entry(room(&item).clone()).or_default().push(item);
+    }
+    result
+}
+
+/// Join attributes of rooms and seats to the comma separated list stored in the database.
+fn join_attributes(attributes: &[String]) -> Result<String, ServiceError> {
+    let mut result = Vec::with_capacity(attributes.len());
+    for a in attributes {
+        let a = a.trim();
+        if a.is_empty() || a.contains(',') {
+            return Err(ServiceError::BadRequest(format!(
+                "Invalid attribute \"{}\", attributes must not be empty or contain commas.",
+                a
+            )));
+        }
+        result.push(a);
+    }
+    Ok(result.join(","))
+}
+
+#[derive(Deserialize)]
+pub struct AvailabilitySearchParams {
+    pub start: String,
+    pub end: String,
+    /// Comma separated list of attributes the room must have
+    pub attributes: Option<String>,
+    /// Number of people that need to fit into the room, one if not given
+    pub people: Option<usize>,
 }
 
-fn check_if_room_available<Conn, Tz>(
+/// Get all rooms that have the required attributes (ignoring case) and can take the given number
+/// of people during the whole time range.
+///
+/// The time range is rounded to the booking slots of each room. Rooms that are closed during the
+/// time range are not included.
+pub fn find_available_rooms<Conn>(
     conn: &Conn,
-    room: &Room,
-    start: DateTime<Tz>,
-    end: DateTime<Tz>,
-    ignore_id: Option<i32>,
-) -> Result<bool, ServiceError>
+    settings: &Settings,
+    start: DateTime<chrono::FixedOffset>,
+    end: DateTime<chrono::FixedOffset>,
+    required_attributes: &[String],
+    persons: usize,
+) -> Result<Vec<Room>, ServiceError>
 where
     Conn: Connection<Backend = diesel::sqlite::Sqlite>,
-    Tz: TimeZone + std::fmt::Display,
-    Tz::Offset: std::fmt::Display,
 {
-    use crate::schema::occupancies::dsl;
+    use crate::schema::rooms::dsl;
 
-    // Do some general validity checks
+    let required_attributes: Vec<String> = required_attributes
+        .iter()
+        .map(|a| a.to_lowercase())
+        .collect();
+    let rooms: Vec<Room> = dsl::rooms.order(dsl::id).load(conn)?;
+    let mut result = Vec::new();
+    for room in rooms {
+        let attributes: Vec<String> = room
+            .attribute_list()
+            .iter()
+            .map(|a| a.to_lowercase())
+            .collect();
+        if !required_attributes.iter().all(|a| attributes.contains(a)) {
+            continue;
+        }
+
+        let slot = room.slot_duration(settings);
+        let tz = room.parsed_timezone();
+        let start = start.duration_round(slot)?.with_timezone(&tz);
+        let end = end.duration_round(slot)?.with_timezone(&tz);
+        match check_if_room_available_for(conn, &room, start, end, None, persons) {
+            Ok(true) => result.push(room),
+            // The room is full or closed during the time range
+            Ok(false) | Err(ServiceError::BadRequest(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(result)
+}
+
+pub async fn search_availability(
+    params: web::Query<AvailabilitySearchParams>,
+    db_pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    _claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let start = DateTime::parse_from_rfc3339(&params.start)?;
+    let end = DateTime::parse_from_rfc3339(&params.end)?;
     if end <= start {
         return Err(ServiceError::BadRequest(format!(
             "Begin of time range ({}) is after end of range ({}).",
-            &start.to_rfc3339(),
-            &end.to_rfc3339(),
+            &params.start, &params.end,
         )));
     }
-
-    if start.date() != end.date() {
-        return Err(ServiceError::BadRequest(format!(
-            "Start ({}) and end ({}) of time range must be on the same day.",
-            &start.to_rfc3339(),
-            &end.to_rfc3339(),
-        )));
+    let people = params.people.unwrap_or(1);
+    if people < 1 {
+        return Err(ServiceError::BadRequest(
+            "The number of people must be at least 1.".to_string(),
+        ));
     }
+    let attributes: Vec<String> = params
+        .attributes
+        .as_deref()
+        .map(split_attributes)
+        .unwrap_or_default()
+        .iter()
+        .map(|a| a.trim().to_string())
+        .filter(|a| !a.is_empty())
+        .collect();
 
-    // Check the number of persons per partially overlapped full hour
-    let mut t = start.clone();
-    while t <= end {
-        let t_next = t.clone() + Duration::hours(1);
-        let basic_overlap_query = dsl::occupancies
-            .filter(dsl::room.eq(&room.id))
-            .filter(dsl::start.lt(t_next.naive_utc()))
-            .filter(dsl::end.gt(t.naive_utc()));
+    let conn = db_pool.get()?;
+    let rooms = find_available_rooms(&conn, &settings, start, end, &attributes, people)?;
+    Ok(HttpResponse::Ok().json(rooms))
+}
 
-        let overlapping_existing: Vec<_> = if let Some(ignore_id) = ignore_id {
-            basic_overlap_query
-                .filter(dsl::id.ne(ignore_id))
-                .load::<Occupancy>(conn)?
-        } else {
-            basic_overlap_query.load::<Occupancy>(conn)?
+/// Deserialize a property that can be omitted (outer `None`) or explicitly set to `null`
+/// (inner `None`).
+fn deserialize_nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: serde::Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Ok(Some(serde::Deserialize::deserialize(deserializer)?))
+}
+
+/// Properties of a room.
+///
+/// When a room is updated, the optional properties keep their existing value if they are not
+/// given and are removed if they are `null`.
+#[derive(Deserialize)]
+pub struct RoomProperties {
+    pub max_occupancy: i32,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub timezone: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub slot_minutes: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub max_hours_per_week: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub max_future_bookings: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub max_days_ahead: Option<Option<i32>>,
+    /// If empty, the room is open at any time.
+    pub opening_hours: Option<Vec<OpeningHoursProperties>>,
+    /// ID of the floor the room is on
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub floor: Option<Option<i32>>,
+    /// Free text attributes of the room, e.g. "projector" or "wheelchair accessible"
+    pub attributes: Option<Vec<String>>,
+}
+
+impl RoomProperties {
+    fn validate(&self) -> Result<(), ServiceError> {
+        if self.max_occupancy < 1 {
+            return Err(ServiceError::BadRequest(format!(
+                "Maximum occupancy must be at least 1 but was {}.",
+                self.max_occupancy
+            )));
+        }
+        if let Some(Some(tz)) = &self.timezone {
+            tz.parse::<chrono_tz::Tz>().map_err(|_| {
+                ServiceError::BadRequest(format!("Unknown timezone \"{}\".", tz))
+            })?;
+        }
+        if let Some(Some(slot_minutes)) = self.slot_minutes {
+            if slot_minutes < 1 || (24 * 60) % slot_minutes != 0 {
+                return Err(ServiceError::BadRequest(format!(
+                    "Slot length of {} minutes is not a divisor of the minutes of a day.",
+                    slot_minutes
+                )));
+            }
+        }
+        let limits = [
+            ("Maximum hours per week", self.max_hours_per_week.flatten()),
+            (
+                "Maximum number of future bookings",
+                self.max_future_bookings.flatten(),
+            ),
+            (
+                "Maximum number of days in advance",
+                self.max_days_ahead.flatten(),
+            ),
+        ];
+        for (name, limit) in &limits {
+            if let Some(limit) = limit {
+                if *limit < 0 {
+                    return Err(ServiceError::BadRequest(format!(
+                        "{} must not be negative but was {}.",
+                        name, limit
+                    )));
+                }
+            }
+        }
+        for hours in self.opening_hours.iter().flatten() {
+            hours.parse("")?;
+        }
+        join_attributes(self.attributes.as_deref().unwrap_or_default())?;
+        Ok(())
+    }
+
+    /// Get the changes of an existing room, omitted properties are not changed.
+    fn changes(&self) -> Result<RoomChanges, ServiceError> {
+        let attributes = match &self.attributes {
+            Some(attributes) => Some(join_attributes(attributes)?),
+            None => None,
         };
+        Ok(RoomChanges {
+            max_occupancy: self.max_occupancy,
+            timezone: self.timezone.clone(),
+            slot_minutes: self.slot_minutes,
+            max_hours_per_week: self.max_hours_per_week,
+            max_future_bookings: self.max_future_bookings,
+            max_days_ahead: self.max_days_ahead,
+            floor_id: self.floor,
+            attributes,
+        })
+    }
+}
 
-        if overlapping_existing.len() as i32 >= room.max_occupancy {
-            return Ok(false);
+/// Check that the floor a room is assigned to exists.
+fn check_floor_exists<Conn>(conn: &Conn, floor: Option<i32>) -> Result<(), ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::floors::dsl;
+
+    if let Some(floor) = floor {
+        let existing: i64 = dsl::floors
+            .filter(dsl::id.eq(floor))
+            .count()
+            .get_result(conn)?;
+        if existing == 0 {
+            return Err(ServiceError::BadRequest(format!(
+                "Floor with ID {} does not exist.",
+                floor
+            )));
         }
-        t = t_next;
     }
-    Ok(true)
+    Ok(())
 }
 
-pub async fn add_occupancy(
-    event: web::Json<TimeRange>,
+/// Replace the opening hours of the room with the given ones.
+fn replace_opening_hours<Conn>(
+    conn: &Conn,
+    room: &str,
+    hours: &[OpeningHoursProperties],
+) -> Result<(), ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::opening_hours::dsl;
+
+    diesel::delete(dsl::opening_hours.filter(dsl::room.eq(room))).execute(conn)?;
+    for h in hours {
+        diesel::insert_into(crate::schema::opening_hours::table)
+            .values(h.parse(room)?)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+pub async fn add_room(
     room: web::Path<String>,
+    properties: web::Json<RoomProperties>,
     db_pool: web::Data<DbPool>,
-    claims: ClaimsFromAuth,
+    _claims: AdminFromAuth,
 ) -> Result<HttpResponse, ServiceError> {
-    // Parse the dates and round them to the full hour
-    let start = DateTime::parse_from_rfc3339(&event.start)?.duration_round(Duration::hours(1))?;
-    let end = DateTime::parse_from_rfc3339(&event.end)?.duration_round(Duration::hours(1))?;
+    properties.validate()?;
 
     let conn = db_pool.get()?;
     let result = conn.transaction::<_, ServiceError, _>(|| {
-        // Get the general room capacity
-        use crate::schema::rooms;
-        let room: Option<Room> = rooms::dsl::rooms
-            .filter(rooms::dsl::id.eq(room.as_ref()))
-            .order(rooms::dsl::id)
-            .load(&conn)?
-            .into_iter()
-            .next();
-        if let Some(room) = room {
-            if check_if_room_available(&conn, &room, start.clone(), end.clone(), None)? {
-                // Check was successful, add the new event
-                let new_item = NewOccupancy {
-                    room: room.id,
-                    user_id: claims.0.sub.to_string(),
-                    user_name: claims.0.name.to_string(),
-                    user_contact: claims.0.contact_info.to_string(),
-                    start: start.naive_utc(),
-                    end: end.naive_utc(),
-                };
-                diesel::insert_into(crate::schema::occupancies::table)
-                    .values(new_item.clone())
-                    .execute(&conn)?;
+        use crate::schema::rooms::dsl;
 
-                Ok(HttpResponse::Ok().json(new_item))
-            } else {
-                return Ok(HttpResponse::Conflict().json("Room already full"));
-            }
-        } else {
-            Ok(HttpResponse::NotFound().json("Room not found"))
+        let existing: i64 = dsl::rooms
+            .filter(dsl::id.eq(room.as_str()))
+            .count()
+            .get_result(&conn)?;
+        if existing > 0 {
+            return Ok(HttpResponse::Conflict().json("Room already exists"));
+        }
+        let floor = properties.floor.flatten();
+        check_floor_exists(&conn, floor)?;
+
+        let new_room = Room {
+            id: room.to_string(),
+            max_occupancy: properties.max_occupancy,
+            timezone: properties.timezone.clone().flatten(),
+            slot_minutes: properties.slot_minutes.flatten(),
+            max_hours_per_week: properties.max_hours_per_week.flatten(),
+            max_future_bookings: properties.max_future_bookings.flatten(),
+            max_days_ahead: properties.max_days_ahead.flatten(),
+            checkin_token: None,
+            floor_id: floor,
+            attributes: join_attributes(properties.attributes.as_deref().unwrap_or_default())?,
+        };
+        diesel::insert_into(crate::schema::rooms::table)
+            .values(&new_room)
+            .execute(&conn)?;
+        if let Some(hours) = &properties.opening_hours {
+            replace_opening_hours(&conn, &new_room.id, hours)?;
         }
+
+        Ok(HttpResponse::Ok().json(new_room))
     })?;
     Ok(result)
 }
 
-pub async fn update_occupancy(
-    path: web::Path<(String, i32)>,
-    event: web::Json<TimeRange>,
+pub async fn update_room(
+    room: web::Path<String>,
+    properties: web::Json<RoomProperties>,
     db_pool: web::Data<DbPool>,
-    claims: ClaimsFromAuth,
+    _claims: AdminFromAuth,
 ) -> Result<HttpResponse, ServiceError> {
+    properties.validate()?;
+
     let conn = db_pool.get()?;
     let result = conn.transaction::<_, ServiceError, _>(|| {
-        use crate::schema::occupancies;
-        use crate::schema::rooms;
+        use crate::schema::rooms::dsl;
+        check_floor_exists(&conn, properties.floor.flatten())?;
+        let updated = diesel::update(dsl::rooms.filter(dsl::id.eq(room.as_str())))
+            .set(&properties.changes()?)
+            .execute(&conn)?;
 
-        // Parse the dates and round them to the full hour
-        let start =
-            DateTime::parse_from_rfc3339(&event.start)?.duration_round(Duration::hours(1))?;
-        let end = DateTime::parse_from_rfc3339(&event.end)?.duration_round(Duration::hours(1))?;
-
-        let room: Option<Room> = rooms::dsl::rooms
-            .filter(rooms::dsl::id.eq(path.0.as_str()))
-            .load(&conn)?
-            .into_iter()
-            .next();
-        if let Some(room) = room {
-            // Check if this event would lead to an invalid state
-            if check_if_room_available(&conn, &room, start, end, Some(path.1))? {
-                // Only update if this event is from the same user
-                diesel::update(occupancies::dsl::occupancies)
-                    .set((
-                        occupancies::dsl::start.eq(start.naive_utc()),
-                        occupancies::dsl::end.eq(end.naive_utc()),
-                    ))
-                    .filter(occupancies::dsl::id.eq(path.1))
-                    .filter(occupancies::dsl::room.eq(path.0.as_str()))
-                    .filter(occupancies::dsl::user_id.eq(claims.0.sub))
-                    .execute(&conn)?;
-                Ok(HttpResponse::Ok().finish())
-            } else {
-                Ok(HttpResponse::Conflict().json("Room already full"))
+        if updated > 0 {
+            if let Some(hours) = &properties.opening_hours {
+                replace_opening_hours(&conn, room.as_str(), hours)?;
             }
+            Ok(HttpResponse::Ok().finish())
         } else {
             Ok(HttpResponse::NotFound().json("Room not found"))
         }
@@ -309,108 +757,4504 @@ pub async fn update_occupancy(
     Ok(result)
 }
 
-pub async fn delete_occupancy(
-    path: web::Path<(String, i32)>,
+pub async fn delete_room(
+    room: web::Path<String>,
     db_pool: web::Data<DbPool>,
-    claims: ClaimsFromAuth,
+    _claims: AdminFromAuth,
 ) -> Result<HttpResponse, ServiceError> {
     let conn = db_pool.get()?;
     let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::capacity_rules;
+        use crate::schema::closures;
+        use crate::schema::holidays;
         use crate::schema::occupancies;
+        use crate::schema::opening_hours;
+        use crate::schema::rooms;
+        use crate::schema::seats;
+        use crate::schema::series;
+        use crate::schema::waitlist;
+
+        let existing: i64 = rooms::dsl::rooms
+            .filter(rooms::dsl::id.eq(room.as_str()))
+            .count()
+            .get_result(&conn)?;
+        if existing == 0 {
+            return Ok(HttpResponse::NotFound().json("Room not found"));
+        }
+
+        // Do not remove rooms that still have bookings which have not ended yet
+        let now = chrono::Utc::now().naive_utc();
+        let future_occupancies: i64 = occupancies::dsl::occupancies
+            .filter(occupancies::dsl::room.eq(room.as_str()))
+            .filter(occupancies::dsl::end.gt(now))
+            .count()
+            .get_result(&conn)?;
+        if future_occupancies > 0 {
+            return Ok(HttpResponse::Conflict().json(format!(
+                "Room still has {} occupancies in the future",
+                future_occupancies
+            )));
+        }
+
+        // Past occupancies are needed for contact tracing until the data retention purged them
+        let unpurged_occupancies: i64 = occupancies::dsl::occupancies
+            .filter(occupancies::dsl::room.eq(room.as_str()))
+            .filter(occupancies::dsl::user_id.ne(retention::ANONYMOUS))
+            .count()
+            .get_result(&conn)?;
+        if unpurged_occupancies > 0 {
+            return Ok(HttpResponse::Conflict().json(format!(
+                "Room still has {} occupancies that have not been purged yet",
+                unpurged_occupancies
+            )));
+        }
 
-        // Only delete if this event is from the same user
+        // Anonymized occupancies reference the room and are removed together with it
         diesel::delete(
-            occupancies::dsl::occupancies
-                .filter(occupancies::dsl::id.eq(path.1))
-                .filter(occupancies::dsl::room.eq(path.0.as_str()))
-                .filter(occupancies::dsl::user_id.eq(claims.0.sub)),
+            occupancies::dsl::occupancies.filter(occupancies::dsl::room.eq(room.as_str())),
         )
         .execute(&conn)?;
+        diesel::delete(series::dsl::series.filter(series::dsl::room.eq(room.as_str())))
+            .execute(&conn)?;
+        diesel::delete(holidays::dsl::holidays.filter(holidays::dsl::room.eq(room.as_str())))
+            .execute(&conn)?;
+        diesel::delete(
+            opening_hours::dsl::opening_hours.filter(opening_hours::dsl::room.eq(room.as_str())),
+        )
+        .execute(&conn)?;
+        diesel::delete(closures::dsl::closures.filter(closures::dsl::room.eq(room.as_str())))
+            .execute(&conn)?;
+        diesel::delete(waitlist::dsl::waitlist.filter(waitlist::dsl::room.eq(room.as_str())))
+            .execute(&conn)?;
+        diesel::delete(seats::dsl::seats.filter(seats::dsl::room.eq(room.as_str())))
+            .execute(&conn)?;
+        diesel::delete(
+            capacity_rules::dsl::capacity_rules.filter(capacity_rules::dsl::room.eq(room.as_str())),
+        )
+        .execute(&conn)?;
+        diesel::delete(rooms::dsl::rooms.filter(rooms::dsl::id.eq(room.as_str())))
+            .execute(&conn)?;
 
         Ok(HttpResponse::Ok().finish())
     })?;
     Ok(result)
 }
 
-#[derive(Deserialize, Clone)]
-pub struct RoomOccupancyParams {
-    pub start: Option<String>,
-    pub end: Option<String>,
+#[derive(Deserialize)]
+pub struct ClosureProperties {
+    pub reason: Option<String>,
 }
 
-pub async fn get_occupancies(
-    params: web::Query<RoomOccupancyParams>,
-    room: web::Path<String>,
+pub async fn add_closure(
+    path: web::Path<(String, String)>,
+    properties: web::Json<ClosureProperties>,
     db_pool: web::Data<DbPool>,
-    claims: ClaimsFromAuth,
+    _claims: RoomManagerFromAuth,
 ) -> Result<HttpResponse, ServiceError> {
-    // Parse the dates and round them to the full hour
-    let start = if let Some(start) = &params.start {
-        Some(DateTime::parse_from_rfc3339(start)?.duration_round(Duration::hours(1))?)
-    } else {
-        None
-    };
-    let end = if let Some(end) = &params.end {
-        Some(DateTime::parse_from_rfc3339(end)?.duration_round(Duration::hours(1))?)
+    let day = NaiveDate::parse_from_str(&path.1, "%Y-%m-%d")?;
+
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::closures::dsl;
+        use crate::schema::rooms;
+
+        let existing: i64 = rooms::dsl::rooms
+            .filter(rooms::dsl::id.eq(path.0.as_str()))
+            .count()
+            .get_result(&conn)?;
+        if existing == 0 {
+            return Ok(HttpResponse::NotFound().json("Room not found"));
+        }
+
+        // Replace any existing closure of the room on this day
+        diesel::delete(
+            dsl::closures
+                .filter(dsl::room.eq(path.0.as_str()))
+                .filter(dsl::day.eq(day)),
+        )
+        .execute(&conn)?;
+        diesel::insert_into(crate::schema::closures::table)
+            .values(NewClosure {
+                room: path.0.clone(),
+                day,
+                reason: properties.reason.clone(),
+            })
+            .execute(&conn)?;
+
+        Ok(HttpResponse::Ok().finish())
+    })?;
+    Ok(result)
+}
+
+pub async fn delete_closure(
+    path: web::Path<(String, String)>,
+    db_pool: web::Data<DbPool>,
+    _claims: RoomManagerFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let day = NaiveDate::parse_from_str(&path.1, "%Y-%m-%d")?;
+
+    let conn = db_pool.get()?;
+    use crate::schema::closures::dsl;
+    let deleted = diesel::delete(
+        dsl::closures
+            .filter(dsl::room.eq(path.0.as_str()))
+            .filter(dsl::day.eq(day)),
+    )
+    .execute(&conn)?;
+
+    if deleted > 0 {
+        Ok(HttpResponse::Ok().finish())
     } else {
-        None
-    };
+        Ok(HttpResponse::NotFound().json("Closure not found"))
+    }
+}
 
-    if let (Some(start), Some(end)) = (start, end) {
-        if end <= start {
-            return Ok(HttpResponse::Forbidden().json(format!(
-                "Begin of time range ({}) ist after end of range ({}).",
-                start, end
-            )));
+/// Label and attributes of a seat in a room.
+#[derive(Deserialize)]
+pub struct SeatProperties {
+    pub label: String,
+    /// Free text attributes of the seat, e.g. "docking station"
+    #[serde(default)]
+    pub attributes: Vec<String>,
+}
+
+impl SeatProperties {
+    fn parse(&self, room: &str) -> Result<NewSeat, ServiceError> {
+        let label = self.label.trim();
+        if label.is_empty() {
+            return Err(ServiceError::BadRequest(
+                "The label of a seat must not be empty.".to_string(),
+            ));
         }
+        Ok(NewSeat {
+            room: room.to_string(),
+            label: label.to_string(),
+            attributes: join_attributes(&self.attributes)?,
+        })
     }
+}
 
-    let conn = db_pool.get()?;
+#[derive(Serialize)]
+pub struct SeatDescription {
+    pub id: i32,
+    pub label: String,
+    pub attributes: Vec<String>,
+}
 
-    // Get the general room capacity
-    use crate::schema::rooms;
-    let room: Option<Room> = rooms::dsl::rooms
-        .filter(rooms::dsl::id.eq(room.as_ref()))
-        .load(&conn)?
-        .into_iter()
-        .next();
+impl From<Seat> for SeatDescription {
+    fn from(seat: Seat) -> Self {
+        SeatDescription {
+            attributes: seat.attribute_list(),
+            id: seat.id,
+            label: seat.label,
+        }
+    }
+}
+
+/// A seat together with the information if it can be booked in the requested time range.
+#[derive(Serialize)]
+pub struct SeatAvailability {
+    #[serde(flatten)]
+    pub seat: SeatDescription,
+    /// False if the seat or the whole room is already booked at some time of the time range
+    pub available: bool,
+}
+
+pub async fn get_seats(
+    params: web::Query<TimeRange>,
+    room: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+    _claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let start = DateTime::parse_from_rfc3339(&params.start)?;
+    let end = DateTime::parse_from_rfc3339(&params.end)?;
+    if end <= start {
+        return Err(ServiceError::BadRequest(format!(
+            "Begin of time range ({}) is after end of range ({}).",
+            &params.start, &params.end,
+        )));
+    }
+
+    let conn = db_pool.get()?;
+    use crate::schema::rooms;
+    let room: Option<Room> = rooms::dsl::rooms
+        .filter(rooms::dsl::id.eq(room.as_str()))
+        .first(&conn)
+        .optional()?;
+    let room = if let Some(room) = room {
+        room
+    } else {
+        return Ok(HttpResponse::NotFound().json("Room not found"));
+    };
+
+    let room_available = match check_if_room_available(&conn, &room, start, end, None) {
+        Ok(available) => available,
+        // Seats can't be booked when the room is closed
+        Err(ServiceError::BadRequest(_)) => false,
+        Err(e) => return Err(e),
+    };
+    let booked =
+        seats::find_booked_seats(&conn, &room.id, start.naive_utc(), end.naive_utc(), None)?;
+    let result: Vec<SeatAvailability> = seats::find_seats(&conn, &room.id)?
+        .into_iter()
+        .map(|seat| SeatAvailability {
+            available: room_available && !booked.contains(&seat.id),
+            seat: seat.into(),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn add_seat(
+    room: web::Path<String>,
+    properties: web::Json<SeatProperties>,
+    db_pool: web::Data<DbPool>,
+    _claims: RoomManagerFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let new_seat = properties.parse(room.as_str())?;
+
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::rooms;
+        use crate::schema::seats::dsl;
+
+        let existing: i64 = rooms::dsl::rooms
+            .filter(rooms::dsl::id.eq(room.as_str()))
+            .count()
+            .get_result(&conn)?;
+        if existing == 0 {
+            return Ok(HttpResponse::NotFound().json("Room not found"));
+        }
+
+        diesel::insert_into(crate::schema::seats::table)
+            .values(&new_seat)
+            .execute(&conn)?;
+        let new_id: i32 = diesel::select(last_insert_rowid).get_result(&conn)?;
+        let seat: Seat = dsl::seats.find(new_id).first(&conn)?;
+        Ok(HttpResponse::Ok().json(SeatDescription::from(seat)))
+    })?;
+    Ok(result)
+}
+
+pub async fn update_seat(
+    path: web::Path<(String, i32)>,
+    properties: web::Json<SeatProperties>,
+    db_pool: web::Data<DbPool>,
+    _claims: RoomManagerFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let new_seat = properties.parse(&path.0)?;
+
+    let conn = db_pool.get()?;
+    use crate::schema::seats::dsl;
+    let updated = diesel::update(
+        dsl::seats
+            .filter(dsl::id.eq(path.1))
+            .filter(dsl::room.eq(path.0.as_str())),
+    )
+    .set((
+        dsl::label.eq(&new_seat.label),
+        dsl::attributes.eq(&new_seat.attributes),
+    ))
+    .execute(&conn)?;
+
+    if updated > 0 {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().json("Seat not found"))
+    }
+}
+
+pub async fn delete_seat(
+    path: web::Path<(String, i32)>,
+    db_pool: web::Data<DbPool>,
+    _claims: RoomManagerFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::occupancies;
+        use crate::schema::seats::dsl;
+
+        let existing: i64 = dsl::seats
+            .filter(dsl::id.eq(path.1))
+            .filter(dsl::room.eq(path.0.as_str()))
+            .count()
+            .get_result(&conn)?;
+        if existing == 0 {
+            return Ok(HttpResponse::NotFound().json("Seat not found"));
+        }
+
+        // Do not remove seats that are still booked in the future
+        let now = chrono::Utc::now().naive_utc();
+        let future_occupancies: i64 = occupancies::dsl::occupancies
+            .filter(occupancies::dsl::seat_id.eq(path.1))
+            .filter(occupancies::dsl::end.gt(now))
+            .count()
+            .get_result(&conn)?;
+        if future_occupancies > 0 {
+            return Ok(HttpResponse::Conflict().json(format!(
+                "Seat still has {} occupancies in the future",
+                future_occupancies
+            )));
+        }
+
+        // Past occupancies are kept, but do not reference the seat anymore
+        diesel::update(occupancies::dsl::occupancies.filter(occupancies::dsl::seat_id.eq(path.1)))
+            .set(occupancies::dsl::seat_id.eq(None::<i32>))
+            .execute(&conn)?;
+        diesel::delete(dsl::seats.find(path.1)).execute(&conn)?;
+
+        Ok(HttpResponse::Ok().finish())
+    })?;
+    Ok(result)
+}
+
+/// A maximum occupancy of a room that replaces the general one during the validity period.
+///
+/// Dates and times are given in the timezone of the room.
+#[derive(Serialize, Deserialize)]
+pub struct CapacityRuleProperties {
+    pub max_occupancy: i32,
+    /// First day the rule is valid, valid since ever if not given
+    pub valid_from: Option<NaiveDate>,
+    /// Last day the rule is valid, valid forever if not given
+    pub valid_to: Option<NaiveDate>,
+    /// Weekdays as two-letter codes, e.g. "MO", valid on all weekdays if empty
+    #[serde(default)]
+    pub weekdays: Vec<String>,
+    /// Local time in the format "HH:MM", valid the whole day if not given
+    pub from_time: Option<String>,
+    /// Local time in the format "HH:MM" (exclusive) or "24:00", valid the whole day if not given
+    pub to_time: Option<String>,
+}
+
+impl CapacityRuleProperties {
+    fn parse(&self, room: &str) -> Result<NewCapacityRule, ServiceError> {
+        if self.max_occupancy < 1 {
+            return Err(ServiceError::BadRequest(format!(
+                "Maximum occupancy of a capacity rule must be at least 1, but was {}.",
+                self.max_occupancy
+            )));
+        }
+        if let (Some(from), Some(to)) = (self.valid_from, self.valid_to) {
+            if to < from {
+                return Err(ServiceError::BadRequest(format!(
+                    "Capacity rule ends ({}) before it starts ({}).",
+                    to, from
+                )));
+            }
+        }
+        let mut weekdays = Vec::with_capacity(self.weekdays.len());
+        for day in &self.weekdays {
+            let code = weekday_code(parse_weekday(day)?);
+            if !weekdays.contains(&code) {
+                weekdays.push(code);
+            }
+        }
+        let (from_time, to_time) = match (&self.from_time, &self.to_time) {
+            (Some(from), Some(to)) => {
+                let (from, to) = (parse_time(from)?, parse_end_time(to)?);
+                if to != NaiveTime::from_hms(0, 0, 0) && to <= from {
+                    return Err(ServiceError::BadRequest(format!(
+                        "Time window of capacity rule ends ({}) before it starts ({}).",
+                        to.format("%H:%M"),
+                        from.format("%H:%M")
+                    )));
+                }
+                (Some(from), Some(to))
+            }
+            (None, None) => (None, None),
+            _ => {
+                return Err(ServiceError::BadRequest(
+                    "Either both or none of from_time and to_time must be given.".to_string(),
+                ))
+            }
+        };
+        Ok(NewCapacityRule {
+            room: room.to_string(),
+            max_occupancy: self.max_occupancy,
+            valid_from: self.valid_from,
+            valid_to: self.valid_to,
+            weekdays: weekdays.join(","),
+            from_time,
+            to_time,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct CapacityRuleDescription {
+    pub id: i32,
+    #[serde(flatten)]
+    pub rule: CapacityRuleProperties,
+}
+
+impl From<CapacityRule> for CapacityRuleDescription {
+    fn from(rule: CapacityRule) -> Self {
+        CapacityRuleDescription {
+            id: rule.id,
+            rule: CapacityRuleProperties {
+                max_occupancy: rule.max_occupancy,
+                valid_from: rule.valid_from,
+                valid_to: rule.valid_to,
+                weekdays: split_attributes(&rule.weekdays),
+                from_time: rule.from_time.map(|t| t.format("%H:%M").to_string()),
+                to_time: rule.to_time.map(format_end_time),
+            },
+        }
+    }
+}
+
+pub async fn get_capacity_rules(
+    room: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+    _claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    use crate::schema::rooms;
+    let existing: i64 = rooms::dsl::rooms
+        .filter(rooms::dsl::id.eq(room.as_str()))
+        .count()
+        .get_result(&conn)?;
+    if existing == 0 {
+        return Ok(HttpResponse::NotFound().json("Room not found"));
+    }
+
+    let result: Vec<CapacityRuleDescription> = capacity::find_rules(&conn, room.as_str())?
+        .into_iter()
+        .map(|r| r.into())
+        .collect();
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn add_capacity_rule(
+    room: web::Path<String>,
+    properties: web::Json<CapacityRuleProperties>,
+    db_pool: web::Data<DbPool>,
+    _claims: RoomManagerFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let new_rule = properties.parse(room.as_str())?;
+
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::capacity_rules::dsl;
+        use crate::schema::rooms;
+
+        let existing: i64 = rooms::dsl::rooms
+            .filter(rooms::dsl::id.eq(room.as_str()))
+            .count()
+            .get_result(&conn)?;
+        if existing == 0 {
+            return Ok(HttpResponse::NotFound().json("Room not found"));
+        }
+
+        diesel::insert_into(crate::schema::capacity_rules::table)
+            .values(&new_rule)
+            .execute(&conn)?;
+        let new_id: i32 = diesel::select(last_insert_rowid).get_result(&conn)?;
+        let rule: CapacityRule = dsl::capacity_rules.find(new_id).first(&conn)?;
+        Ok(HttpResponse::Ok().json(CapacityRuleDescription::from(rule)))
+    })?;
+    Ok(result)
+}
+
+pub async fn delete_capacity_rule(
+    path: web::Path<(String, i32)>,
+    db_pool: web::Data<DbPool>,
+    _claims: RoomManagerFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    use crate::schema::capacity_rules::dsl;
+    let deleted = diesel::delete(
+        dsl::capacity_rules
+            .filter(dsl::id.eq(path.1))
+            .filter(dsl::room.eq(path.0.as_str())),
+    )
+    .execute(&conn)?;
+
+    if deleted > 0 {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().json("Capacity rule not found"))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BuildingProperties {
+    pub name: String,
+    /// Maximum number of people in all rooms of the building at the same time
+    pub max_occupancy: Option<i32>,
+}
+
+impl BuildingProperties {
+    fn validate(&self) -> Result<(), ServiceError> {
+        if let Some(max_occupancy) = self.max_occupancy {
+            if max_occupancy < 1 {
+                return Err(ServiceError::BadRequest(format!(
+                    "Maximum occupancy must be at least 1 but was {}.",
+                    max_occupancy
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FloorProperties {
+    pub name: String,
+    /// Used to order the floors of a building, e.g. 0 for the ground floor
+    pub level: i32,
+}
+
+/// A floor together with the rooms on it.
+#[derive(Serialize)]
+pub struct FloorDescription {
+    #[serde(flatten)]
+    pub floor: Floor,
+    /// IDs of the rooms on this floor
+    pub rooms: Vec<String>,
+}
+
+/// A building together with its floors and their rooms.
+#[derive(Serialize)]
+pub struct BuildingDescription {
+    #[serde(flatten)]
+    pub building: Building,
+    /// Floors ordered by their level
+    pub floors: Vec<FloorDescription>,
+}
+
+fn describe_building<Conn>(conn: &Conn, building: Building) -> QueryResult<BuildingDescription>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::floors;
+    use crate::schema::rooms;
+
+    let floors: Vec<Floor> = floors::dsl::floors
+        .filter(floors::dsl::building.eq(&building.id))
+        .order((floors::dsl::level, floors::dsl::id))
+        .load(conn)?;
+    let mut result = Vec::with_capacity(floors.len());
+    for floor in floors {
+        let rooms: Vec<String> = rooms::dsl::rooms
+            .select(rooms::dsl::id)
+            .filter(rooms::dsl::floor_id.eq(floor.id))
+            .order(rooms::dsl::id)
+            .load(conn)?;
+        result.push(FloorDescription { floor, rooms });
+    }
+    Ok(BuildingDescription {
+        building,
+        floors: result,
+    })
+}
+
+pub async fn all_buildings(
+    db_pool: web::Data<DbPool>,
+    _claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    use crate::schema::buildings::dsl;
+
+    let buildings: Vec<Building> = dsl::buildings.order(dsl::id).load(&conn)?;
+    let mut result = Vec::with_capacity(buildings.len());
+    for building in buildings {
+        result.push(describe_building(&conn, building)?);
+    }
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn get_building(
+    building: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+    _claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    use crate::schema::buildings::dsl;
+
+    let building: Option<Building> = dsl::buildings
+        .find(building.as_str())
+        .first(&conn)
+        .optional()?;
+    if let Some(building) = building {
+        Ok(HttpResponse::Ok().json(describe_building(&conn, building)?))
+    } else {
+        Ok(HttpResponse::NotFound().json("Building not found"))
+    }
+}
+
+pub async fn add_building(
+    building: web::Path<String>,
+    properties: web::Json<BuildingProperties>,
+    db_pool: web::Data<DbPool>,
+    _claims: AdminFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    properties.validate()?;
+
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::buildings::dsl;
+
+        let existing: i64 = dsl::buildings
+            .filter(dsl::id.eq(building.as_str()))
+            .count()
+            .get_result(&conn)?;
+        if existing > 0 {
+            return Ok(HttpResponse::Conflict().json("Building already exists"));
+        }
+
+        let new_building = Building {
+            id: building.to_string(),
+            name: properties.name.clone(),
+            max_occupancy: properties.max_occupancy,
+        };
+        diesel::insert_into(crate::schema::buildings::table)
+            .values(&new_building)
+            .execute(&conn)?;
+        Ok(HttpResponse::Ok().json(new_building))
+    })?;
+    Ok(result)
+}
+
+pub async fn update_building(
+    building: web::Path<String>,
+    properties: web::Json<BuildingProperties>,
+    db_pool: web::Data<DbPool>,
+    _claims: AdminFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    properties.validate()?;
+
+    let conn = db_pool.get()?;
+    use crate::schema::buildings::dsl;
+    let updated = diesel::update(dsl::buildings.filter(dsl::id.eq(building.as_str())))
+        .set((
+            dsl::name.eq(&properties.name),
+            dsl::max_occupancy.eq(properties.max_occupancy),
+        ))
+        .execute(&conn)?;
+
+    if updated > 0 {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().json("Building not found"))
+    }
+}
+
+pub async fn delete_building(
+    building: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+    _claims: AdminFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::buildings;
+        use crate::schema::floors;
+
+        let existing: i64 = buildings::dsl::buildings
+            .filter(buildings::dsl::id.eq(building.as_str()))
+            .count()
+            .get_result(&conn)?;
+        if existing == 0 {
+            return Ok(HttpResponse::NotFound().json("Building not found"));
+        }
+
+        // Rooms must be moved or removed first
+        let rooms = crate::buildings::find_rooms_of_building(&conn, building.as_str())?;
+        if !rooms.is_empty() {
+            return Ok(
+                HttpResponse::Conflict().json(format!("Building still has {} rooms", rooms.len()))
+            );
+        }
+
+        diesel::delete(floors::dsl::floors.filter(floors::dsl::building.eq(building.as_str())))
+            .execute(&conn)?;
+        diesel::delete(buildings::dsl::buildings.filter(buildings::dsl::id.eq(building.as_str())))
+            .execute(&conn)?;
+        Ok(HttpResponse::Ok().finish())
+    })?;
+    Ok(result)
+}
+
+pub async fn add_floor(
+    building: web::Path<String>,
+    properties: web::Json<FloorProperties>,
+    db_pool: web::Data<DbPool>,
+    _claims: AdminFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::buildings;
+        use crate::schema::floors::dsl;
+
+        let existing: i64 = buildings::dsl::buildings
+            .filter(buildings::dsl::id.eq(building.as_str()))
+            .count()
+            .get_result(&conn)?;
+        if existing == 0 {
+            return Ok(HttpResponse::NotFound().json("Building not found"));
+        }
+
+        diesel::insert_into(crate::schema::floors::table)
+            .values(NewFloor {
+                building: building.to_string(),
+                name: properties.name.clone(),
+                level: properties.level,
+            })
+            .execute(&conn)?;
+        let new_id: i32 = diesel::select(last_insert_rowid).get_result(&conn)?;
+        let floor: Floor = dsl::floors.find(new_id).first(&conn)?;
+        Ok(HttpResponse::Ok().json(floor))
+    })?;
+    Ok(result)
+}
+
+pub async fn update_floor(
+    path: web::Path<(String, i32)>,
+    properties: web::Json<FloorProperties>,
+    db_pool: web::Data<DbPool>,
+    _claims: AdminFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    use crate::schema::floors::dsl;
+    let updated = diesel::update(
+        dsl::floors
+            .filter(dsl::id.eq(path.1))
+            .filter(dsl::building.eq(path.0.as_str())),
+    )
+    .set((
+        dsl::name.eq(&properties.name),
+        dsl::level.eq(properties.level),
+    ))
+    .execute(&conn)?;
+
+    if updated > 0 {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().json("Floor not found"))
+    }
+}
+
+pub async fn delete_floor(
+    path: web::Path<(String, i32)>,
+    db_pool: web::Data<DbPool>,
+    _claims: AdminFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::floors::dsl;
+        use crate::schema::rooms;
+
+        let existing: i64 = dsl::floors
+            .filter(dsl::id.eq(path.1))
+            .filter(dsl::building.eq(path.0.as_str()))
+            .count()
+            .get_result(&conn)?;
+        if existing == 0 {
+            return Ok(HttpResponse::NotFound().json("Floor not found"));
+        }
+
+        // Rooms must be moved or removed first
+        let rooms: i64 = rooms::dsl::rooms
+            .filter(rooms::dsl::floor_id.eq(path.1))
+            .count()
+            .get_result(&conn)?;
+        if rooms > 0 {
+            return Ok(HttpResponse::Conflict().json(format!("Floor still has {} rooms", rooms)));
+        }
+
+        diesel::delete(dsl::floors.find(path.1)).execute(&conn)?;
+        Ok(HttpResponse::Ok().finish())
+    })?;
+    Ok(result)
+}
+
+/// Get all occupancies of the room that overlap with the given time range (in UTC).
+pub fn find_overlapping_occupancies<Conn>(
+    conn: &Conn,
+    room: &str,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    ignore_id: Option<i32>,
+) -> QueryResult<Vec<Occupancy>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::occupancies::dsl;
+
+    let basic_overlap_query = dsl::occupancies
+        .filter(dsl::room.eq(room))
+        .filter(dsl::start.lt(end))
+        .filter(dsl::end.gt(start));
+
+    if let Some(ignore_id) = ignore_id {
+        basic_overlap_query
+            .filter(dsl::id.ne(ignore_id))
+            .load::<Occupancy>(conn)
+    } else {
+        basic_overlap_query.load::<Occupancy>(conn)
+    }
+}
+
+/// Get the maximum number of occupancies that overlap at the same time in the given time range.
+pub fn max_concurrent_occupancies(
+    occupancies: &[Occupancy],
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> usize {
+    // Each occupancy (limited to the time range) starts and ends an interval,
+    // the remaining time of occupancies that have been checked out is free again
+    let mut events: Vec<(NaiveDateTime, i32)> = Vec::with_capacity(occupancies.len() * 2);
+    for o in occupancies {
+        let o_start = o.start.max(start);
+        let o_end = o.actual_end().min(end);
+        if o_start < o_end {
+            events.push((o_start, 1));
+            events.push((o_end, -1));
+        }
+    }
+    // Sort by time and process ending intervals first, so adjacent occupancies do not overlap
+    events.sort();
+
+    let mut current = 0;
+    let mut max = 0;
+    for (_, change) in events {
+        current += change;
+        if current > max {
+            max = current;
+        }
+    }
+    max as usize
+}
+
+/// Check if the room is open during the whole time range (in UTC).
+///
+/// Returns a description why the room can't be booked or `None` if it is open.
+fn find_closed_reason<Conn>(
+    conn: &Conn,
+    room: &Room,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> Result<Option<String>, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::closures;
+    use crate::schema::opening_hours;
+
+    // Closures and opening hours are given in the local time of the room
+    let tz = room.parsed_timezone();
+    let local_start = tz.from_utc_datetime(&start).naive_local();
+    let local_end = tz.from_utc_datetime(&end).naive_local();
+    // A time range that ends at midnight does not include the following day
+    let last_day = tz
+        .from_utc_datetime(&(end - Duration::nanoseconds(1)))
+        .naive_local()
+        .date();
+
+    let closure: Option<Closure> = closures::dsl::closures
+        .filter(closures::dsl::room.eq(&room.id))
+        .filter(closures::dsl::day.between(local_start.date(), last_day))
+        .order(closures::dsl::day)
+        .first(conn)
+        .optional()?;
+    if let Some(closure) = closure {
+        let reason = closure
+            .reason
+            .map(|r| format!(" ({})", r))
+            .unwrap_or_default();
+        return Ok(Some(format!(
+            "Room {} is closed on {}{}.",
+            room.id, closure.day, reason
+        )));
+    }
+
+    let holiday = holidays::find_holiday(conn, &room.id, local_start.date(), last_day)?;
+    if let Some(holiday) = holiday {
+        return Ok(Some(format!(
+            "Room {} is closed on {} ({}).",
+            room.id, holiday.day, holiday.name
+        )));
+    }
+
+    let hours: Vec<OpeningHours> = opening_hours::dsl::opening_hours
+        .filter(opening_hours::dsl::room.eq(&room.id))
+        .order(opening_hours::dsl::opens)
+        .load(conn)?;
+    if hours.is_empty() {
+        // No opening hours are configured, the room is always open
+        return Ok(None);
+    }
+
+    let day = local_start.date();
+    let day_code = weekday_code(day.weekday());
+    let hours_of_day: Vec<&OpeningHours> = hours.iter().filter(|h| h.weekday == day_code).collect();
+    let is_open = hours_of_day
+        .iter()
+        .any(|h| day.and_time(h.opens) <= local_start && local_end <= end_time_on(day, h.closes));
+    if is_open {
+        Ok(None)
+    } else if hours_of_day.is_empty() {
+        Ok(Some(format!(
+            "Room {} is closed on {}.",
+            room.id,
+            local_start.date().format("%A, %Y-%m-%d")
+        )))
+    } else {
+        let ranges: Vec<String> = hours_of_day
+            .iter()
+            .map(|h| {
+                format!(
+                    "{} to {}",
+                    h.opens.format("%H:%M"),
+                    format_end_time(h.closes)
+                )
+            })
+            .collect();
+        Ok(Some(format!(
+            "Room {} is only open from {} on {}.",
+            room.id,
+            ranges.join(" and from "),
+            local_start.date().format("%A, %Y-%m-%d")
+        )))
+    }
+}
+
+/// Check if another person fits into the room during the whole time range.
+///
+/// Invalid time ranges and time ranges in which the room is closed are rejected with an error.
+pub fn check_if_room_available<Conn, Tz>(
+    conn: &Conn,
+    room: &Room,
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    ignore_id: Option<i32>,
+) -> Result<bool, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+    Tz: TimeZone,
+    Tz::Offset: std::fmt::Display,
+{
+    check_if_room_available_for(conn, room, start, end, ignore_id, 1)
+}
+
+/// Check if the given number of additional persons fit into the room during the whole time range.
+///
+/// Invalid time ranges and time ranges in which the room is closed are rejected with an error.
+pub fn check_if_room_available_for<Conn, Tz>(
+    conn: &Conn,
+    room: &Room,
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    ignore_id: Option<i32>,
+    persons: usize,
+) -> Result<bool, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+    Tz: TimeZone,
+    Tz::Offset: std::fmt::Display,
+{
+    // Do some general validity checks
+    if end <= start {
+        return Err(ServiceError::BadRequest(format!(
+            "Begin of time range ({}) is after end of range ({}).",
+            &start.to_rfc3339(),
+            &end.to_rfc3339(),
+        )));
+    }
+
+    // The time range can end at midnight of the following day
+    if start.date() != (end.clone() - Duration::nanoseconds(1)).date() {
+        return Err(ServiceError::BadRequest(format!(
+            "Start ({}) and end ({}) of time range must be on the same day.",
+            &start.to_rfc3339(),
+            &end.to_rfc3339(),
+        )));
+    }
+
+    let start = start.naive_utc();
+    let end = end.naive_utc();
+    if let Some(reason) = find_closed_reason(conn, room, start, end)? {
+        return Err(ServiceError::BadRequest(reason));
+    }
+
+    // Check the maximum number of persons at the same time in each part of the time range,
+    // since capacity rules can change the limit of the room during the time range
+    let overlapping_existing = find_overlapping_occupancies(conn, &room.id, start, end, ignore_id)?;
+    for (part_start, part_end, max_occupancy) in capacity::find_limits(conn, room, start, end)? {
+        let max_existing = max_concurrent_occupancies(&overlapping_existing, part_start, part_end);
+        if (max_existing + persons) as i32 > max_occupancy {
+            return Ok(false);
+        }
+    }
+
+    // The building of the room can have an additional limit for all of its rooms
+    Ok(buildings::check_if_building_available(
+        conn, room, start, end, ignore_id, persons,
+    )?)
+}
+
+/// The maximum number of days a single booking can span.
+const MAX_BOOKING_DAYS: i64 = 366;
+
+/// Start and end of a time range in the local time of a room.
+type LocalTimeRange = (DateTime<chrono_tz::Tz>, DateTime<chrono_tz::Tz>);
+
+/// Split a time range into one range per local day of the room, limited by its opening hours.
+///
+/// Days on which the room is closed are skipped. If there are several opening
+/// hours on the same day, there is one range for each of them.
+fn split_into_days<Conn, Tz>(
+    conn: &Conn,
+    room: &Room,
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+) -> Result<Vec<LocalTimeRange>, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+    Tz: TimeZone,
+{
+    use crate::schema::opening_hours;
+
+    let tz = room.parsed_timezone();
+    let start = start.with_timezone(&tz);
+    let end = end.with_timezone(&tz);
+    let local_time =
+        |day: NaiveDate, time: NaiveTime| tz.from_local_datetime(&day.and_time(time)).earliest();
+
+    let hours: Vec<OpeningHours> = opening_hours::dsl::opening_hours
+        .filter(opening_hours::dsl::room.eq(&room.id))
+        .order(opening_hours::dsl::opens)
+        .load(conn)?;
+
+    let mut result = Vec::new();
+    let mut day = start.naive_local().date();
+    let last_day = (end - Duration::nanoseconds(1)).naive_local().date();
+    while day <= last_day {
+        let midnight = NaiveTime::from_hms(0, 0, 0);
+        let day_start = local_time(day, midnight).map_or(start, |t| t.max(start));
+        let day_end = local_time(day.succ(), midnight).map_or(end, |t| t.min(end));
+
+        let mut ranges = Vec::new();
+        if hours.is_empty() {
+            ranges.push((day_start, day_end));
+        } else {
+            let day_code = weekday_code(day.weekday());
+            for h in hours.iter().filter(|h| h.weekday == day_code) {
+                let closes = tz
+                    .from_local_datetime(&end_time_on(day, h.closes))
+                    .earliest();
+                if let (Some(opens), Some(closes)) = (local_time(day, h.opens), closes) {
+                    ranges.push((opens.max(day_start), closes.min(day_end)));
+                }
+            }
+        }
+        for (range_start, range_end) in ranges {
+            if range_start < range_end
+                && find_closed_reason(conn, room, range_start.naive_utc(), range_end.naive_utc())?
+                    .is_none()
+            {
+                result.push((range_start, range_end));
+            }
+        }
+        day = day.succ();
+    }
+    Ok(result)
+}
+
+/// Notify the owner of the changed occupancies and send the change to the webhook endpoints.
+pub fn announce_occupancy_change<Conn>(
+    conn: &Conn,
+    room: &Room,
+    settings: &Settings,
+    change: OccupancyChange,
+    occupancies: &[Occupancy],
+    actor: &str,
+) -> Result<(), ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    notifications::notify_occupancy_change(conn, room, settings, change, occupancies, actor)?;
+    webhooks::enqueue(conn, &settings.webhooks, change, occupancies)
+}
+
+/// A user an occupancy is booked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Participant {
+    pub user_id: String,
+    pub name: String,
+    pub contact: String,
+}
+
+impl From<&Claims> for Participant {
+    fn from(claims: &Claims) -> Self {
+        Participant {
+            user_id: claims.sub.clone(),
+            name: claims.name.clone(),
+            contact: claims.contact_info.clone(),
+        }
+    }
+}
+
+/// Check that the user of the claims may book for the given users.
+///
+/// Users can only book for themselves, room managers and admins can book for all users.
+fn check_can_book_for(user_ids: &[String], claims: &Claims) -> Result<(), ServiceError> {
+    if claims.role < Role::RoomManager && user_ids.iter().any(|id| *id != claims.sub) {
+        return Err(ServiceError::Forbidden(
+            "Only room managers can book for other users".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Look up the participants in the `users` table or via LDAP, duplicates are removed.
+///
+/// Unknown users are rejected with an error.
+async fn resolve_participants(
+    user_ids: &[String],
+    claims: &Claims,
+    db_pool: &DbPool,
+    settings: &Settings,
+) -> Result<Vec<Participant>, ServiceError> {
+    use crate::schema::users::dsl;
+
+    let mut unique_ids: Vec<&String> = Vec::with_capacity(user_ids.len());
+    for id in user_ids {
+        if !unique_ids.contains(&id) {
+            unique_ids.push(id);
+        }
+    }
+
+    let mut resolved: BTreeMap<String, Participant> = BTreeMap::new();
+    let mut unresolved = Vec::new();
+    {
+        let conn = db_pool.get()?;
+        for id in &unique_ids {
+            if **id == claims.sub {
+                resolved.insert(claims.sub.clone(), claims.into());
+                continue;
+            }
+            let user: Option<User> = dsl::users.find(id.as_str()).first(&conn).optional()?;
+            if let Some(user) = user {
+                resolved.insert(
+                    user.id.clone(),
+                    Participant {
+                        user_id: user.id,
+                        name: user.display_name,
+                        contact: user.contact_info,
+                    },
+                );
+            } else {
+                unresolved.push(id.to_string());
+            }
+        }
+    }
+
+    if !unresolved.is_empty() {
+        let ldap_settings = ldap3::LdapConnSettings::new();
+        let (conn, mut ldap) =
+            LdapConnAsync::with_settings(ldap_settings, &settings.ldap.url).await?;
+        ldap3::drive!(conn);
+        if let (Some(bind_dn), Some(bind_password)) =
+            (&settings.ldap.bind_dn, &settings.ldap.bind_password)
+        {
+            let result = ldap.simple_bind(bind_dn, bind_password).await?;
+            if result.rc != 0 {
+                return Err(ServiceError::InternalServerError(format!(
+                    "Could not bind to LDAP as {} (result code {})",
+                    bind_dn, result.rc
+                )));
+            }
+        }
+
+        for id in unresolved {
+            let user_query = format!(
+                "uid={},{}",
+                ldap3::dn_escape(id.as_str()),
+                settings.ldap.organization
+            );
+            let search = ldap
+                .search(
+                    &user_query,
+                    Scope::Base,
+                    &settings.ldap.filter,
+                    vec!["cn", "publicEMailAddress"],
+                )
+                .await?;
+            // Users that do not exist are reported as "no such object" (32)
+            if search.1.rc != 0 && search.1.rc != 32 {
+                return Err(ServiceError::InternalServerError(format!(
+                    "Could not search user {} in LDAP (result code {})",
+                    id, search.1.rc
+                )));
+            }
+            if let Some(entry) = search.0.into_iter().next() {
+                let entry = SearchEntry::construct(entry);
+                if let (Some(cn), Some(email)) = (
+                    entry.attrs.get("cn").and_then(|v| v.first()),
+                    entry
+                        .attrs
+                        .get("publicEMailAddress")
+                        .and_then(|v| v.first()),
+                ) {
+                    resolved.insert(
+                        id.clone(),
+                        Participant {
+                            user_id: id,
+                            name: cn.clone(),
+                            contact: email.clone(),
+                        },
+                    );
+                }
+            }
+        }
+        ldap.unbind().await?;
+    }
+
+    let unknown: Vec<&str> = unique_ids
+        .iter()
+        .filter(|id| !resolved.contains_key(id.as_str()))
+        .map(|id| id.as_str())
+        .collect();
+    if !unknown.is_empty() {
+        return Err(ServiceError::BadRequest(format!(
+            "Unknown participants: {}",
+            unknown.join(", ")
+        )));
+    }
+    Ok(unique_ids
+        .into_iter()
+        .filter_map(|id| resolved.remove(id))
+        .collect())
+}
+
+/// Insert a new occupancy and record its creation by the actor in the audit log.
+pub fn insert_occupancy<Conn>(
+    conn: &Conn,
+    new_item: &NewOccupancy,
+    actor: &str,
+) -> Result<Occupancy, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    diesel::insert_into(crate::schema::occupancies::table)
+        .values(new_item)
+        .execute(conn)?;
+
+    let new_id: i32 = diesel::select(last_insert_rowid).get_result(conn)?;
+    let inserted: Occupancy = crate::schema::occupancies::dsl::occupancies
+        .find(new_id)
+        .first(conn)?;
+    audit::record_create(conn, actor, &inserted)?;
+    Ok(inserted)
+}
+
+pub async fn add_occupancy(
+    event: web::Json<OccupancyProperties>,
+    room: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let start = DateTime::parse_from_rfc3339(&event.start)?;
+    let end = DateTime::parse_from_rfc3339(&event.end)?;
+
+    // The room is booked for the current user, unless the participants are given explicitly
+    check_can_book_for(&event.participants, &claims.0)?;
+    let participants = if event.participants.is_empty() {
+        vec![Participant::from(&claims.0)]
+    } else {
+        resolve_participants(&event.participants, &claims.0, &db_pool, &settings).await?
+    };
+    if event.seat.is_some() && participants.len() > 1 {
+        return Err(ServiceError::BadRequest(
+            "A seat can only be booked for a single participant.".to_string(),
+        ));
+    }
+
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        // Get the general room capacity
+        use crate::schema::rooms;
+        let room: Option<Room> = rooms::dsl::rooms
+            .filter(rooms::dsl::id.eq(room.as_ref()))
+            .order(rooms::dsl::id)
+            .load(&conn)?
+            .into_iter()
+            .next();
+        if let Some(room) = room {
+            // Round the dates to the booking slots of the room
+            let slot = room.slot_duration(&settings);
+            let start = start.duration_round(slot)?;
+            let end = end.duration_round(slot)?;
+
+            // Days are determined by the local time of the room
+            let tz = room.parsed_timezone();
+            let start = start.with_timezone(&tz);
+            let end = end.with_timezone(&tz);
+            if start < end && start.date() != (end - Duration::nanoseconds(1)).date() {
+                return add_multi_day_occupancy(
+                    &conn,
+                    &room,
+                    start,
+                    end,
+                    event.seat,
+                    &participants,
+                    &claims.0,
+                    &settings,
+                );
+            }
+            if participants.len() > 1 {
+                return add_occupancies_for_days(
+                    &conn,
+                    &room,
+                    &[(start, end)],
+                    event.seat,
+                    &participants,
+                    &claims.0,
+                    &settings,
+                );
+            }
+
+            let participant = &participants[0];
+            if check_if_room_available(&conn, &room, start, end, None)? {
+                if let Some(seat) = event.seat {
+                    let (start, end) = (start.naive_utc(), end.naive_utc());
+                    if !seats::check_if_seat_available(&conn, &room, seat, start, end, None)? {
+                        return Ok(HttpResponse::Conflict().json("Seat already taken"));
+                    }
+                }
+                quotas::check_quotas(
+                    &conn,
+                    &room,
+                    &settings,
+                    &participant.user_id,
+                    &[(start.naive_utc(), end.naive_utc())],
+                    None,
+                )?;
+                // Check was successful, add the new event
+                let new_item = NewOccupancy {
+                    room: room.id.clone(),
+                    user_id: participant.user_id.clone(),
+                    user_name: participant.name.clone(),
+                    user_contact: participant.contact.clone(),
+                    start: start.naive_utc(),
+                    end: end.naive_utc(),
+                    series_id: None,
+                    seat_id: event.seat,
+                };
+                let occupancies = vec![insert_occupancy(&conn, &new_item, &claims.0.sub)?];
+                announce_occupancy_change(
+                    &conn,
+                    &room,
+                    &settings,
+                    OccupancyChange::Created,
+                    &occupancies,
+                    &claims.0.name,
+                )?;
+
+                Ok(HttpResponse::Ok().json(occupancies))
+            } else {
+                return Ok(HttpResponse::Conflict().json("Room already full"));
+            }
+        } else {
+            Ok(HttpResponse::NotFound().json("Room not found"))
+        }
+    })?;
+    Ok(result)
+}
+
+/// Add one occupancy for each participant and each day of a time range that spans several days.
+///
+/// Either all occupancies are added or none, if the room (or the seat) is already full on one
+/// of the days.
+#[allow(clippy::too_many_arguments)]
+fn add_multi_day_occupancy<Conn>(
+    conn: &Conn,
+    room: &Room,
+    start: DateTime<chrono_tz::Tz>,
+    end: DateTime<chrono_tz::Tz>,
+    seat: Option<i32>,
+    participants: &[Participant],
+    actor: &Claims,
+    settings: &Settings,
+) -> Result<HttpResponse, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    if (end - start).num_days() > MAX_BOOKING_DAYS {
+        return Err(ServiceError::BadRequest(format!(
+            "A booking can span at most {} days.",
+            MAX_BOOKING_DAYS
+        )));
+    }
+
+    let days = split_into_days(conn, room, start, end)?;
+    if days.is_empty() {
+        return Err(ServiceError::BadRequest(format!(
+            "Room {} is closed during the whole time range.",
+            room.id
+        )));
+    }
+    add_occupancies_for_days(conn, room, &days, seat, participants, actor, settings)
+}
+
+/// Add one occupancy for each participant on each of the days.
+///
+/// Either all occupancies are added or none, if the room (or the seat) can't take all
+/// participants on one of the days or a quota of one of the participants would be exceeded.
+fn add_occupancies_for_days<Conn>(
+    conn: &Conn,
+    room: &Room,
+    days: &[LocalTimeRange],
+    seat: Option<i32>,
+    participants: &[Participant],
+    actor: &Claims,
+    settings: &Settings,
+) -> Result<HttpResponse, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    // Check all days before adding any occupancy
+    let mut conflicts = Vec::new();
+    for (day_start, day_end) in days {
+        let seat_available = if let Some(seat) = seat {
+            let (start, end) = (day_start.naive_utc(), day_end.naive_utc());
+            seats::check_if_seat_available(conn, room, seat, start, end, None)?
+        } else {
+            true
+        };
+        if !seat_available
+            || !check_if_room_available_for(
+                conn,
+                room,
+                *day_start,
+                *day_end,
+                None,
+                participants.len(),
+            )?
+        {
+            conflicts.push(TimeRange {
+                start: day_start.to_rfc3339(),
+                end: day_end.to_rfc3339(),
+            });
+        }
+    }
+    if !conflicts.is_empty() {
+        return Ok(HttpResponse::Conflict().json(conflicts));
+    }
+    let ranges: Vec<_> = days
+        .iter()
+        .map(|(day_start, day_end)| (day_start.naive_utc(), day_end.naive_utc()))
+        .collect();
+    for p in participants {
+        quotas::check_quotas(conn, room, settings, &p.user_id, &ranges, None)?;
+    }
+
+    let mut result = Vec::with_capacity(days.len() * participants.len());
+    for p in participants {
+        let mut occupancies = Vec::with_capacity(days.len());
+        for (day_start, day_end) in days {
+            let new_item = NewOccupancy {
+                room: room.id.clone(),
+                user_id: p.user_id.clone(),
+                user_name: p.name.clone(),
+                user_contact: p.contact.clone(),
+                start: day_start.naive_utc(),
+                end: day_end.naive_utc(),
+                series_id: None,
+                seat_id: seat,
+            };
+            occupancies.push(insert_occupancy(conn, &new_item, &actor.sub)?);
+        }
+        announce_occupancy_change(
+            conn,
+            room,
+            settings,
+            OccupancyChange::Created,
+            &occupancies,
+            &actor.name,
+        )?;
+        result.append(&mut occupancies);
+    }
+    Ok(HttpResponse::Ok().json(result))
+}
+
+/// Get an existing occupancy that the user of the given claims is allowed to change.
+///
+/// Users can only change their own occupancies, room managers and admins can
+/// change the occupancies of all users.
+fn get_changeable_occupancy<Conn>(
+    conn: &Conn,
+    room: &str,
+    id: i32,
+    claims: &Claims,
+) -> Result<Option<Occupancy>, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::occupancies::dsl;
+
+    let occupancy: Option<Occupancy> = dsl::occupancies
+        .filter(dsl::id.eq(id))
+        .filter(dsl::room.eq(room))
+        .first(conn)
+        .optional()?;
+    if let Some(occupancy) = &occupancy {
+        if occupancy.user_id != claims.sub && claims.role < Role::RoomManager {
+            return Err(ServiceError::Forbidden(
+                "Occupancy belongs to a different user".to_string(),
+            ));
+        }
+    }
+    Ok(occupancy)
+}
+
+pub async fn update_occupancy(
+    path: web::Path<(String, i32)>,
+    event: web::Json<TimeRange>,
+    db_pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::occupancies;
+        use crate::schema::rooms;
+
+        let start = DateTime::parse_from_rfc3339(&event.start)?;
+        let end = DateTime::parse_from_rfc3339(&event.end)?;
+
+        let room: Option<Room> = rooms::dsl::rooms
+            .filter(rooms::dsl::id.eq(path.0.as_str()))
+            .load(&conn)?
+            .into_iter()
+            .next();
+        if let Some(room) = room {
+            // Round the dates to the booking slots of the room
+            let slot = room.slot_duration(&settings);
+            let start = start.duration_round(slot)?;
+            let end = end.duration_round(slot)?;
+
+            // Days are determined by the local time of the room
+            let tz = room.parsed_timezone();
+            let start = start.with_timezone(&tz);
+            let end = end.with_timezone(&tz);
+
+            let occupancy = get_changeable_occupancy(&conn, &room.id, path.1, &claims.0)?;
+            if let Some(occupancy) = occupancy {
+                // Check if this event would lead to an invalid state, the seat is kept
+                let seat_available = if let Some(seat) = occupancy.seat_id {
+                    let (start, end) = (start.naive_utc(), end.naive_utc());
+                    seats::check_if_seat_available(
+                        &conn,
+                        &room,
+                        seat,
+                        start,
+                        end,
+                        Some(occupancy.id),
+                    )?
+                } else {
+                    true
+                };
+                if !seat_available {
+                    Ok(HttpResponse::Conflict().json("Seat already taken"))
+                } else if check_if_room_available(&conn, &room, start, end, Some(occupancy.id))? {
+                    quotas::check_quotas(
+                        &conn,
+                        &room,
+                        &settings,
+                        &occupancy.user_id,
+                        &[(start.naive_utc(), end.naive_utc())],
+                        Some(occupancy.id),
+                    )?;
+                    diesel::update(
+                        occupancies::dsl::occupancies
+                            .filter(occupancies::dsl::id.eq(occupancy.id)),
+                    )
+                    .set((
+                        occupancies::dsl::start.eq(start.naive_utc()),
+                        occupancies::dsl::end.eq(end.naive_utc()),
+                        occupancies::dsl::modified_by.eq(&claims.0.sub),
+                    ))
+                    .execute(&conn)?;
+                    audit::record_update(
+                        &conn,
+                        &claims.0.sub,
+                        &occupancy,
+                        start.naive_utc(),
+                        end.naive_utc(),
+                    )?;
+                    let updated: Occupancy = occupancies::dsl::occupancies
+                        .find(occupancy.id)
+                        .first(&conn)?;
+                    announce_occupancy_change(
+                        &conn,
+                        &room,
+                        &settings,
+                        OccupancyChange::Updated {
+                            old_start: occupancy.start,
+                            old_end: occupancy.end,
+                        },
+                        &[updated],
+                        &claims.0.name,
+                    )?;
+                    // The old time range of the occupancy might be free now
+                    waitlist::book_waiting_users(&conn, &room, &settings)?;
+                    Ok(HttpResponse::Ok().finish())
+                } else {
+                    Ok(HttpResponse::Conflict().json("Room already full"))
+                }
+            } else {
+                Ok(HttpResponse::NotFound().json("Occupancy not found"))
+            }
+        } else {
+            Ok(HttpResponse::NotFound().json("Room not found"))
+        }
+    })?;
+    Ok(result)
+}
+
+pub async fn delete_occupancy(
+    path: web::Path<(String, i32)>,
+    db_pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::occupancies;
+        use crate::schema::rooms;
+
+        let occupancy = get_changeable_occupancy(&conn, &path.0, path.1, &claims.0)?;
+        if let Some(occupancy) = occupancy {
+            diesel::delete(
+                occupancies::dsl::occupancies.filter(occupancies::dsl::id.eq(occupancy.id)),
+            )
+            .execute(&conn)?;
+            audit::record_delete(&conn, &claims.0.sub, &occupancy)?;
+
+            let room: Room = rooms::dsl::rooms.find(&occupancy.room).first(&conn)?;
+            announce_occupancy_change(
+                &conn,
+                &room,
+                &settings,
+                OccupancyChange::Deleted,
+                &[occupancy],
+                &claims.0.name,
+            )?;
+            waitlist::book_waiting_users(&conn, &room, &settings)?;
+            Ok(HttpResponse::Ok().finish())
+        } else {
+            Ok(HttpResponse::NotFound().json("Occupancy not found"))
+        }
+    })?;
+    Ok(result)
+}
+
+#[derive(Deserialize)]
+pub struct CheckinParams {
+    /// Check-in token of the room, needed if the room requires one
+    pub token: Option<String>,
+}
+
+pub async fn checkin_occupancy(
+    path: web::Path<(String, i32)>,
+    params: web::Query<CheckinParams>,
+    db_pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::occupancies::dsl;
+        use crate::schema::rooms;
+
+        let room: Option<Room> = rooms::dsl::rooms
+            .filter(rooms::dsl::id.eq(path.0.as_str()))
+            .first(&conn)
+            .optional()?;
+        let room = if let Some(room) = room {
+            room
+        } else {
+            return Ok(HttpResponse::NotFound().json("Room not found"));
+        };
+        let occupancy = get_changeable_occupancy(&conn, &room.id, path.1, &claims.0)?;
+        let occupancy = if let Some(occupancy) = occupancy {
+            occupancy
+        } else {
+            return Ok(HttpResponse::NotFound().json("Occupancy not found"));
+        };
+
+        // Room managers can check in users without being in the room
+        if let Some(token) = &room.checkin_token {
+            if claims.0.role < Role::RoomManager && params.token.as_ref() != Some(token) {
+                return Err(ServiceError::Forbidden(
+                    "Checking in to this room requires the check-in token of the room".to_string(),
+                ));
+            }
+        }
+        if occupancy.checked_in_at.is_some() {
+            return Ok(HttpResponse::Ok().json(occupancy));
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let early = Duration::minutes(settings.checkin.early_minutes);
+        if now < occupancy.start - early || now >= occupancy.end {
+            return Err(ServiceError::BadRequest(format!(
+                "Occupancies can only be checked in from {} minutes before their start until their end.",
+                settings.checkin.early_minutes
+            )));
+        }
+        diesel::update(dsl::occupancies.find(occupancy.id))
+            .set(dsl::checked_in_at.eq(now))
+            .execute(&conn)?;
+        audit::record_checkin(&conn, &claims.0.sub, &occupancy, now)?;
+        let updated: Occupancy = dsl::occupancies.find(occupancy.id).first(&conn)?;
+        announce_occupancy_change(
+            &conn,
+            &room,
+            &settings,
+            OccupancyChange::Updated {
+                old_start: occupancy.start,
+                old_end: occupancy.end,
+            },
+            std::slice::from_ref(&updated),
+            &claims.0.name,
+        )?;
+        Ok(HttpResponse::Ok().json(updated))
+    })?;
+    Ok(result)
+}
+
+pub async fn checkout_occupancy(
+    path: web::Path<(String, i32)>,
+    db_pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::occupancies::dsl;
+        use crate::schema::rooms;
+
+        let room: Option<Room> = rooms::dsl::rooms
+            .filter(rooms::dsl::id.eq(path.0.as_str()))
+            .first(&conn)
+            .optional()?;
+        let room = if let Some(room) = room {
+            room
+        } else {
+            return Ok(HttpResponse::NotFound().json("Room not found"));
+        };
+        let occupancy = get_changeable_occupancy(&conn, &room.id, path.1, &claims.0)?;
+        let occupancy = if let Some(occupancy) = occupancy {
+            occupancy
+        } else {
+            return Ok(HttpResponse::NotFound().json("Occupancy not found"));
+        };
+        if occupancy.checked_out_at.is_some() {
+            return Ok(HttpResponse::Ok().json(occupancy));
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        if now < occupancy.actual_start() || now >= occupancy.end {
+            return Err(ServiceError::BadRequest(
+                "Occupancies can only be checked out after their start and before their end."
+                    .to_string(),
+            ));
+        }
+        diesel::update(dsl::occupancies.find(occupancy.id))
+            .set(dsl::checked_out_at.eq(now))
+            .execute(&conn)?;
+        // The check-out time is recorded as the actual end
+        audit::record_checkout(&conn, &claims.0.sub, &occupancy, now)?;
+        let updated: Occupancy = dsl::occupancies.find(occupancy.id).first(&conn)?;
+        announce_occupancy_change(
+            &conn,
+            &room,
+            &settings,
+            OccupancyChange::Updated {
+                old_start: occupancy.start,
+                old_end: occupancy.end,
+            },
+            std::slice::from_ref(&updated),
+            &claims.0.name,
+        )?;
+
+        // The rest of the occupancy is free for other users now
+        waitlist::book_waiting_users(&conn, &room, &settings)?;
+        Ok(HttpResponse::Ok().json(updated))
+    })?;
+    Ok(result)
+}
+
+#[derive(Serialize)]
+pub struct CheckinToken {
+    pub token: String,
+}
+
+pub async fn create_checkin_token(
+    room: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+    _claims: RoomManagerFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    use crate::schema::rooms::dsl;
+
+    // Replaces any existing token, e.g. when a QR code has been copied
+    let token: String = thread_rng().sample_iter(&Alphanumeric).take(20).collect();
+    let updated = diesel::update(dsl::rooms.filter(dsl::id.eq(room.as_str())))
+        .set(dsl::checkin_token.eq(&token))
+        .execute(&conn)?;
+    if updated > 0 {
+        Ok(HttpResponse::Ok().json(CheckinToken { token }))
+    } else {
+        Ok(HttpResponse::NotFound().json("Room not found"))
+    }
+}
+
+pub async fn delete_checkin_token(
+    room: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+    _claims: RoomManagerFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    use crate::schema::rooms::dsl;
+
+    let updated = diesel::update(dsl::rooms.filter(dsl::id.eq(room.as_str())))
+        .set(dsl::checkin_token.eq(None::<String>))
+        .execute(&conn)?;
+    if updated > 0 {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().json("Room not found"))
+    }
+}
+
+pub async fn join_waitlist(
+    event: web::Json<TimeRange>,
+    room: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let start = DateTime::parse_from_rfc3339(&event.start)?;
+    let end = DateTime::parse_from_rfc3339(&event.end)?;
+
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::rooms;
+        use crate::schema::waitlist::dsl;
+
+        let room: Option<Room> = rooms::dsl::rooms
+            .filter(rooms::dsl::id.eq(room.as_str()))
+            .first(&conn)
+            .optional()?;
+        if let Some(room) = room {
+            // Round the dates to the booking slots of the room
+            let slot = room.slot_duration(&settings);
+            let tz = room.parsed_timezone();
+            let start = start.duration_round(slot)?.with_timezone(&tz);
+            let end = end.duration_round(slot)?.with_timezone(&tz);
+
+            if start.naive_utc() <= chrono::Utc::now().naive_utc() {
+                return Err(ServiceError::BadRequest(
+                    "Only time ranges in the future can be waited for.".to_string(),
+                ));
+            }
+            if check_if_room_available(&conn, &room, start, end, None)? {
+                return Err(ServiceError::BadRequest(
+                    "Room is not full at this time, add an occupancy instead.".to_string(),
+                ));
+            }
+
+            diesel::insert_into(crate::schema::waitlist::table)
+                .values(NewWaitlistEntry {
+                    room: room.id.clone(),
+                    user_id: claims.0.sub.to_string(),
+                    user_name: claims.0.name.to_string(),
+                    user_contact: claims.0.contact_info.to_string(),
+                    start: start.naive_utc(),
+                    end: end.naive_utc(),
+                    created_at: chrono::Utc::now().naive_utc(),
+                })
+                .execute(&conn)?;
+            let entry_id: i32 = diesel::select(last_insert_rowid).get_result(&conn)?;
+            let entry: WaitlistEntry = dsl::waitlist.find(entry_id).first(&conn)?;
+
+            Ok(HttpResponse::Ok().json(entry))
+        } else {
+            Ok(HttpResponse::NotFound().json("Room not found"))
+        }
+    })?;
+    Ok(result)
+}
+
+pub async fn leave_waitlist(
+    path: web::Path<(String, i32)>,
+    db_pool: web::Data<DbPool>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    use crate::schema::waitlist::dsl;
+
+    let entry: Option<WaitlistEntry> = dsl::waitlist
+        .filter(dsl::id.eq(path.1))
+        .filter(dsl::room.eq(path.0.as_str()))
+        .first(&conn)
+        .optional()?;
+    if let Some(entry) = entry {
+        if entry.user_id != claims.0.sub && claims.0.role < Role::RoomManager {
+            return Err(ServiceError::Forbidden(
+                "Waitlist entry belongs to a different user".to_string(),
+            ));
+        }
+        diesel::delete(dsl::waitlist.find(entry.id)).execute(&conn)?;
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().json("Waitlist entry not found"))
+    }
+}
+
+pub async fn user_waitlist(
+    db_pool: web::Data<DbPool>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    use crate::schema::waitlist::dsl;
+
+    let entries: Vec<WaitlistEntry> = dsl::waitlist
+        .filter(dsl::user_id.eq(&claims.0.sub))
+        .filter(dsl::end.gt(chrono::Utc::now().naive_utc()))
+        .order(dsl::start)
+        .load(&conn)?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+pub async fn user_notifications(
+    db_pool: web::Data<DbPool>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = notifications::find_for_user(&conn, &claims.0.sub)?;
+    Ok(HttpResponse::Ok().json(result))
+}
+
+/// The maximum number of days a series of occupancies can span.
+const MAX_SERIES_DAYS: i64 = 366;
+
+#[derive(Deserialize)]
+pub struct SeriesRequest {
+    /// RFC 3339 formatted start time of the first instance
+    pub start: String,
+    /// RFC 3339 formatted end time of the first instance
+    pub end: String,
+    /// Weekdays of the instances as two-letter abbreviations like in iCalendar ("MO", "TU", ...)
+    pub weekdays: Vec<String>,
+    /// Last day (YYYY-MM-DD) on which an instance can occur
+    pub until: String,
+}
+
+#[derive(Serialize)]
+pub struct SeriesResult {
+    pub series: Series,
+    /// All created occupancies
+    pub occupancies: Vec<Occupancy>,
+    /// Instances that could not be created because the room was already full or closed or
+    /// because they would exceed a quota of the user
+    pub conflicts: Vec<TimeRange>,
+}
+
+fn parse_weekday(day: &str) -> Result<Weekday, ServiceError> {
+    match day.to_uppercase().as_str() {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(ServiceError::BadRequest(format!(
+            "Invalid weekday \"{}\", use one of MO, TU, WE, TH, FR, SA or SU.",
+            day
+        ))),
+    }
+}
+
+pub fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+/// Get the start and end of all instances of a weekly series.
+///
+/// The instances have the same local time of day as the first instance and
+/// are on the given weekdays, starting with the day of the first instance.
+fn expand_series<Tz: TimeZone>(
+    first_start: DateTime<Tz>,
+    first_end: DateTime<Tz>,
+    weekdays: &[Weekday],
+    until: NaiveDate,
+) -> Vec<(DateTime<Tz>, DateTime<Tz>)> {
+    let tz = first_start.timezone();
+    let start_time = first_start.time();
+    let end_time = first_end.time();
+
+    let mut result = Vec::new();
+    let mut day = first_start.naive_local().date();
+    while day <= until {
+        if weekdays.contains(&day.weekday()) {
+            let start = tz.from_local_datetime(&day.and_time(start_time)).earliest();
+            let end = tz.from_local_datetime(&day.and_time(end_time)).latest();
+            if let (Some(start), Some(end)) = (start, end) {
+                result.push((start, end));
+            }
+        }
+        day = day.succ();
+    }
+    result
+}
+
+pub async fn add_series(
+    request: web::Json<SeriesRequest>,
+    room: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let start = DateTime::parse_from_rfc3339(&request.start)?;
+    let end = DateTime::parse_from_rfc3339(&request.end)?;
+    let until = NaiveDate::parse_from_str(&request.until, "%Y-%m-%d")?;
+    let mut weekdays = request
+        .weekdays
+        .iter()
+        .map(|d| parse_weekday(d))
+        .collect::<Result<Vec<_>, _>>()?;
+    weekdays.sort_by_key(|d| d.num_days_from_monday());
+    weekdays.dedup();
+
+    if weekdays.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "At least one weekday must be given.".to_string(),
+        ));
+    }
+    if until < start.date().naive_local() {
+        return Err(ServiceError::BadRequest(format!(
+            "Last day of the series ({}) is before its start ({}).",
+            until,
+            start.to_rfc3339()
+        )));
+    }
+    if (until - start.date().naive_local()).num_days() > MAX_SERIES_DAYS {
+        return Err(ServiceError::BadRequest(format!(
+            "A series can span at most {} days.",
+            MAX_SERIES_DAYS
+        )));
+    }
+
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::rooms;
+        use crate::schema::series;
+
+        let room: Option<Room> = rooms::dsl::rooms
+            .filter(rooms::dsl::id.eq(room.as_ref()))
+            .first(&conn)
+            .optional()?;
+        if let Some(room) = room {
+            // Round the dates to the booking slots of the room
+            let slot = room.slot_duration(&settings);
+            let start = start.duration_round(slot)?;
+            let end = end.duration_round(slot)?;
+
+            // Use the local time of the room for the instances, so they stay at the same time
+            // of day when daylight saving time changes
+            let tz = room.parsed_timezone();
+            let instances = expand_series(
+                start.with_timezone(&tz),
+                end.with_timezone(&tz),
+                &weekdays,
+                until,
+            );
+
+            // The instances are on different days and can be checked independently
+            let mut available = Vec::new();
+            let mut ranges = Vec::new();
+            let mut conflicts = Vec::new();
+            for (instance_start, instance_end) in instances {
+                // Skip instances on days the room is closed
+                let closed = find_closed_reason(
+                    &conn,
+                    &room,
+                    instance_start.naive_utc(),
+                    instance_end.naive_utc(),
+                )?;
+                let mut fits = closed.is_none()
+                    && check_if_room_available(&conn, &room, instance_start, instance_end, None)?;
+                if fits {
+                    // The quotas apply to the instance together with the ones accepted before
+                    ranges.push((instance_start.naive_utc(), instance_end.naive_utc()));
+                    match quotas::check_quotas(
+                        &conn,
+                        &room,
+                        &settings,
+                        &claims.0.sub,
+                        &ranges,
+                        None,
+                    ) {
+                        Ok(()) => {}
+                        Err(ServiceError::QuotaExceeded(_)) => {
+                            ranges.pop();
+                            fits = false;
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                if fits {
+                    available.push((instance_start, instance_end));
+                } else {
+                    conflicts.push(TimeRange {
+                        start: instance_start.to_rfc3339(),
+                        end: instance_end.to_rfc3339(),
+                    });
+                }
+            }
+            if available.is_empty() {
+                return Ok(HttpResponse::Conflict().json(conflicts));
+            }
+
+            let weekday_names: Vec<&str> = weekdays.iter().map(|d| weekday_code(*d)).collect();
+            diesel::insert_into(series::table)
+                .values(NewSeries {
+                    room: room.id.clone(),
+                    user_id: claims.0.sub.to_string(),
+                    first_start: start.naive_utc(),
+                    first_end: end.naive_utc(),
+                    weekdays: weekday_names.join(","),
+                    until,
+                })
+                .execute(&conn)?;
+            let series_id: i32 = diesel::select(last_insert_rowid).get_result(&conn)?;
+            let series: Series = series::dsl::series.find(series_id).first(&conn)?;
+
+            let mut occupancies = Vec::with_capacity(available.len());
+            for (instance_start, instance_end) in available {
+                let new_item = NewOccupancy {
+                    room: room.id.clone(),
+                    user_id: claims.0.sub.to_string(),
+                    user_name: claims.0.name.to_string(),
+                    user_contact: claims.0.contact_info.to_string(),
+                    start: instance_start.naive_utc(),
+                    end: instance_end.naive_utc(),
+                    series_id: Some(series_id),
+                    seat_id: None,
+                };
+                occupancies.push(insert_occupancy(&conn, &new_item, &claims.0.sub)?);
+            }
+            announce_occupancy_change(
+                &conn,
+                &room,
+                &settings,
+                OccupancyChange::Created,
+                &occupancies,
+                &claims.0.name,
+            )?;
+
+            Ok(HttpResponse::Ok().json(SeriesResult {
+                series,
+                occupancies,
+                conflicts,
+            }))
+        } else {
+            Ok(HttpResponse::NotFound().json("Room not found"))
+        }
+    })?;
+    Ok(result)
+}
+
+pub async fn delete_series(
+    path: web::Path<(String, i32)>,
+    db_pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::occupancies;
+        use crate::schema::rooms;
+        use crate::schema::series;
+
+        let existing: Option<Series> = series::dsl::series
+            .filter(series::dsl::id.eq(path.1))
+            .filter(series::dsl::room.eq(path.0.as_str()))
+            .first(&conn)
+            .optional()?;
+        if let Some(existing) = existing {
+            if existing.user_id != claims.0.sub && claims.0.role < Role::RoomManager {
+                return Err(ServiceError::Forbidden(
+                    "Series belongs to a different user".to_string(),
+                ));
+            }
+
+            // Cancel all instances that have not started yet and keep the past ones
+            // as regular occupancies
+            let now = chrono::Utc::now().naive_utc();
+            let upcoming: Vec<Occupancy> = occupancies::dsl::occupancies
+                .filter(occupancies::dsl::series_id.eq(existing.id))
+                .filter(occupancies::dsl::start.ge(now))
+                .load(&conn)?;
+            for o in upcoming.iter() {
+                diesel::delete(occupancies::dsl::occupancies.filter(occupancies::dsl::id.eq(o.id)))
+                    .execute(&conn)?;
+                audit::record_delete(&conn, &claims.0.sub, o)?;
+            }
+            diesel::update(
+                occupancies::dsl::occupancies.filter(occupancies::dsl::series_id.eq(existing.id)),
+            )
+            .set(occupancies::dsl::series_id.eq(None::<i32>))
+            .execute(&conn)?;
+            diesel::delete(series::dsl::series.filter(series::dsl::id.eq(existing.id)))
+                .execute(&conn)?;
+
+            let room: Room = rooms::dsl::rooms.find(&existing.room).first(&conn)?;
+            announce_occupancy_change(
+                &conn,
+                &room,
+                &settings,
+                OccupancyChange::Deleted,
+                &upcoming,
+                &claims.0.name,
+            )?;
+            waitlist::book_waiting_users(&conn, &room, &settings)?;
+            Ok(HttpResponse::Ok().finish())
+        } else {
+            Ok(HttpResponse::NotFound().json("Series not found"))
+        }
+    })?;
+    Ok(result)
+}
+
+/// Remove all personal information from the occupancy if it does not belong to the current user.
+fn anonymize_for_other_users(o: &mut Occupancy, current_user: &str) {
+    if o.user_id != current_user {
+        o.user_id = "<anonym>".to_string();
+        o.user_contact = "".to_string();
+        o.user_name = "<anonym>".to_string();
+        o.modified_by = None;
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct RoomOccupancyParams {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+pub async fn get_occupancies(
+    params: web::Query<RoomOccupancyParams>,
+    room: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let start = if let Some(start) = &params.start {
+        Some(DateTime::parse_from_rfc3339(start)?)
+    } else {
+        None
+    };
+    let end = if let Some(end) = &params.end {
+        Some(DateTime::parse_from_rfc3339(end)?)
+    } else {
+        None
+    };
+
+    let conn = db_pool.get()?;
+
+    // Get the general room capacity
+    use crate::schema::rooms;
+    let room: Option<Room> = rooms::dsl::rooms
+        .filter(rooms::dsl::id.eq(room.as_ref()))
+        .load(&conn)?
+        .into_iter()
+        .next();
 
     if let Some(room) = room {
+        // Round the dates to the booking slots of the room
+        let slot = room.slot_duration(&settings);
+        let start = if let Some(start) = start {
+            Some(start.duration_round(slot)?)
+        } else {
+            None
+        };
+        let end = if let Some(end) = end {
+            Some(end.duration_round(slot)?)
+        } else {
+            None
+        };
+
+        if let (Some(start), Some(end)) = (start, end) {
+            if end <= start {
+                return Ok(HttpResponse::Forbidden().json(format!(
+                    "Begin of time range ({}) ist after end of range ({}).",
+                    start, end
+                )));
+            }
+        }
+
+        use crate::schema::occupancies::dsl;
+        // Get all occupancy events for the given range
+        let mut result = if let (Some(start), Some(end)) = (start, end) {
+            dsl::occupancies
+                .filter(dsl::room.eq(&room.id))
+                .filter(dsl::start.ge(start.naive_utc()))
+                .filter(dsl::end.le(end.naive_utc()))
+                .load::<Occupancy>(&conn)?
+        } else if let Some(start) = start {
+            dsl::occupancies
+                .filter(dsl::room.eq(&room.id))
+                .filter(dsl::start.ge(start.naive_utc()))
+                .load::<Occupancy>(&conn)?
+        } else if let Some(end) = end {
+            dsl::occupancies
+                .filter(dsl::room.eq(&room.id))
+                .filter(dsl::end.le(end.naive_utc()))
+                .load::<Occupancy>(&conn)?
+        } else {
+            dsl::occupancies
+                .filter(dsl::room.eq(&room.id))
+                .load::<Occupancy>(&conn)?
+        };
+
+        // anonymize all occupancy entries for other users
+        for o in result.iter_mut() {
+            anonymize_for_other_users(o, &claims.0.sub);
+        }
+
+        Ok(HttpResponse::Ok().json(result))
+    } else {
+        Ok(HttpResponse::NotFound().json("Room not found"))
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AuditParams {
+    pub user_id: Option<String>,
+    pub actor: Option<String>,
+    pub room: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+pub async fn get_audit_log(
+    params: web::Query<AuditParams>,
+    db_pool: web::Data<DbPool>,
+    _claims: AdminFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let start = if let Some(start) = &params.start {
+        Some(DateTime::parse_from_rfc3339(start)?.naive_utc())
+    } else {
+        None
+    };
+    let end = if let Some(end) = &params.end {
+        Some(DateTime::parse_from_rfc3339(end)?.naive_utc())
+    } else {
+        None
+    };
+    let filter = AuditFilter {
+        user_id: params.user_id.clone(),
+        actor: params.actor.clone(),
+        room: params.room.clone(),
+        start,
+        end,
+    };
+
+    let conn = db_pool.get()?;
+    let result = audit::find_entries(&conn, &filter)?;
+    Ok(HttpResponse::Ok().json(result))
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TraceParams {
+    pub user_id: String,
+    pub start: String,
+    pub end: String,
+}
+
+pub async fn trace_contacts(
+    params: web::Query<TraceParams>,
+    db_pool: web::Data<DbPool>,
+    _claims: AdminFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let start = DateTime::parse_from_rfc3339(&params.start)?;
+    let end = DateTime::parse_from_rfc3339(&params.end)?;
+    if end <= start {
+        return Err(ServiceError::BadRequest(format!(
+            "Begin of time range ({}) is after end of range ({}).",
+            &start.to_rfc3339(),
+            &end.to_rfc3339(),
+        )));
+    }
+
+    let conn = db_pool.get()?;
+    let contacts =
+        export::find_contacts(&conn, &params.user_id, start.naive_utc(), end.naive_utc())?;
+    Ok(HttpResponse::Ok().json(contacts))
+}
+
+/// Create a new token to access the calendar feed of the current user.
+///
+/// Any previously created token of the user becomes invalid.
+pub async fn create_calendar_token(
+    db_pool: web::Data<DbPool>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+    let result = conn.transaction::<_, ServiceError, _>(|| {
+        use crate::schema::calendar_tokens::dsl;
+
+        diesel::delete(dsl::calendar_tokens.filter(dsl::user_id.eq(&claims.0.sub)))
+            .execute(&conn)?;
+
+        let new_token = CalendarToken {
+            token: thread_rng().sample_iter(&Alphanumeric).take(40).collect(),
+            user_id: claims.0.sub.to_string(),
+            created_at: chrono::Utc::now().naive_utc(),
+            role: claims.0.role.to_string(),
+        };
+        diesel::insert_into(crate::schema::calendar_tokens::table)
+            .values(&new_token)
+            .execute(&conn)?;
+
+        Ok(HttpResponse::Ok()
+            .content_type("text/plain")
+            .body(new_token.token))
+    })?;
+    Ok(result)
+}
+
+/// Revoke the token of the current user for the calendar feed.
+pub async fn delete_calendar_token(
+    db_pool: web::Data<DbPool>,
+    claims: ClaimsFromAuth,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+
+    use crate::schema::calendar_tokens::dsl;
+    diesel::delete(dsl::calendar_tokens.filter(dsl::user_id.eq(&claims.0.sub))).execute(&conn)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Deserialize, Clone)]
+pub struct CalendarParams {
+    pub token: String,
+}
+
+fn find_calendar_token<Conn>(
+    conn: &Conn,
+    token: &str,
+) -> Result<Option<CalendarToken>, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::calendar_tokens::dsl;
+
+    let result = dsl::calendar_tokens
+        .filter(dsl::token.eq(token))
+        .first::<CalendarToken>(conn)
+        .optional()?;
+    Ok(result)
+}
+
+pub async fn user_calendar(
+    params: web::Query<CalendarParams>,
+    db_pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+
+    let user = if let Some(token) = find_calendar_token(&conn, &params.token)? {
+        token.user_id
+    } else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+
+    use crate::schema::occupancies::dsl as o_dsl;
+    let occupancies: Vec<Occupancy> = o_dsl::occupancies
+        .filter(o_dsl::user_id.eq(&user))
+        .order(o_dsl::start)
+        .load(&conn)?;
+
+    // Show the occupancies in the local time of their rooms
+    use crate::schema::rooms::dsl as r_dsl;
+    let timezones: BTreeMap<String, chrono_tz::Tz> = r_dsl::rooms
+        .filter(r_dsl::id.eq_any(occupancies.iter().map(|o| &o.room)))
+        .load::<Room>(&conn)?
+        .into_iter()
+        .map(|r| (r.id.clone(), r.parsed_timezone()))
+        .collect();
+
+    let events: Vec<ical::Event> = occupancies
+        .into_iter()
+        .map(|o| ical::Event {
+            uid: format!("occupancy-{}@roompla", o.id),
+            start: o.start,
+            end: o.end,
+            summary: format!("Room {}", o.room),
+            description: None,
+            timezone: timezones
+                .get(&o.room)
+                .copied()
+                .unwrap_or(chrono_tz::Tz::UTC),
+            location: Some(o.room),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(ical::render("Roompla", &events)))
+}
+
+/// How many weeks of past occupancies are included in the room calendar feed.
+const ROOM_CALENDAR_PAST_WEEKS: i64 = 4;
+
+/// Get the current role of the owner of the calendar token.
+///
+/// Users from the `users` table have the role stored there. LDAP users have the role
+/// from their last login, which is also stored with the calendar token.
+fn current_role_of_token<Conn>(conn: &Conn, token: &CalendarToken) -> Result<Role, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::users::dsl;
+
+    let role: Option<String> = dsl::users
+        .find(&token.user_id)
+        .select(dsl::role)
+        .first(conn)
+        .optional()?;
+    role.unwrap_or_else(|| token.role.clone())
+        .parse()
+        .map_err(ServiceError::InternalServerError)
+}
+
+/// Get the maximum number of concurrent occupancies for each full hour in the local time
+/// of the room that is at least partially occupied.
+///
+/// The hours are identified by their start in UTC. Occupancies that have been checked out
+/// only count until the check-out.
+fn count_load_per_hour(
+    occupancies: &[Occupancy],
+    tz: chrono_tz::Tz,
+) -> BTreeMap<NaiveDateTime, usize> {
+    let mut load_per_hour: BTreeMap<NaiveDateTime, usize> = BTreeMap::new();
+    for o in occupancies.iter() {
+        // Not all timezones have an offset of full hours to UTC
+        let local_start = tz.from_utc_datetime(&o.start);
+        let mut hour = o.start
+            - Duration::minutes(local_start.minute() as i64)
+            - Duration::seconds(local_start.second() as i64)
+            - Duration::nanoseconds(local_start.nanosecond() as i64);
+        while hour < o.actual_end() {
+            load_per_hour.entry(hour).or_insert_with(|| {
+                max_concurrent_occupancies(occupancies, hour, hour + Duration::hours(1))
+            });
+            hour += Duration::hours(1);
+        }
+    }
+    load_per_hour
+}
+
+pub async fn room_calendar(
+    params: web::Query<CalendarParams>,
+    room: web::Path<String>,
+    db_pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ServiceError> {
+    let conn = db_pool.get()?;
+
+    let token = if let Some(token) = find_calendar_token(&conn, &params.token)? {
+        token
+    } else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    if current_role_of_token(&conn, &token)? < Role::RoomManager {
+        return Err(ServiceError::Forbidden(
+            "Only room managers can access the room calendar".to_string(),
+        ));
+    }
+
+    use crate::schema::rooms;
+    let room: Option<Room> = rooms::dsl::rooms
+        .filter(rooms::dsl::id.eq(room.as_ref()))
+        .first(&conn)
+        .optional()?;
+    let room = if let Some(room) = room {
+        room
+    } else {
+        return Ok(HttpResponse::NotFound().json("Room not found"));
+    };
+
+    use crate::schema::occupancies::dsl;
+    let since = (chrono::Utc::now() - Duration::weeks(ROOM_CALENDAR_PAST_WEEKS)).naive_utc();
+    let mut occupancies: Vec<Occupancy> = dsl::occupancies
+        .filter(dsl::room.eq(&room.id))
+        .filter(dsl::end.ge(since))
+        .order(dsl::start)
+        .load(&conn)?;
+
+    let load_per_hour = count_load_per_hour(&occupancies, room.parsed_timezone());
+
+    let mut events = Vec::with_capacity(occupancies.len() + load_per_hour.len());
+    for o in occupancies.iter_mut() {
+        anonymize_for_other_users(o, &token.user_id);
+        events.push(ical::Event {
+            uid: format!("occupancy-{}@roompla", o.id),
+            start: o.start,
+            end: o.end,
+            summary: format!("{} ({})", o.user_name, room.id),
+            description: None,
+            location: Some(room.id.clone()),
+            timezone: room.parsed_timezone(),
+        });
+    }
+    let rules = capacity::find_rules(&conn, &room.id)?;
+    for (hour, load) in load_per_hour {
+        let max_occupancy = capacity::limit_at(&room, &rules, hour);
+        events.push(ical::Event {
+            uid: format!("load-{}-{}@roompla", room.id, hour.format("%Y%m%dT%H%M%SZ")),
+            start: hour,
+            end: hour + Duration::hours(1),
+            summary: format!("{} of {}", load, max_occupancy),
+            description: Some(format!(
+                "{} of maximal {} persons in room {}",
+                load, max_occupancy, room.id
+            )),
+            location: Some(room.id.clone()),
+            timezone: room.parsed_timezone(),
+        });
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(ical::render(&format!("Roompla room {}", room.id), &events)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::create_test_pool;
+    use chrono::Utc;
+
+    fn time(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2020, 7, 8).and_hms(hour, minute, 0)
+    }
+
+    fn add<Conn>(conn: &Conn, room: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> i32
+    where
+        Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+    {
+        let new_item = NewOccupancy {
+            room: room.to_string(),
+            user_id: "someone".to_string(),
+            user_name: "Someone".to_string(),
+            user_contact: "someone@example.com".to_string(),
+            start: start.naive_utc(),
+            end: end.naive_utc(),
+            series_id: None,
+            seat_id: None,
+        };
+        insert_occupancy(conn, &new_item, "someone").unwrap().id
+    }
+
+    fn get_room<Conn>(conn: &Conn, id: &str) -> Room
+    where
+        Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+    {
+        crate::schema::rooms::dsl::rooms
+            .find(id)
+            .first(conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn back_to_back_occupancies_do_not_overlap() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        add(&conn, "large", time(10, 0), time(10, 30));
+        add(&conn, "large", time(10, 30), time(11, 0));
+
+        let room = get_room(&conn, "large");
+        assert!(check_if_room_available(&conn, &room, time(10, 0), time(11, 0), None).unwrap());
+    }
+
+    #[test]
+    fn overlapping_occupancies_fill_room() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        add(&conn, "large", time(10, 0), time(12, 0));
+        let second = add(&conn, "large", time(11, 0), time(13, 0));
+
+        let room = get_room(&conn, "large");
+        assert!(!check_if_room_available(&conn, &room, time(11, 0), time(11, 30), None).unwrap());
+        assert!(!check_if_room_available(&conn, &room, time(9, 0), time(14, 0), None).unwrap());
+        // The time ranges where only one of the occupancies is active are still free
+        assert!(check_if_room_available(&conn, &room, time(10, 0), time(11, 0), None).unwrap());
+        assert!(check_if_room_available(&conn, &room, time(12, 0), time(13, 0), None).unwrap());
+        // Updating an existing occupancy ignores its old time range
+        assert!(
+            check_if_room_available(&conn, &room, time(11, 0), time(11, 30), Some(second)).unwrap()
+        );
+    }
+
+    #[test]
+    fn adjacent_occupancies_at_range_boundaries() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        add(&conn, "small", time(9, 0), time(10, 0));
+        add(&conn, "small", time(12, 0), time(13, 0));
+
+        let room = get_room(&conn, "small");
+        assert!(check_if_room_available(&conn, &room, time(10, 0), time(12, 0), None).unwrap());
+        assert!(!check_if_room_available(&conn, &room, time(10, 0), time(12, 15), None).unwrap());
+        assert!(!check_if_room_available(&conn, &room, time(9, 45), time(12, 0), None).unwrap());
+    }
+
+    #[test]
+    fn occupancies_in_other_rooms_are_ignored() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        add(&conn, "large", time(10, 0), time(12, 0));
+
+        let room = get_room(&conn, "small");
+        assert!(check_if_room_available(&conn, &room, time(10, 0), time(12, 0), None).unwrap());
+    }
+
+    #[test]
+    fn invalid_time_ranges_are_rejected() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        let room = get_room(&conn, "small");
+        assert!(check_if_room_available(&conn, &room, time(12, 0), time(12, 0), None).is_err());
+        assert!(check_if_room_available(&conn, &room, time(12, 0), time(10, 0), None).is_err());
+        assert!(check_if_room_available(
+            &conn,
+            &room,
+            time(12, 0),
+            time(12, 0) + Duration::days(1),
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn opening_hours_in_local_time_are_enforced() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        use crate::schema::rooms::dsl;
+        diesel::update(dsl::rooms.filter(dsl::id.eq("small")))
+            .set(dsl::timezone.eq("Europe/Berlin"))
+            .execute(&conn)
+            .unwrap();
+        replace_opening_hours(
+            &conn,
+            "small",
+            &[OpeningHoursProperties {
+                weekday: "WE".to_string(),
+                opens: "08:00".to_string(),
+                closes: "18:00".to_string(),
+            }],
+        )
+        .unwrap();
+
+        // The room is two hours ahead of UTC in summer
+        let room = get_room(&conn, "small");
+        assert!(check_if_room_available(&conn, &room, time(6, 0), time(16, 0), None).unwrap());
+        assert!(check_if_room_available(&conn, &room, time(5, 0), time(7, 0), None).is_err());
+        assert!(check_if_room_available(&conn, &room, time(15, 0), time(17, 0), None).is_err());
+        // There are no opening hours on the next day
+        assert!(check_if_room_available(
+            &conn,
+            &room,
+            time(8, 0) + Duration::days(1),
+            time(9, 0) + Duration::days(1),
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn opening_hours_can_end_at_midnight() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let hours = OpeningHoursProperties {
+            weekday: "WE".to_string(),
+            opens: "20:00".to_string(),
+            closes: "24:00".to_string(),
+        };
+        replace_opening_hours(&conn, "small", &[hours]).unwrap();
+
+        let room = get_room(&conn, "small");
+        let midnight = time(0, 0) + Duration::days(1);
+        assert!(check_if_room_available(&conn, &room, time(22, 0), midnight, None).unwrap());
+        assert!(check_if_room_available(&conn, &room, time(19, 0), time(21, 0), None).is_err());
+        let days = split_into_days(&conn, &room, time(18, 0), midnight).unwrap();
+        assert_eq!(1, days.len());
+        assert_eq!(time(20, 0), days[0].0);
+        assert_eq!(midnight, days[0].1);
+
+        let stored: Vec<OpeningHours> = crate::schema::opening_hours::dsl::opening_hours
+            .load(&conn)
+            .unwrap();
+        let shown: Vec<OpeningHoursProperties> = stored.into_iter().map(|h| h.into()).collect();
+        assert_eq!("24:00", shown[0].closes);
+    }
+
+    #[test]
+    fn closed_days_are_enforced() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        diesel::insert_into(crate::schema::closures::table)
+            .values(NewClosure {
+                room: "small".to_string(),
+                day: NaiveDate::from_ymd(2020, 7, 8),
+                reason: Some("Cleaning".to_string()),
+            })
+            .execute(&conn)
+            .unwrap();
+
+        let room = get_room(&conn, "small");
+        assert!(check_if_room_available(&conn, &room, time(10, 0), time(11, 0), None).is_err());
+        assert!(check_if_room_available(
+            &conn,
+            &room,
+            time(10, 0) + Duration::days(1),
+            time(11, 0) + Duration::days(1),
+            None
+        )
+        .unwrap());
+        // Other rooms are not affected
+        let room = get_room(&conn, "large");
+        assert!(check_if_room_available(&conn, &room, time(10, 0), time(11, 0), None).unwrap());
+    }
+
+    #[test]
+    fn holidays_are_enforced() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        for (room, day) in &[(None, 8), (Some("small".to_string()), 9)] {
+            diesel::insert_into(crate::schema::holidays::table)
+                .values(crate::models::NewHoliday {
+                    room: room.clone(),
+                    day: NaiveDate::from_ymd(2020, 7, *day),
+                    name: "Holiday".to_string(),
+                })
+                .execute(&conn)
+                .unwrap();
+        }
+
+        let next_day = Duration::days(1);
+        // Global holidays apply to all rooms, the others only to their room
+        let small = get_room(&conn, "small");
+        assert!(check_if_room_available(&conn, &small, time(10, 0), time(11, 0), None).is_err());
+        assert!(check_if_room_available(
+            &conn,
+            &small,
+            time(10, 0) + next_day,
+            time(11, 0) + next_day,
+            None
+        )
+        .is_err());
+        let large = get_room(&conn, "large");
+        assert!(check_if_room_available(&conn, &large, time(10, 0), time(11, 0), None).is_err());
+        assert!(check_if_room_available(
+            &conn,
+            &large,
+            time(10, 0) + next_day,
+            time(11, 0) + next_day,
+            None
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn multi_day_ranges_are_split_by_opening_hours() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let weekdays = ["MO", "TU", "WE", "TH", "FR"];
+        let hours: Vec<OpeningHoursProperties> = weekdays
+            .iter()
+            .map(|day| OpeningHoursProperties {
+                weekday: day.to_string(),
+                opens: "08:00".to_string(),
+                closes: "18:00".to_string(),
+            })
+            .collect();
+        replace_opening_hours(&conn, "small", &hours).unwrap();
+
+        // From Wednesday to Saturday, but the room is closed on the weekend
+        let start = time(10, 0);
+        let end = time(12, 0) + Duration::days(3);
+
+        let room = get_room(&conn, "small");
+        let days: Vec<_> = split_into_days(&conn, &room, start, end)
+            .unwrap()
+            .into_iter()
+            .map(|(s, e)| (s.with_timezone(&Utc), e.with_timezone(&Utc)))
+            .collect();
+        assert_eq!(
+            vec![
+                (time(10, 0), time(18, 0)),
+                (
+                    time(8, 0) + Duration::days(1),
+                    time(18, 0) + Duration::days(1)
+                ),
+                (
+                    time(8, 0) + Duration::days(2),
+                    time(18, 0) + Duration::days(2)
+                ),
+            ],
+            days
+        );
+
+        // Without opening hours, the whole days are used
+        let room = get_room(&conn, "large");
+        let days = split_into_days(&conn, &room, start, end).unwrap();
+        assert_eq!(4, days.len());
+        assert_eq!(Utc.ymd(2020, 7, 11).and_hms(0, 0, 0), days[3].0);
+        assert_eq!(end, days[3].1);
+    }
+
+    #[test]
+    fn multi_day_occupancies_are_added_completely_or_not_at_all() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        add(
+            &conn,
+            "small",
+            time(9, 0) + Duration::days(2),
+            time(10, 0) + Duration::days(2),
+        );
+
+        let claims = Claims {
+            sub: "guest".to_string(),
+            name: "Guest".to_string(),
+            contact_info: "guest@example.com".to_string(),
+            role: Role::User,
+            exp: None,
+        };
+        let participants = [Participant::from(&claims)];
+        let count = |conn: &SqliteConnection| -> i64 {
+            crate::schema::occupancies::dsl::occupancies
+                .count()
+                .get_result(conn)
+                .unwrap()
+        };
+        let settings = Settings::default();
+        let tz = chrono_tz::Tz::UTC;
+        let start = time(10, 0).with_timezone(&tz);
+        let end = (time(12, 0) + Duration::days(3)).with_timezone(&tz);
+
+        let room = get_room(&conn, "small");
+        let response = add_multi_day_occupancy(
+            &conn,
+            &room,
+            start,
+            end,
+            None,
+            &participants,
+            &claims,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(409, response.status().as_u16());
+        assert_eq!(1, count(&conn));
+
+        let room = get_room(&conn, "large");
+        let response = add_multi_day_occupancy(
+            &conn,
+            &room,
+            start,
+            end,
+            None,
+            &participants,
+            &claims,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(200, response.status().as_u16());
+        assert_eq!(5, count(&conn));
+    }
+
+    #[test]
+    fn quotas_of_users_are_enforced() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        use crate::schema::rooms::dsl;
+        diesel::update(dsl::rooms.filter(dsl::id.eq("large")))
+            .set(dsl::max_hours_per_week.eq(Some(3)))
+            .execute(&conn)
+            .unwrap();
+        let mut settings = Settings::default();
+        settings.booking.max_future_bookings = Some(2);
+        settings.booking.max_days_ahead = Some(14);
+
+        // Use a Monday in the future, so all occupancies are in the same week
+        let today = Utc::today().naive_utc();
+        let monday = today + Duration::days(7 - today.weekday().num_days_from_monday() as i64);
+        let at = |day: i64, hour: u32| {
+            Utc.from_utc_datetime(&(monday + Duration::days(day)).and_hms(hour, 0, 0))
+        };
+        let quota_exceeded = |result: Result<(), ServiceError>| {
+            matches!(result, Err(ServiceError::QuotaExceeded(_)))
+        };
+
+        let small = get_room(&conn, "small");
+        let large = get_room(&conn, "large");
+        let range =
+            |start: DateTime<Utc>, end: DateTime<Utc>| vec![(start.naive_utc(), end.naive_utc())];
+        let check = |room: &Room, ranges: &[(NaiveDateTime, NaiveDateTime)], ignore_id| {
+            quotas::check_quotas(&conn, room, &settings, "someone", ranges, ignore_id)
+        };
+
+        assert!(check(&large, &range(at(0, 9), at(0, 11)), None).is_ok());
+        let first = add(&conn, "large", at(0, 9), at(0, 11));
+        // The hours per week of the room are exceeded, but not in other rooms
+        assert!(quota_exceeded(check(
+            &large,
+            &range(at(1, 9), at(1, 11)),
+            None
+        )));
+        assert!(check(&small, &range(at(1, 9), at(1, 11)), None).is_ok());
+        // Changing the existing occupancy does not count its old hours
+        assert!(check(&large, &range(at(1, 9), at(1, 12)), Some(first)).is_ok());
+
+        // Bookings too far in the future are not allowed
+        assert!(quota_exceeded(check(
+            &small,
+            &range(at(14, 9), at(14, 10)),
+            None
+        )));
+
+        // Only two future bookings are allowed in all rooms
+        add(&conn, "small", at(1, 9), at(1, 10));
+        assert!(quota_exceeded(check(
+            &small,
+            &range(at(2, 9), at(2, 10)),
+            None
+        )));
+        assert!(quota_exceeded(check(
+            &large,
+            &range(at(2, 9), at(2, 10)),
+            None
+        )));
+    }
+
+    #[test]
+    fn waiting_users_are_booked_when_capacity_is_freed() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let settings = Settings::default();
+
+        let tomorrow = Utc::today().succ();
+        let start = tomorrow.and_hms(10, 0, 0);
+        let end = tomorrow.and_hms(11, 0, 0);
+        let existing = add(&conn, "small", start, end);
+        for user in &["first", "second"] {
+            diesel::insert_into(crate::schema::waitlist::table)
+                .values(NewWaitlistEntry {
+                    room: "small".to_string(),
+                    user_id: user.to_string(),
+                    user_name: user.to_string(),
+                    user_contact: format!("{}@example.com", user),
+                    start: start.naive_utc(),
+                    end: end.naive_utc(),
+                    created_at: Utc::now().naive_utc(),
+                })
+                .execute(&conn)
+                .unwrap();
+        }
+
+        // Nobody is booked as long as the room is full
+        let room = get_room(&conn, "small");
+        assert!(waitlist::book_waiting_users(&conn, &room, &settings)
+            .unwrap()
+            .is_empty());
+
         use crate::schema::occupancies::dsl;
-        // Get all occupancy events for the given range
-        let mut result = if let (Some(start), Some(end)) = (start, end) {
-            dsl::occupancies
-                .filter(dsl::room.eq(&room.id))
-                .filter(dsl::start.ge(start.naive_utc()))
-                .filter(dsl::end.le(end.naive_utc()))
-                .load::<Occupancy>(&conn)?
-        } else if let Some(start) = start {
-            dsl::occupancies
-                .filter(dsl::room.eq(&room.id))
-                .filter(dsl::start.ge(start.naive_utc()))
-                .load::<Occupancy>(&conn)?
-        } else if let Some(end) = end {
-            dsl::occupancies
-                .filter(dsl::room.eq(&room.id))
-                .filter(dsl::end.le(end.naive_utc()))
-                .load::<Occupancy>(&conn)?
-        } else {
+        diesel::delete(dsl::occupancies.find(existing))
+            .execute(&conn)
+            .unwrap();
+        let booked = waitlist::book_waiting_users(&conn, &room, &settings).unwrap();
+        assert_eq!(1, booked.len());
+        assert_eq!("first", booked[0].user_id);
+        assert_eq!(
+            1,
+            notifications::find_for_user(&conn, "first").unwrap().len()
+        );
+        assert!(notifications::find_for_user(&conn, "second")
+            .unwrap()
+            .is_empty());
+
+        let waiting: i64 = crate::schema::waitlist::dsl::waitlist
+            .count()
+            .get_result(&conn)
+            .unwrap();
+        assert_eq!(1, waiting);
+    }
+
+    #[test]
+    fn occupancies_without_checkin_are_released() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let settings = Settings::default();
+
+        let now = Utc::now();
+        let no_show = add(
+            &conn,
+            "large",
+            now - Duration::hours(1),
+            now + Duration::hours(1),
+        );
+        let present = add(
+            &conn,
+            "large",
+            now - Duration::hours(1),
+            now + Duration::hours(1),
+        );
+        let within_grace = add(
+            &conn,
+            "small",
+            now - Duration::minutes(5),
+            now + Duration::hours(1),
+        );
+        let booked_late = add(
+            &conn,
+            "small",
+            now - Duration::hours(1),
+            now + Duration::hours(1),
+        );
+
+        use crate::schema::audit_log;
+        use crate::schema::occupancies::dsl;
+        diesel::update(dsl::occupancies.find(present))
+            .set(dsl::checked_in_at.eq(now.naive_utc()))
+            .execute(&conn)
+            .unwrap();
+        // All occupancies except the last one have been booked well in advance
+        diesel::update(
+            audit_log::dsl::audit_log.filter(audit_log::dsl::occupancy_id.ne(booked_late)),
+        )
+        .set(audit_log::dsl::changed_at.eq((now - Duration::days(1)).naive_utc()))
+        .execute(&conn)
+        .unwrap();
+
+        let released = crate::checkin::release_no_shows(&conn, &settings, 15).unwrap();
+        assert_eq!(1, released.len());
+        assert_eq!(no_show, released[0].id);
+
+        let remaining: Vec<i32> = dsl::occupancies
+            .select(dsl::id)
+            .order(dsl::id)
+            .load(&conn)
+            .unwrap();
+        assert_eq!(vec![present, within_grace, booked_late], remaining);
+        assert_eq!(
+            1,
+            notifications::find_for_user(&conn, "someone")
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn checkins_are_recorded_in_audit_log() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let now = Utc::now();
+        let id = add(
+            &conn,
+            "small",
+            now - Duration::minutes(10),
+            now + Duration::hours(1),
+        );
+        drop(conn);
+
+        let response = actix_rt::System::new("test")
+            .block_on(checkin_occupancy(
+                web::Path::from(("small".to_string(), id)),
+                web::Query(CheckinParams { token: None }),
+                web::Data::new(db_pool.clone()),
+                web::Data::new(Settings::default()),
+                ClaimsFromAuth(claims_of("someone", Role::User)),
+            ))
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+
+        let conn = db_pool.get().unwrap();
+        let occupancy: Occupancy = crate::schema::occupancies::dsl::occupancies
+            .find(id)
+            .first(&conn)
+            .unwrap();
+        let checked_in_at = occupancy.checked_in_at.unwrap();
+        let entries = audit::find_entries(&conn, &AuditFilter::default()).unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!(audit::ACTION_CHECKIN, entries[1].action);
+        assert_eq!("someone", entries[1].actor);
+        assert_eq!(checked_in_at, entries[1].changed_at);
+        // The planned times are not changed by the check-in
+        assert_eq!(Some(occupancy.start), entries[1].old_start);
+        assert_eq!(Some(occupancy.start), entries[1].new_start);
+        assert_eq!(Some(occupancy.end), entries[1].new_end);
+
+        // The check-in is announced like a change with the actual start
+        assert_eq!(
+            1,
+            notifications::find_for_user(&conn, "someone")
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn rooms_are_described_with_their_own_rules_and_days() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let future_day = NaiveDate::from_ymd(2100, 1, 1);
+        diesel::insert_into(crate::schema::capacity_rules::table)
+            .values(&NewCapacityRule {
+                room: "small".to_string(),
+                max_occupancy: 0,
+                valid_from: None,
+                valid_to: None,
+                weekdays: String::default(),
+                from_time: None,
+                to_time: None,
+            })
+            .execute(&conn)
+            .unwrap();
+        diesel::insert_into(crate::schema::closures::table)
+            .values(&NewClosure {
+                room: "large".to_string(),
+                day: future_day,
+                reason: None,
+            })
+            .execute(&conn)
+            .unwrap();
+        for room in &[None, Some("small".to_string())] {
+            diesel::insert_into(crate::schema::holidays::table)
+                .values(&crate::models::NewHoliday {
+                    room: room.clone(),
+                    day: future_day,
+                    name: "Holiday".to_string(),
+                })
+                .execute(&conn)
+                .unwrap();
+        }
+        diesel::insert_into(crate::schema::buildings::table)
+            .values(&Building {
+                id: "main".to_string(),
+                name: "Main".to_string(),
+                max_occupancy: None,
+            })
+            .execute(&conn)
+            .unwrap();
+        diesel::insert_into(crate::schema::floors::table)
+            .values(&NewFloor {
+                building: "main".to_string(),
+                name: "Ground floor".to_string(),
+                level: 0,
+            })
+            .execute(&conn)
+            .unwrap();
+        let floor_id: i32 = crate::schema::floors::dsl::floors
+            .select(crate::schema::floors::dsl::id)
+            .first(&conn)
+            .unwrap();
+        diesel::update(crate::schema::rooms::dsl::rooms.find("large"))
+            .set(crate::schema::rooms::dsl::floor_id.eq(floor_id))
+            .execute(&conn)
+            .unwrap();
+        drop(conn);
+
+        let response = actix_rt::System::new("test")
+            .block_on(all_rooms(
+                web::Query(RoomFilter {
+                    building: None,
+                    floor: None,
+                }),
+                web::Data::new(db_pool.clone()),
+                ClaimsFromAuth(claims_of("someone", Role::User)),
+            ))
+            .unwrap();
+        let rooms = json_body(&response);
+        let (large, small) = (&rooms[0], &rooms[1]);
+        assert_eq!("large", large["id"]);
+        assert_eq!(2, large["current_max_occupancy"]);
+        assert_eq!(0, large["capacity_rules"].as_array().unwrap().len());
+        assert_eq!(1, large["closures"].as_array().unwrap().len());
+        assert_eq!(1, large["holidays"].as_array().unwrap().len());
+        assert_eq!("main", large["building"]);
+        assert_eq!("small", small["id"]);
+        assert_eq!(0, small["current_max_occupancy"]);
+        assert_eq!(1, small["capacity_rules"].as_array().unwrap().len());
+        assert_eq!(0, small["closures"].as_array().unwrap().len());
+        assert_eq!(2, small["holidays"].as_array().unwrap().len());
+        assert!(small["building"].is_null());
+    }
+
+    #[test]
+    fn checked_out_occupancies_free_capacity() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let room = get_room(&conn, "small");
+
+        let id = add(&conn, "small", time(10, 0), time(12, 0));
+        assert!(!check_if_room_available(&conn, &room, time(11, 0), time(12, 0), None).unwrap());
+
+        use crate::schema::occupancies::dsl;
+        diesel::update(dsl::occupancies.find(id))
+            .set(dsl::checked_out_at.eq(time(11, 0).naive_utc()))
+            .execute(&conn)
+            .unwrap();
+        assert!(!check_if_room_available(&conn, &room, time(10, 0), time(11, 0), None).unwrap());
+        assert!(check_if_room_available(&conn, &room, time(11, 0), time(12, 0), None).unwrap());
+
+        // Contacts are traced with the actual times
+        let other = NewOccupancy {
+            room: "small".to_string(),
+            user_id: "other".to_string(),
+            user_name: "Other".to_string(),
+            user_contact: "other@example.com".to_string(),
+            start: time(10, 30).naive_utc(),
+            end: time(12, 0).naive_utc(),
+            series_id: None,
+            seat_id: None,
+        };
+        insert_occupancy(&conn, &other, "other").unwrap();
+        let contacts = crate::export::find_contacts(
+            &conn,
+            "someone",
+            time(0, 0).naive_utc(),
+            time(23, 0).naive_utc(),
+        )
+        .unwrap();
+        assert_eq!(1, contacts.len());
+        assert_eq!("other", contacts[0].user_id);
+        assert_eq!(time(11, 0).naive_utc(), contacts[0].end);
+        assert_eq!(30, contacts[0].overlap_minutes);
+
+        // Overlaps are limited to the time window
+        let contacts = crate::export::find_contacts(
+            &conn,
+            "someone",
+            time(0, 0).naive_utc(),
+            time(10, 45).naive_utc(),
+        )
+        .unwrap();
+        assert_eq!(1, contacts.len());
+        assert_eq!(time(10, 45).naive_utc(), contacts[0].end);
+        assert_eq!(15, contacts[0].overlap_minutes);
+
+        // Users that checked in long before their planned start are found as well
+        let early = NewOccupancy {
+            user_id: "early".to_string(),
+            start: time(14, 0).naive_utc(),
+            end: time(15, 0).naive_utc(),
+            ..other
+        };
+        let early_id = insert_occupancy(&conn, &early, "early").unwrap().id;
+        diesel::update(dsl::occupancies.find(early_id))
+            .set(dsl::checked_in_at.eq(time(10, 50).naive_utc()))
+            .execute(&conn)
+            .unwrap();
+        let contacts = crate::export::find_contacts(
+            &conn,
+            "someone",
+            time(0, 0).naive_utc(),
+            time(23, 0).naive_utc(),
+        )
+        .unwrap();
+        assert_eq!(2, contacts.len());
+        assert_eq!("early", contacts[1].user_id);
+        assert_eq!(10, contacts[1].overlap_minutes);
+
+        // The days of the window are taken in the given timezone
+        let tz: chrono_tz::Tz = "Europe/Berlin".parse().unwrap();
+        let day = NaiveDate::from_ymd(2020, 7, 8);
+        assert_eq!(
+            (time(0, 0).naive_utc() - Duration::hours(2), time(22, 0).naive_utc()),
+            crate::export::day_window(&tz, day, day)
+        );
+    }
+
+    #[test]
+    fn checkouts_are_recorded_and_announced() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let mut settings = Settings::default();
+        settings.webhooks.endpoints = vec![crate::config::WebhookEndpoint {
+            url: "http://localhost/hook".to_string(),
+            secret: "secret".to_string(),
+            events: vec!["occupancy.updated".to_string()],
+        }];
+        let now = Utc::now();
+        let id = add(
+            &conn,
+            "small",
+            now - Duration::hours(1),
+            now + Duration::hours(1),
+        );
+        let booked: Occupancy = crate::schema::occupancies::dsl::occupancies
+            .find(id)
+            .first(&conn)
+            .unwrap();
+        drop(conn);
+
+        let response = actix_rt::System::new("test")
+            .block_on(checkout_occupancy(
+                web::Path::from(("small".to_string(), id)),
+                web::Data::new(db_pool.clone()),
+                web::Data::new(settings.clone()),
+                ClaimsFromAuth(claims_of("someone", Role::User)),
+            ))
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+
+        let conn = db_pool.get().unwrap();
+        let checked_out_at = crate::schema::occupancies::dsl::occupancies
+            .find(id)
+            .select(crate::schema::occupancies::dsl::checked_out_at)
+            .first::<Option<NaiveDateTime>>(&conn)
+            .unwrap()
+            .unwrap();
+        let entries = audit::find_entries(&conn, &AuditFilter::default()).unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!(audit::ACTION_CHECKOUT, entries[1].action);
+        assert_eq!(checked_out_at, entries[1].changed_at);
+        // The planned times are not changed by the check-out
+        assert_eq!(Some(booked.end), entries[1].old_end);
+        assert_eq!(Some(booked.end), entries[1].new_end);
+
+        let deliveries: Vec<crate::models::WebhookDelivery> =
+            crate::schema::webhook_deliveries::dsl::webhook_deliveries
+                .load(&conn)
+                .unwrap();
+        assert_eq!(1, deliveries.len());
+        assert_eq!("occupancy.updated", deliveries[0].event);
+        let payload: serde_json::Value = serde_json::from_str(&deliveries[0].payload).unwrap();
+        let format = |t: NaiveDateTime| serde_json::to_value(t).unwrap();
+        assert_eq!(format(checked_out_at), payload["occupancy"]["end"]);
+        assert_eq!(format(booked.end), payload["previous"]["end"]);
+        assert_eq!(
+            1,
+            notifications::find_for_user(&conn, "someone")
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn seats_are_booked_exclusively() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let large = get_room(&conn, "large");
+        let small = get_room(&conn, "small");
+
+        let mut seat_ids = Vec::new();
+        for label in &["Window", "Door"] {
+            let properties = SeatProperties {
+                label: label.to_string(),
+                attributes: vec!["docking station".to_string()],
+            };
+            diesel::insert_into(crate::schema::seats::table)
+                .values(properties.parse("large").unwrap())
+                .execute(&conn)
+                .unwrap();
+            seat_ids.push(diesel::select(last_insert_rowid).get_result(&conn).unwrap());
+        }
+        let (window, door) = (seat_ids[0], seat_ids[1]);
+
+        let booked = NewOccupancy {
+            room: "large".to_string(),
+            user_id: "someone".to_string(),
+            user_name: "Someone".to_string(),
+            user_contact: "someone@example.com".to_string(),
+            start: time(10, 0).naive_utc(),
+            end: time(12, 0).naive_utc(),
+            series_id: None,
+            seat_id: Some(window),
+        };
+        let id = insert_occupancy(&conn, &booked, "someone").unwrap().id;
+
+        let available = |seat, start: DateTime<Utc>, end: DateTime<Utc>| {
+            let (start, end) = (start.naive_utc(), end.naive_utc());
+            seats::check_if_seat_available(&conn, &large, seat, start, end, None).unwrap()
+        };
+        // The room has capacity for a second person, but not on the same seat
+        assert!(check_if_room_available(&conn, &large, time(11, 0), time(13, 0), None).unwrap());
+        assert!(!available(window, time(11, 0), time(13, 0)));
+        assert!(available(door, time(11, 0), time(13, 0)));
+        assert!(available(window, time(12, 0), time(13, 0)));
+        assert!(seats::check_if_seat_available(
+            &conn,
+            &large,
+            window,
+            time(11, 0).naive_utc(),
+            time(13, 0).naive_utc(),
+            Some(id)
+        )
+        .unwrap());
+        // Seats of other rooms can't be booked
+        assert!(seats::check_if_seat_available(
+            &conn,
+            &small,
+            door,
+            time(11, 0).naive_utc(),
+            time(13, 0).naive_utc(),
+            None
+        )
+        .is_err());
+
+        // The seat is free again after checking out
+        use crate::schema::occupancies::dsl;
+        diesel::update(dsl::occupancies.find(id))
+            .set(dsl::checked_out_at.eq(time(11, 0).naive_utc()))
+            .execute(&conn)
+            .unwrap();
+        assert!(available(window, time(11, 0), time(13, 0)));
+
+        let seats: Vec<SeatDescription> = seats::find_seats(&conn, "large")
+            .unwrap()
+            .into_iter()
+            .map(SeatDescription::from)
+            .collect();
+        assert_eq!(
+            vec!["Door", "Window"],
+            seats.iter().map(|s| s.label.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["docking station".to_string()], seats[0].attributes);
+    }
+
+    #[test]
+    fn building_capacity_is_enforced() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        diesel::insert_into(crate::schema::buildings::table)
+            .values(&Building {
+                id: "main".to_string(),
+                name: "Main building".to_string(),
+                max_occupancy: Some(2),
+            })
+            .execute(&conn)
+            .unwrap();
+        diesel::insert_into(crate::schema::floors::table)
+            .values(NewFloor {
+                building: "main".to_string(),
+                name: "Ground floor".to_string(),
+                level: 0,
+            })
+            .execute(&conn)
+            .unwrap();
+        let floor: i32 = diesel::select(last_insert_rowid).get_result(&conn).unwrap();
+        {
+            use crate::schema::rooms::dsl;
+            diesel::update(dsl::rooms)
+                .set(dsl::floor_id.eq(floor))
+                .execute(&conn)
+                .unwrap();
+        }
+        assert_eq!(
+            vec!["large".to_string(), "small".to_string()],
+            crate::buildings::find_rooms_of_building(&conn, "main").unwrap()
+        );
+
+        add(&conn, "small", time(10, 0), time(12, 0));
+        add(&conn, "large", time(11, 0), time(12, 0));
+        let large = get_room(&conn, "large");
+        // The room itself has space for another person, but the building is full
+        assert!(!check_if_room_available(&conn, &large, time(11, 0), time(12, 0), None).unwrap());
+        assert!(check_if_room_available(&conn, &large, time(10, 0), time(11, 0), None).unwrap());
+
+        use crate::schema::buildings::dsl;
+        diesel::update(dsl::buildings)
+            .set(dsl::max_occupancy.eq(None::<i32>))
+            .execute(&conn)
+            .unwrap();
+        assert!(check_if_room_available(&conn, &large, time(11, 0), time(12, 0), None).unwrap());
+    }
+
+    #[test]
+    fn room_calendar_load_is_counted_in_local_hours() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        add(&conn, "large", time(10, 0), time(11, 0));
+        add(&conn, "large", time(10, 45), time(11, 15));
+        let occupancies: Vec<Occupancy> = crate::schema::occupancies::dsl::occupancies
+            .load(&conn)
+            .unwrap();
+
+        let utc = count_load_per_hour(&occupancies, chrono_tz::UTC);
+        assert_eq!(
+            vec![(time(10, 0).naive_utc(), 2), (time(11, 0).naive_utc(), 1)],
+            utc.into_iter().collect::<Vec<_>>()
+        );
+        // India has an offset of 5:30 hours to UTC
+        let india = count_load_per_hour(&occupancies, chrono_tz::Asia::Kolkata);
+        assert_eq!(
+            vec![(time(9, 30).naive_utc(), 1), (time(10, 30).naive_utc(), 2)],
+            india.into_iter().collect::<Vec<_>>()
+        );
+
+        // Back-to-back occupancies do not overlap and the time after a check-out is free
+        use crate::schema::occupancies::dsl;
+        add(&conn, "small", time(12, 0), time(12, 30));
+        add(&conn, "small", time(12, 30), time(13, 0));
+        let checked_out = add(&conn, "small", time(13, 0), time(15, 0));
+        diesel::update(dsl::occupancies.find(checked_out))
+            .set(dsl::checked_out_at.eq(time(13, 30).naive_utc()))
+            .execute(&conn)
+            .unwrap();
+        let occupancies: Vec<Occupancy> = dsl::occupancies
+            .filter(dsl::room.eq("small"))
+            .load(&conn)
+            .unwrap();
+        assert_eq!(
+            vec![(time(12, 0).naive_utc(), 1), (time(13, 0).naive_utc(), 1)],
+            count_load_per_hour(&occupancies, chrono_tz::UTC)
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn room_calendar_uses_current_role() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        let token = CalendarToken {
+            token: "token".to_string(),
+            user_id: "manager".to_string(),
+            created_at: time(10, 0).naive_utc(),
+            role: Role::RoomManager.to_string(),
+        };
+        // LDAP users keep the role of their last login
+        assert_eq!(
+            Role::RoomManager,
+            current_role_of_token(&conn, &token).unwrap()
+        );
+
+        diesel::insert_into(crate::schema::users::table)
+            .values(&User {
+                id: "manager".to_string(),
+                display_name: "Manager".to_string(),
+                contact_info: "manager@example.com".to_string(),
+                password_hash: Some(String::default()),
+                role: Role::User.to_string(),
+            })
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(Role::User, current_role_of_token(&conn, &token).unwrap());
+    }
+
+    #[test]
+    fn user_calendar_is_in_room_timezone_and_revocable() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        diesel::update(crate::schema::rooms::dsl::rooms.find("small"))
+            .set(crate::schema::rooms::dsl::timezone.eq(Some("Europe/Berlin")))
+            .execute(&conn)
+            .unwrap();
+        add(&conn, "small", time(10, 0), time(11, 0));
+        drop(conn);
+
+        let mut system = actix_rt::System::new("test");
+        let token = system
+            .block_on(create_calendar_token(
+                web::Data::new(db_pool.clone()),
+                ClaimsFromAuth(claims_of("someone", Role::User)),
+            ))
+            .unwrap();
+        let token = match token.body().as_ref() {
+            Some(actix_web::dev::Body::Bytes(bytes)) => String::from_utf8(bytes.to_vec()).unwrap(),
+            _ => panic!("Response has no token"),
+        };
+        let mut get_calendar = || {
+            system
+                .block_on(user_calendar(
+                    web::Query(CalendarParams {
+                        token: token.clone(),
+                    }),
+                    web::Data::new(db_pool.clone()),
+                ))
+                .unwrap()
+        };
+
+        let calendar = get_calendar();
+        assert_eq!(200, calendar.status().as_u16());
+        let calendar = match calendar.body().as_ref() {
+            Some(actix_web::dev::Body::Bytes(bytes)) => String::from_utf8(bytes.to_vec()).unwrap(),
+            _ => panic!("Response has no calendar"),
+        };
+        assert!(calendar.contains("\r\nDTSTART;TZID=Europe/Berlin:20200708T120000\r\n"));
+        assert!(calendar.contains("\r\nTZID:Europe/Berlin\r\n"));
+
+        actix_rt::System::new("test")
+            .block_on(delete_calendar_token(
+                web::Data::new(db_pool.clone()),
+                ClaimsFromAuth(claims_of("someone", Role::User)),
+            ))
+            .unwrap();
+        assert_eq!(401, get_calendar().status().as_u16());
+    }
+
+    #[test]
+    fn omitted_room_properties_are_kept() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        use crate::schema::rooms::dsl;
+        diesel::update(dsl::rooms.filter(dsl::id.eq("large")))
+            .set((
+                dsl::timezone.eq("Europe/Berlin"),
+                dsl::max_days_ahead.eq(14),
+            ))
+            .execute(&conn)
+            .unwrap();
+
+        let properties: RoomProperties =
+            serde_json::from_str(r#"{"max_occupancy": 3, "max_days_ahead": null}"#).unwrap();
+        assert!(properties.timezone.is_none());
+        assert_eq!(Some(None), properties.max_days_ahead);
+        diesel::update(dsl::rooms.filter(dsl::id.eq("large")))
+            .set(&properties.changes().unwrap())
+            .execute(&conn)
+            .unwrap();
+
+        let large = get_room(&conn, "large");
+        assert_eq!(3, large.max_occupancy);
+        assert_eq!(Some("Europe/Berlin".to_string()), large.timezone);
+        assert_eq!(None, large.max_days_ahead);
+    }
+
+    fn claims_of(user_id: &str, role: Role) -> Claims {
+        Claims {
+            sub: user_id.to_string(),
+            name: user_id.to_uppercase(),
+            contact_info: format!("{}@example.com", user_id),
+            role,
+            exp: None,
+        }
+    }
+
+    #[test]
+    fn roles_are_derived_from_ldap_groups() {
+        let mut settings = Settings::default();
+        settings.ldap.room_manager_groups = vec!["managers".to_string(), "staff".to_string()];
+        settings.ldap.admin_groups = vec!["admins".to_string()];
+        let role = |groups: &[&str]| {
+            let groups: Vec<String> = groups.iter().map(|g| g.to_string()).collect();
+            role_from_ldap_groups(&groups, &settings)
+        };
+
+        assert_eq!(Role::User, role(&[]));
+        assert_eq!(Role::User, role(&["students", "Managers"]));
+        assert_eq!(Role::RoomManager, role(&["students", "staff"]));
+        assert_eq!(Role::Admin, role(&["admins"]));
+        // The role with the most privileges is used
+        assert_eq!(Role::Admin, role(&["managers", "admins"]));
+
+        // Without configured groups, all LDAP users are normal users
+        let settings = Settings::default();
+        assert_eq!(
+            Role::User,
+            role_from_ldap_groups(&["admins".to_string()], &settings)
+        );
+    }
+
+    #[test]
+    fn only_room_managers_change_occupancies_of_other_users() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let id = add(&conn, "small", time(10, 0), time(12, 0));
+
+        let owner = claims_of("someone", Role::User);
+        let other = claims_of("other", Role::User);
+        let manager = claims_of("manager", Role::RoomManager);
+        assert_eq!(
+            Some(id),
+            get_changeable_occupancy(&conn, "small", id, &owner)
+                .unwrap()
+                .map(|o| o.id)
+        );
+        assert!(matches!(
+            get_changeable_occupancy(&conn, "small", id, &other),
+            Err(ServiceError::Forbidden(_))
+        ));
+        assert_eq!(
+            Some(id),
+            get_changeable_occupancy(&conn, "small", id, &manager)
+                .unwrap()
+                .map(|o| o.id)
+        );
+        // The occupancy must be in the given room
+        assert!(get_changeable_occupancy(&conn, "large", id, &manager)
+            .unwrap()
+            .is_none());
+        drop(conn);
+
+        let mut system = actix_rt::System::new("test");
+        let mut delete = |id: i32, claims: &Claims| {
+            system.block_on(delete_occupancy(
+                web::Path::from(("small".to_string(), id)),
+                web::Data::new(db_pool.clone()),
+                web::Data::new(Settings::default()),
+                ClaimsFromAuth(claims.clone()),
+            ))
+        };
+        assert!(matches!(
+            delete(id, &other),
+            Err(ServiceError::Forbidden(_))
+        ));
+        assert_eq!(404, delete(id + 1, &manager).unwrap().status().as_u16());
+        assert_eq!(200, delete(id, &manager).unwrap().status().as_u16());
+        assert_eq!(404, delete(id, &owner).unwrap().status().as_u16());
+
+        // The deletion is recorded for the room manager
+        let filter = AuditFilter {
+            actor: Some("manager".to_string()),
+            ..Default::default()
+        };
+        let entries = audit::find_entries(&db_pool.get().unwrap(), &filter).unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(audit::ACTION_DELETE, entries[0].action);
+        assert_eq!("someone", entries[0].user_id);
+    }
+
+    #[test]
+    fn rooms_with_unpurged_occupancies_are_not_deleted() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let now = Utc::now();
+        let past_start = now - Duration::days(2);
+        add(&conn, "small", past_start, past_start + Duration::hours(1));
+        let future = add(
+            &conn,
+            "small",
+            now + Duration::days(1),
+            now + Duration::days(1) + Duration::hours(1),
+        );
+        // The test pool only has a single connection
+        drop(conn);
+
+        let mut system = actix_rt::System::new("test");
+        let mut delete = |room: &str| {
+            let path = web::Path::from(room.to_string());
+            let db_pool = web::Data::new(db_pool.clone());
+            let claims = AdminFromAuth(claims_of("admin", Role::Admin));
+            system
+                .block_on(delete_room(path, db_pool, claims))
+                .unwrap()
+                .status()
+                .as_u16()
+        };
+        assert_eq!(404, delete("unknown"));
+        assert_eq!(409, delete("small"));
+
+        use crate::schema::occupancies::dsl;
+        diesel::delete(dsl::occupancies.find(future))
+            .execute(&db_pool.get().unwrap())
+            .unwrap();
+        // The past occupancy is still needed for contact tracing
+        assert_eq!(409, delete("small"));
+
+        let mut settings = Settings::default();
+        settings.retention.mode = crate::config::RetentionMode::Anonymize;
+        retention::purge(&db_pool.get().unwrap(), 1, &settings).unwrap();
+        assert_eq!(200, delete("small"));
+
+        let conn = db_pool.get().unwrap();
+        let rooms: Vec<String> = crate::schema::rooms::dsl::rooms
+            .select(crate::schema::rooms::dsl::id)
+            .load(&conn)
+            .unwrap();
+        assert_eq!(vec!["large"], rooms);
+        let remaining: i64 = dsl::occupancies.count().get_result(&conn).unwrap();
+        assert_eq!(0, remaining);
+    }
+
+    fn json_body(response: &HttpResponse) -> serde_json::Value {
+        match response.body().as_ref() {
+            Some(actix_web::dev::Body::Bytes(bytes)) => serde_json::from_slice(bytes).unwrap(),
+            _ => panic!("Response has no JSON body"),
+        }
+    }
+
+    #[test]
+    fn occupancies_are_updated_in_the_timezone_of_the_room() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        diesel::update(crate::schema::rooms::dsl::rooms.find("small"))
+            .set(crate::schema::rooms::dsl::timezone.eq(Some("Europe/Berlin")))
+            .execute(&conn)
+            .unwrap();
+        let id = add(&conn, "small", time(6, 0), time(7, 0));
+        drop(conn);
+
+        // 08:00 to 13:00 in Berlin is on the same day, but not with the offset of the client
+        let response = actix_rt::System::new("test")
+            .block_on(update_occupancy(
+                web::Path::from(("small".to_string(), id)),
+                web::Json(TimeRange {
+                    start: "2020-07-07T20:00:00-10:00".to_string(),
+                    end: "2020-07-08T01:00:00-10:00".to_string(),
+                }),
+                web::Data::new(db_pool.clone()),
+                web::Data::new(Settings::default()),
+                ClaimsFromAuth(claims_of("someone", Role::User)),
+            ))
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+
+        let conn = db_pool.get().unwrap();
+        let updated: Occupancy = crate::schema::occupancies::dsl::occupancies
+            .find(id)
+            .first(&conn)
+            .unwrap();
+        assert_eq!(time(6, 0).naive_utc(), updated.start);
+        assert_eq!(time(11, 0).naive_utc(), updated.end);
+    }
+
+    #[test]
+    fn series_keep_their_local_time_of_day() {
+        let tz: chrono_tz::Tz = "Europe/Berlin".parse().unwrap();
+        let at = |day: u32, hour: u32| NaiveDate::from_ymd(2020, 10, day).and_hms(hour, 0, 0);
+        // Daylight saving time ends on Sunday, 2020-10-25
+        let first_start = tz.ymd(2020, 10, 19).and_hms(10, 0, 0);
+        let first_end = tz.ymd(2020, 10, 19).and_hms(11, 0, 0);
+        let weekdays = [Weekday::Mon, Weekday::Wed];
+
+        let instances: Vec<_> = expand_series(
+            first_start,
+            first_end,
+            &weekdays,
+            NaiveDate::from_ymd(2020, 10, 28),
+        )
+        .into_iter()
+        .map(|(start, end)| (start.naive_utc(), end.naive_utc()))
+        .collect();
+        assert_eq!(
+            vec![
+                (at(19, 8), at(19, 9)),
+                (at(21, 8), at(21, 9)),
+                (at(26, 9), at(26, 10)),
+                (at(28, 9), at(28, 10)),
+            ],
+            instances
+        );
+
+        // The last day is included, but not the days after it
+        let until_tuesday = expand_series(
+            first_start,
+            first_end,
+            &weekdays,
+            NaiveDate::from_ymd(2020, 10, 27),
+        );
+        assert_eq!(3, until_tuesday.len());
+        let until_first_day = expand_series(
+            first_start,
+            first_end,
+            &weekdays,
+            NaiveDate::from_ymd(2020, 10, 19),
+        );
+        assert_eq!(1, until_first_day.len());
+    }
+
+    #[test]
+    fn series_report_conflicts_per_instance() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let mut settings = Settings::default();
+        settings.booking.max_future_bookings = Some(3);
+
+        let today = Utc::today().naive_utc();
+        let monday = today + Duration::days(7 - today.weekday().num_days_from_monday() as i64);
+        let at = |day: i64, hour: u32| {
+            Utc.from_utc_datetime(&(monday + Duration::days(day)).and_hms(hour, 0, 0))
+        };
+        // The room is already full on the first Wednesday
+        add(&conn, "small", at(2, 9), at(2, 12));
+        drop(conn);
+
+        let mut system = actix_rt::System::new("test");
+        let request = SeriesRequest {
+            start: at(0, 10).to_rfc3339(),
+            end: at(0, 11).to_rfc3339(),
+            weekdays: vec!["we".to_string(), "MO".to_string(), "WE".to_string()],
+            until: (monday + Duration::days(16)).format("%Y-%m-%d").to_string(),
+        };
+        let response = system
+            .block_on(add_series(
+                web::Json(request),
+                web::Path::from("small".to_string()),
+                web::Data::new(db_pool.clone()),
+                web::Data::new(settings.clone()),
+                ClaimsFromAuth(claims_of("alice", Role::User)),
+            ))
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+        let result = json_body(&response);
+        assert_eq!("MO,WE", result["series"]["weekdays"]);
+        let series_id = result["series"]["id"].as_i64().unwrap() as i32;
+
+        // The instance in the full room and the ones exceeding the quota are reported
+        let conflicts: Vec<&str> = result["conflicts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["start"].as_str().unwrap())
+            .collect();
+        let expected_conflicts = vec![
+            at(2, 10).to_rfc3339(),
+            at(14, 10).to_rfc3339(),
+            at(16, 10).to_rfc3339(),
+        ];
+        assert_eq!(expected_conflicts, conflicts);
+
+        use crate::schema::occupancies::dsl;
+        let instances = || -> Vec<Occupancy> {
             dsl::occupancies
-                .filter(dsl::room.eq(&room.id))
-                .load::<Occupancy>(&conn)?
+                .filter(dsl::user_id.eq("alice"))
+                .order(dsl::start)
+                .load(&db_pool.get().unwrap())
+                .unwrap()
         };
+        let created = instances();
+        assert_eq!(
+            vec![at(0, 10), at(7, 10), at(9, 10)],
+            created
+                .iter()
+                .map(|o| Utc.from_utc_datetime(&o.start))
+                .collect::<Vec<_>>()
+        );
+        assert!(created.iter().all(|o| o.series_id == Some(series_id)));
 
-        // anonymize all occupancy entries for other users
-        for mut o in result.iter_mut() {
-            if o.user_id != claims.0.sub {
-                o.user_id = "<anonym>".to_string();
-                o.user_contact = "".to_string();
-                o.user_name = "<anonym>".to_string();
-            }
+        // Single instances can be cancelled like any other occupancy
+        let response = system
+            .block_on(delete_occupancy(
+                web::Path::from(("small".to_string(), created[1].id)),
+                web::Data::new(db_pool.clone()),
+                web::Data::new(settings.clone()),
+                ClaimsFromAuth(claims_of("alice", Role::User)),
+            ))
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+        assert_eq!(2, instances().len());
+
+        // Cancelling the whole series keeps the instances that have already started
+        let started = at(0, 10) - Duration::days(14);
+        diesel::update(dsl::occupancies.find(created[0].id))
+            .set((
+                dsl::start.eq(started.naive_utc()),
+                dsl::end.eq((started + Duration::hours(1)).naive_utc()),
+            ))
+            .execute(&db_pool.get().unwrap())
+            .unwrap();
+        let response = system
+            .block_on(delete_series(
+                web::Path::from(("small".to_string(), series_id)),
+                web::Data::new(db_pool.clone()),
+                web::Data::new(settings.clone()),
+                ClaimsFromAuth(claims_of("alice", Role::User)),
+            ))
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+        let remaining = instances();
+        assert_eq!(1, remaining.len());
+        assert_eq!(created[0].id, remaining[0].id);
+        assert_eq!(None, remaining[0].series_id);
+        let series: i64 = crate::schema::series::dsl::series
+            .count()
+            .get_result(&db_pool.get().unwrap())
+            .unwrap();
+        assert_eq!(0, series);
+    }
+
+    #[test]
+    fn capacity_rules_limit_occupancy() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        // The test day is a Wednesday
+        let rules = vec![
+            CapacityRuleProperties {
+                max_occupancy: 1,
+                valid_from: Some(NaiveDate::from_ymd(2020, 7, 1)),
+                valid_to: Some(NaiveDate::from_ymd(2020, 7, 31)),
+                weekdays: vec!["we".to_string(), "TH".to_string()],
+                from_time: Some("11:00".to_string()),
+                to_time: Some("13:00".to_string()),
+            },
+            // Evenings until midnight, the room "large" has a maximum occupancy of 2
+            CapacityRuleProperties {
+                max_occupancy: 1,
+                valid_from: None,
+                valid_to: None,
+                weekdays: vec![],
+                from_time: Some("20:00".to_string()),
+                to_time: Some("24:00".to_string()),
+            },
+            // Already expired
+            CapacityRuleProperties {
+                max_occupancy: 1,
+                valid_from: None,
+                valid_to: Some(NaiveDate::from_ymd(2020, 6, 30)),
+                weekdays: vec![],
+                from_time: None,
+                to_time: None,
+            },
+        ];
+        for rule in rules {
+            diesel::insert_into(crate::schema::capacity_rules::table)
+                .values(rule.parse("large").unwrap())
+                .execute(&conn)
+                .unwrap();
         }
 
-        Ok(HttpResponse::Ok().json(result))
-    } else {
-        Ok(HttpResponse::NotFound().json("Room not found"))
+        let large = get_room(&conn, "large");
+        assert_eq!(
+            vec![
+                (time(10, 0).naive_utc(), time(11, 0).naive_utc(), 2),
+                (time(11, 0).naive_utc(), time(13, 0).naive_utc(), 1),
+                (time(13, 0).naive_utc(), time(14, 0).naive_utc(), 2),
+            ],
+            capacity::find_limits(
+                &conn,
+                &large,
+                time(10, 0).naive_utc(),
+                time(14, 0).naive_utc()
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            1,
+            capacity::max_occupancy_at(&conn, &large, time(12, 0).naive_utc()).unwrap()
+        );
+        assert_eq!(
+            1,
+            capacity::max_occupancy_at(&conn, &large, time(23, 30).naive_utc()).unwrap()
+        );
+        let midnight = time(23, 0).naive_utc() + Duration::hours(1);
+        assert_eq!(
+            vec![
+                (time(19, 0).naive_utc(), time(20, 0).naive_utc(), 2),
+                (time(20, 0).naive_utc(), midnight, 1),
+                (midnight, midnight + Duration::hours(1), 2),
+            ],
+            capacity::find_limits(
+                &conn,
+                &large,
+                time(19, 0).naive_utc(),
+                midnight + Duration::hours(1)
+            )
+            .unwrap()
+        );
+        let evening = capacity::find_rules(&conn, "large")
+            .unwrap()
+            .into_iter()
+            .find(|r| r.from_time == Some(NaiveTime::from_hms(20, 0, 0)))
+            .unwrap();
+        assert_eq!(
+            Some("24:00".to_string()),
+            CapacityRuleDescription::from(evening).rule.to_time
+        );
+
+        add(&conn, "large", time(10, 0), time(12, 0));
+        assert!(check_if_room_available(&conn, &large, time(10, 0), time(11, 0), None).unwrap());
+        assert!(!check_if_room_available(&conn, &large, time(10, 0), time(12, 0), None).unwrap());
+        assert!(!check_if_room_available(&conn, &large, time(11, 30), time(12, 0), None).unwrap());
+        assert!(check_if_room_available(&conn, &large, time(12, 0), time(13, 0), None).unwrap());
+
+        // Invalid rules are rejected
+        let invalid = CapacityRuleProperties {
+            max_occupancy: 1,
+            valid_from: None,
+            valid_to: None,
+            weekdays: vec![],
+            from_time: Some("13:00".to_string()),
+            to_time: None,
+        };
+        assert!(invalid.parse("large").is_err());
+    }
+
+    #[test]
+    fn rooms_are_searched_by_attributes_and_capacity() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let settings = Settings::default();
+        {
+            use crate::schema::rooms::dsl;
+            let attributes = vec!["Projector".to_string(), "quiet zone".to_string()];
+            diesel::update(dsl::rooms.filter(dsl::id.eq("large")))
+                .set(dsl::attributes.eq(join_attributes(&attributes).unwrap()))
+                .execute(&conn)
+                .unwrap();
+        }
+        let search = |attributes: &[&str], persons| {
+            let attributes: Vec<String> = attributes.iter().map(|a| a.to_string()).collect();
+            let start = time(10, 0).into();
+            let end = time(12, 0).into();
+            find_available_rooms(&conn, &settings, start, end, &attributes, persons)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["large", "small"], search(&[], 1));
+        assert_eq!(vec!["large"], search(&["projector"], 1));
+        assert_eq!(vec!["large"], search(&["projector", "Quiet Zone"], 2));
+        assert!(search(&["projector"], 3).is_empty());
+        assert!(search(&["whiteboard"], 1).is_empty());
+
+        add(&conn, "large", time(11, 0), time(13, 0));
+        add(&conn, "small", time(9, 0), time(10, 30));
+        assert_eq!(vec!["large"], search(&[], 1));
+        assert!(search(&[], 2).is_empty());
+    }
+
+    #[test]
+    fn groups_are_booked_together() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let settings = Settings::default();
+
+        for id in &["alice", "bob"] {
+            diesel::insert_into(crate::schema::users::table)
+                .values(&User {
+                    id: id.to_string(),
+                    display_name: id.to_uppercase(),
+                    contact_info: format!("{}@example.com", id),
+                    password_hash: Some(String::default()),
+                    role: Role::User.to_string(),
+                })
+                .execute(&conn)
+                .unwrap();
+        }
+        let mut claims = Claims {
+            sub: "lead".to_string(),
+            name: "Team Lead".to_string(),
+            contact_info: "lead@example.com".to_string(),
+            role: Role::User,
+            exp: None,
+        };
+        let ids: Vec<String> = ["lead", "alice", "bob", "alice"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        // Only room managers can book for other users
+        assert!(check_can_book_for(&ids[..1], &claims).is_ok());
+        assert!(matches!(
+            check_can_book_for(&ids, &claims),
+            Err(ServiceError::Forbidden(_))
+        ));
+        claims.role = Role::RoomManager;
+        assert!(check_can_book_for(&ids, &claims).is_ok());
+        // The test pool only has a single connection
+        drop(conn);
+        let participants = {
+            let db_pool = db_pool.clone();
+            let claims = claims.clone();
+            let settings = settings.clone();
+            actix_rt::System::new("test").block_on(async move {
+                resolve_participants(&ids, &claims, &db_pool, &settings).await
+            })
+        }
+        .unwrap();
+        assert_eq!(
+            vec!["lead", "alice", "bob"],
+            participants
+                .iter()
+                .map(|p| p.user_id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("ALICE", participants[1].name);
+        let conn = db_pool.get().unwrap();
+
+        let count = |conn: &SqliteConnection| -> i64 {
+            crate::schema::occupancies::dsl::occupancies
+                .count()
+                .get_result(conn)
+                .unwrap()
+        };
+        let tz = chrono_tz::Tz::UTC;
+        let days = [(
+            time(10, 0).with_timezone(&tz),
+            time(12, 0).with_timezone(&tz),
+        )];
+
+        // Nobody is booked if the whole group does not fit into the room
+        let large = get_room(&conn, "large");
+        let response = add_occupancies_for_days(
+            &conn,
+            &large,
+            &days,
+            None,
+            &participants,
+            &claims,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(409, response.status().as_u16());
+        assert_eq!(0, count(&conn));
+
+        let response = add_occupancies_for_days(
+            &conn,
+            &large,
+            &days,
+            None,
+            &participants[1..],
+            &claims,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(200, response.status().as_u16());
+        let booked: Vec<Occupancy> = crate::schema::occupancies::dsl::occupancies
+            .load(&conn)
+            .unwrap();
+        assert_eq!(
+            vec!["alice", "bob"],
+            booked
+                .iter()
+                .map(|o| o.user_id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("bob@example.com", booked[1].user_contact);
+        // The changes are recorded for the user who booked the group
+        let filter = AuditFilter {
+            actor: Some("lead".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(2, audit::find_entries(&conn, &filter).unwrap().len());
+    }
+
+    /// Start a minimal SMTP server that accepts all mails and collects their content.
+    fn start_fake_smtp_server() -> (u16, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mails = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = mails.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                    let command = line.trim_end().to_uppercase();
+                    if command == "DATA" {
+                        stream.write_all(b"354 Start mail input\r\n").unwrap();
+                        let mut data = String::new();
+                        loop {
+                            let mut data_line = String::new();
+                            if reader.read_line(&mut data_line).unwrap_or(0) == 0
+                                || data_line.trim_end() == "."
+                            {
+                                break;
+                            }
+                            data.push_str(&data_line);
+                        }
+                        received.lock().unwrap().push(data);
+                        stream.write_all(b"250 OK\r\n").unwrap();
+                    } else if command == "QUIT" {
+                        stream.write_all(b"221 Bye\r\n").unwrap();
+                        break;
+                    } else {
+                        stream.write_all(b"250 OK\r\n").unwrap();
+                    }
+                    line.clear();
+                }
+            }
+        });
+        (port, mails)
+    }
+
+    #[test]
+    fn notifications_are_sent_by_mail() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let room = get_room(&conn, "small");
+        let mut settings = Settings::default();
+
+        let id = add(&conn, "small", time(10, 0), time(12, 0));
+        let occupancy: Occupancy = crate::schema::occupancies::dsl::occupancies
+            .find(id)
+            .first(&conn)
+            .unwrap();
+        notifications::notify_occupancy_change(
+            &conn,
+            &room,
+            &settings,
+            OccupancyChange::Created,
+            &[occupancy],
+            "Admin",
+        )
+        .unwrap();
+
+        // Nothing is sent without a mail server
+        assert_eq!(
+            0,
+            notifications::send_pending(&conn, &settings.smtp).unwrap()
+        );
+
+        // A mail server that is not reachable keeps the notification in the queue
+        let unused_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        settings.smtp.host = Some("127.0.0.1".to_string());
+        settings.smtp.port = unused_port;
+        settings.smtp.security = crate::config::SmtpSecurity::None;
+        settings.smtp.from = "roompla@example.com".to_string();
+        settings.smtp.max_attempts = 3;
+        assert_eq!(
+            0,
+            notifications::send_pending(&conn, &settings.smtp).unwrap()
+        );
+        let queued = notifications::find_for_user(&conn, "someone").unwrap();
+        assert_eq!(1, queued[0].attempts);
+        assert!(queued[0].last_error.is_some());
+        assert!(queued[0].sent_at.is_none());
+
+        let (port, mails) = start_fake_smtp_server();
+        settings.smtp.port = port;
+        assert_eq!(
+            1,
+            notifications::send_pending(&conn, &settings.smtp).unwrap()
+        );
+        assert_eq!(
+            0,
+            notifications::send_pending(&conn, &settings.smtp).unwrap()
+        );
+
+        let mails = mails.lock().unwrap();
+        assert_eq!(1, mails.len());
+        assert!(mails[0].contains("To: <someone@example.com>"));
+        assert!(mails[0].contains("Subject: Room small has been booked"));
+        assert!(mails[0].contains("Wed, 2020-07-08 10:00 - 12:00 (UTC)"));
+        assert!(mails[0].contains("by Admin"));
+        let sent = notifications::find_for_user(&conn, "someone").unwrap();
+        assert!(sent[0].sent_at.is_some());
     }
 }
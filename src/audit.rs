@@ -0,0 +1,387 @@
+use crate::{
+    config::Settings,
+    models::{AuditLogEntry, NewAuditLogEntry, Occupancy},
+};
+use anyhow::Result;
+use chrono::{prelude::*, Duration};
+use diesel::{prelude::*, r2d2::ConnectionManager, sqlite::SqliteConnection};
+
+pub const ACTION_CREATE: &str = "create";
+pub const ACTION_UPDATE: &str = "update";
+pub const ACTION_DELETE: &str = "delete";
+pub const ACTION_CHECKIN: &str = "checkin";
+pub const ACTION_CHECKOUT: &str = "checkout";
+
+/// Criteria to select entries of the audit log, all given criteria must match.
+#[derive(Clone, Default)]
+pub struct AuditFilter {
+    /// ID of the user that owns the changed occupancy
+    pub user_id: Option<String>,
+    /// ID of the user that performed the change
+    pub actor: Option<String>,
+    pub room: Option<String>,
+    /// Only include changes after this point in time
+    pub start: Option<NaiveDateTime>,
+    /// Only include changes before this point in time
+    pub end: Option<NaiveDateTime>,
+}
+
+fn insert_entry<Conn>(conn: &Conn, entry: NewAuditLogEntry) -> QueryResult<()>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    diesel::insert_into(crate::schema::audit_log::table)
+        .values(entry)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Record that the given occupancy has been created by the actor.
+pub fn record_create<Conn>(conn: &Conn, actor: &str, occupancy: &Occupancy) -> QueryResult<()>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    insert_entry(
+        conn,
+        NewAuditLogEntry {
+            changed_at: Utc::now().naive_utc(),
+            actor: actor.to_string(),
+            action: ACTION_CREATE.to_string(),
+            occupancy_id: occupancy.id,
+            room: occupancy.room.clone(),
+            user_id: occupancy.user_id.clone(),
+            old_start: None,
+            old_end: None,
+            new_start: Some(occupancy.start),
+            new_end: Some(occupancy.end),
+        },
+    )
+}
+
+/// Record that the time range of the given occupancy has been changed by the actor.
+pub fn record_update<Conn>(
+    conn: &Conn,
+    actor: &str,
+    old: &Occupancy,
+    new_start: NaiveDateTime,
+    new_end: NaiveDateTime,
+) -> QueryResult<()>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    insert_entry(
+        conn,
+        NewAuditLogEntry {
+            changed_at: Utc::now().naive_utc(),
+            actor: actor.to_string(),
+            action: ACTION_UPDATE.to_string(),
+            occupancy_id: old.id,
+            room: old.room.clone(),
+            user_id: old.user_id.clone(),
+            old_start: Some(old.start),
+            old_end: Some(old.end),
+            new_start: Some(new_start),
+            new_end: Some(new_end),
+        },
+    )
+}
+
+/// Record that the given occupancy has been deleted by the actor.
+pub fn record_delete<Conn>(conn: &Conn, actor: &str, old: &Occupancy) -> QueryResult<()>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    insert_entry(
+        conn,
+        NewAuditLogEntry {
+            changed_at: Utc::now().naive_utc(),
+            actor: actor.to_string(),
+            action: ACTION_DELETE.to_string(),
+            occupancy_id: old.id,
+            room: old.room.clone(),
+            user_id: old.user_id.clone(),
+            old_start: Some(old.start),
+            old_end: Some(old.end),
+            new_start: None,
+            new_end: None,
+        },
+    )
+}
+
+/// Record that the user arrived or left at the given time, the planned times are not changed.
+fn record_attendance<Conn>(
+    conn: &Conn,
+    actor: &str,
+    action: &str,
+    occupancy: &Occupancy,
+    time: NaiveDateTime,
+) -> QueryResult<()>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    insert_entry(
+        conn,
+        NewAuditLogEntry {
+            changed_at: time,
+            actor: actor.to_string(),
+            action: action.to_string(),
+            occupancy_id: occupancy.id,
+            room: occupancy.room.clone(),
+            user_id: occupancy.user_id.clone(),
+            old_start: Some(occupancy.start),
+            old_end: Some(occupancy.end),
+            new_start: Some(occupancy.start),
+            new_end: Some(occupancy.end),
+        },
+    )
+}
+
+/// Record that the given occupancy has been checked in at the given time by the actor.
+pub fn record_checkin<Conn>(
+    conn: &Conn,
+    actor: &str,
+    occupancy: &Occupancy,
+    checked_in_at: NaiveDateTime,
+) -> QueryResult<()>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    record_attendance(conn, actor, ACTION_CHECKIN, occupancy, checked_in_at)
+}
+
+/// Record that the given occupancy has been checked out at the given time by the actor.
+pub fn record_checkout<Conn>(
+    conn: &Conn,
+    actor: &str,
+    occupancy: &Occupancy,
+    checked_out_at: NaiveDateTime,
+) -> QueryResult<()>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    record_attendance(conn, actor, ACTION_CHECKOUT, occupancy, checked_out_at)
+}
+
+/// Get all entries of the audit log matching the filter, ordered by the time of the change.
+pub fn find_entries<Conn>(conn: &Conn, filter: &AuditFilter) -> QueryResult<Vec<AuditLogEntry>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::audit_log::dsl;
+
+    let mut query = dsl::audit_log.into_boxed();
+    if let Some(user_id) = &filter.user_id {
+        query = query.filter(dsl::user_id.eq(user_id));
+    }
+    if let Some(actor) = &filter.actor {
+        query = query.filter(dsl::actor.eq(actor));
+    }
+    if let Some(room) = &filter.room {
+        query = query.filter(dsl::room.eq(room));
+    }
+    if let Some(start) = filter.start {
+        query = query.filter(dsl::changed_at.ge(start));
+    }
+    if let Some(end) = filter.end {
+        query = query.filter(dsl::changed_at.le(end));
+    }
+    query.order((dsl::changed_at, dsl::id)).load(conn)
+}
+
+fn format_optional_time(t: Option<NaiveDateTime>) -> String {
+    if let Some(t) = t {
+        DateTime::<Utc>::from_utc(t, Utc).to_rfc3339()
+    } else {
+        "".to_string()
+    }
+}
+
+pub fn to_csv(file: &str, weeks: u8, filter: AuditFilter, settings: Settings) -> Result<()> {
+    info!("Loading database from {}", &settings.database.url);
+    let manager = ConnectionManager::<SqliteConnection>::new(&settings.database.url);
+    let db_pool = r2d2::Pool::builder().build(manager)?;
+
+    let conn = db_pool.get()?;
+
+    let filter = AuditFilter {
+        start: Some((Utc::now() - Duration::weeks(weeks as i64)).naive_utc()),
+        ..filter
+    };
+    let entries = find_entries(&conn, &filter)?;
+
+    let mut writer = csv::Writer::from_path(file)?;
+
+    // Write header
+    writer.write_record(&[
+        "changed_at",
+        "actor",
+        "action",
+        "occupancy_id",
+        "room",
+        "user_id",
+        "old_start",
+        "old_end",
+        "new_start",
+        "new_end",
+    ])?;
+
+    for e in entries {
+        writer.write_record(&[
+            format_optional_time(Some(e.changed_at)),
+            e.actor,
+            e.action,
+            e.occupancy_id.to_string(),
+            e.room,
+            e.user_id,
+            format_optional_time(e.old_start),
+            format_optional_time(e.old_end),
+            format_optional_time(e.new_start),
+            format_optional_time(e.new_end),
+        ])?;
+    }
+    info!("Finished export of audit log to {}", file);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::NewOccupancy;
+    use crate::test_util::create_test_pool;
+
+    fn time(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2020, 7, 22).and_hms(hour, 0, 0)
+    }
+
+    fn add(conn: &SqliteConnection, room: &str, user_id: &str, actor: &str) -> Occupancy {
+        let new_item = NewOccupancy {
+            room: room.to_string(),
+            user_id: user_id.to_string(),
+            user_name: user_id.to_string(),
+            user_contact: format!("{}@example.com", user_id),
+            start: time(10),
+            end: time(12),
+            series_id: None,
+            seat_id: None,
+        };
+        crate::api::insert_occupancy(conn, &new_item, actor).unwrap()
+    }
+
+    #[test]
+    fn changes_are_recorded_with_old_and_new_times() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let occupancy = add(&conn, "small", "someone", "someone");
+        record_update(&conn, "manager", &occupancy, time(11), time(13)).unwrap();
+        record_delete(&conn, "admin", &occupancy).unwrap();
+
+        let entries = find_entries(&conn, &AuditFilter::default()).unwrap();
+        assert_eq!(3, entries.len());
+        assert!(entries.iter().all(|e| e.occupancy_id == occupancy.id
+            && e.room == "small"
+            && e.user_id == "someone"));
+
+        assert_eq!(ACTION_CREATE, entries[0].action);
+        assert_eq!("someone", entries[0].actor);
+        assert_eq!((None, None), (entries[0].old_start, entries[0].old_end));
+        assert_eq!(
+            (Some(time(10)), Some(time(12))),
+            (entries[0].new_start, entries[0].new_end)
+        );
+
+        assert_eq!(ACTION_UPDATE, entries[1].action);
+        assert_eq!("manager", entries[1].actor);
+        assert_eq!(
+            (Some(time(10)), Some(time(12))),
+            (entries[1].old_start, entries[1].old_end)
+        );
+        assert_eq!(
+            (Some(time(11)), Some(time(13))),
+            (entries[1].new_start, entries[1].new_end)
+        );
+
+        assert_eq!(ACTION_DELETE, entries[2].action);
+        assert_eq!("admin", entries[2].actor);
+        assert_eq!(
+            (Some(time(10)), Some(time(12))),
+            (entries[2].old_start, entries[2].old_end)
+        );
+        assert_eq!((None, None), (entries[2].new_start, entries[2].new_end));
+    }
+
+    #[test]
+    fn entries_are_filtered() {
+        use crate::schema::audit_log::dsl;
+
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+        let first = add(&conn, "small", "someone", "someone");
+        let second = add(&conn, "large", "someone", "manager");
+        let third = add(&conn, "large", "other", "other");
+        // Each occupancy has been created on a different day
+        for (occupancy, day) in &[(&first, 1), (&second, 2), (&third, 3)] {
+            diesel::update(dsl::audit_log.filter(dsl::occupancy_id.eq(occupancy.id)))
+                .set(dsl::changed_at.eq(NaiveDate::from_ymd(2020, 7, *day).and_hms(8, 0, 0)))
+                .execute(&conn)
+                .unwrap();
+        }
+
+        let ids = |filter: AuditFilter| -> Vec<i32> {
+            find_entries(&conn, &filter)
+                .unwrap()
+                .iter()
+                .map(|e| e.occupancy_id)
+                .collect()
+        };
+        assert_eq!(
+            vec![first.id, second.id, third.id],
+            ids(AuditFilter::default())
+        );
+        assert_eq!(
+            vec![second.id, third.id],
+            ids(AuditFilter {
+                room: Some("large".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec![first.id, second.id],
+            ids(AuditFilter {
+                user_id: Some("someone".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec![second.id],
+            ids(AuditFilter {
+                actor: Some("manager".to_string()),
+                ..Default::default()
+            })
+        );
+        // The time range includes its start and end
+        assert_eq!(
+            vec![second.id, third.id],
+            ids(AuditFilter {
+                start: Some(NaiveDate::from_ymd(2020, 7, 2).and_hms(8, 0, 0)),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec![first.id, second.id],
+            ids(AuditFilter {
+                end: Some(NaiveDate::from_ymd(2020, 7, 2).and_hms(8, 0, 0)),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec![second.id],
+            ids(AuditFilter {
+                user_id: Some("someone".to_string()),
+                room: Some("large".to_string()),
+                start: Some(NaiveDate::from_ymd(2020, 7, 1).and_hms(12, 0, 0)),
+                end: Some(NaiveDate::from_ymd(2020, 7, 3).and_hms(12, 0, 0)),
+                ..Default::default()
+            })
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{
+    api::max_concurrent_occupancies,
+    models::{Building, Occupancy, Room},
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+/// Get the building the room is in, if it has been assigned to a floor.
+pub fn find_building_of_room<Conn>(conn: &Conn, room: &Room) -> QueryResult<Option<Building>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::buildings;
+    use crate::schema::floors;
+
+    if let Some(floor_id) = room.floor_id {
+        buildings::table
+            .inner_join(floors::table)
+            .filter(floors::dsl::id.eq(floor_id))
+            .select(buildings::all_columns)
+            .first(conn)
+            .optional()
+    } else {
+        Ok(None)
+    }
+}
+
+/// Get the IDs of all rooms on the floors of the building.
+pub fn find_rooms_of_building<Conn>(conn: &Conn, building: &str) -> QueryResult<Vec<String>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::floors;
+    use crate::schema::rooms;
+
+    rooms::table
+        .inner_join(floors::table)
+        .filter(floors::dsl::building.eq(building))
+        .select(rooms::dsl::id)
+        .order(rooms::dsl::id)
+        .load(conn)
+}
+
+/// Check if the given number of additional persons fit into the building of the room during
+/// the whole time range (in UTC).
+///
+/// Rooms that are not assigned to a building or whose building has no capacity limit
+/// are always available.
+pub fn check_if_building_available<Conn>(
+    conn: &Conn,
+    room: &Room,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    ignore_id: Option<i32>,
+    persons: usize,
+) -> QueryResult<bool>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::occupancies::dsl;
+
+    let building = find_building_of_room(conn, room)?;
+    let (building, max_occupancy) = match building {
+        Some(Building {
+            id,
+            max_occupancy: Some(max_occupancy),
+            ..
+        }) => (id, max_occupancy),
+        _ => return Ok(true),
+    };
+
+    let rooms = find_rooms_of_building(conn, &building)?;
+    let occupancies: Vec<Occupancy> = dsl::occupancies
+        .filter(dsl::room.eq_any(&rooms))
+        .filter(dsl::start.lt(end))
+        .filter(dsl::end.gt(start))
+        .load(conn)?;
+    let occupancies: Vec<Occupancy> = occupancies
+        .into_iter()
+        .filter(|o| Some(o.id) != ignore_id)
+        .collect();
+    let max_existing = max_concurrent_occupancies(&occupancies, start, end);
+
+    Ok(((max_existing + persons) as i32) <= max_occupancy)
+}
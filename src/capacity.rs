@@ -0,0 +1,116 @@
+use crate::{
+    api::weekday_code,
+    models::{CapacityRule, Room},
+};
+use chrono::prelude::*;
+use diesel::prelude::*;
+
+/// Get all capacity rules of the room.
+pub fn find_rules<Conn>(conn: &Conn, room: &str) -> QueryResult<Vec<CapacityRule>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::capacity_rules::dsl;
+
+    dsl::capacity_rules
+        .filter(dsl::room.eq(room))
+        .order((dsl::valid_from, dsl::id))
+        .load(conn)
+}
+
+/// Check if the rule is valid at the given local time of the room.
+fn applies_at(rule: &CapacityRule, local: NaiveDateTime) -> bool {
+    let day = local.date();
+    let time = local.time();
+    rule.valid_from.iter().all(|from| *from <= day)
+        && rule.valid_to.iter().all(|to| day <= *to)
+        && (rule.weekdays.is_empty()
+            || rule
+                .weekdays
+                .split(',')
+                .any(|d| d == weekday_code(day.weekday())))
+        && rule.from_time.iter().all(|from| *from <= time)
+        // A time window until midnight ends at 00:00
+        && rule
+            .to_time
+            .iter()
+            .all(|to| time < *to || *to == NaiveTime::from_hms(0, 0, 0))
+}
+
+/// Get the maximum occupancy from the rules that are valid at the given time (UTC).
+///
+/// If several rules are valid at the same time, the lowest limit is used. If no rule is
+/// valid, the general maximum occupancy of the room is used. Use this instead of
+/// [`max_occupancy_at`] to check many times with the rules from [`find_rules`].
+pub fn limit_at(room: &Room, rules: &[CapacityRule], time: NaiveDateTime) -> i32 {
+    let local = room
+        .parsed_timezone()
+        .from_utc_datetime(&time)
+        .naive_local();
+    rules
+        .iter()
+        .filter(|r| applies_at(r, local))
+        .map(|r| r.max_occupancy)
+        .min()
+        .unwrap_or(room.max_occupancy)
+}
+
+/// Get the maximum occupancy of the room at the given time (UTC).
+pub fn max_occupancy_at<Conn>(conn: &Conn, room: &Room, time: NaiveDateTime) -> QueryResult<i32>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    let rules = find_rules(conn, &room.id)?;
+    Ok(limit_at(room, &rules, time))
+}
+
+/// Split the time range (in UTC) into consecutive parts with the same maximum occupancy of the
+/// room and return each part together with its maximum occupancy.
+pub fn find_limits<Conn>(
+    conn: &Conn,
+    room: &Room,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> QueryResult<Vec<(NaiveDateTime, NaiveDateTime, i32)>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    let rules = find_rules(conn, &room.id)?;
+    if rules.is_empty() {
+        return Ok(vec![(start, end, room.max_occupancy)]);
+    }
+
+    // The limit can only change at midnight and when the time window of a rule starts or ends
+    let tz = room.parsed_timezone();
+    let to_utc = |local: NaiveDateTime| {
+        tz.from_local_datetime(&local)
+            .earliest()
+            .map(|t| t.naive_utc())
+    };
+    let mut boundaries = vec![start, end];
+    let mut day = tz.from_utc_datetime(&start).naive_local().date();
+    let last_day = tz.from_utc_datetime(&end).naive_local().date();
+    while day <= last_day {
+        boundaries.extend(to_utc(day.and_hms(0, 0, 0)));
+        for rule in &rules {
+            for time in rule.from_time.iter().chain(rule.to_time.iter()) {
+                boundaries.extend(to_utc(day.and_time(*time)));
+            }
+        }
+        day = day.succ();
+    }
+    boundaries.retain(|b| start <= *b && *b <= end);
+    boundaries.sort();
+    boundaries.dedup();
+
+    let mut result: Vec<(NaiveDateTime, NaiveDateTime, i32)> = Vec::new();
+    for part in boundaries.windows(2) {
+        let limit = limit_at(room, &rules, part[0]);
+        match result.last_mut() {
+            // Merge adjacent parts with the same limit
+            Some(last) if last.2 == limit => last.1 = part[1],
+            _ => result.push((part[0], part[1], limit)),
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,103 @@
+use crate::{
+    api::announce_occupancy_change, audit, config::Settings, errors::ServiceError,
+    models::Occupancy, models::Room, notifications::OccupancyChange, waitlist, DbPool,
+};
+use chrono::{prelude::*, Duration};
+use diesel::prelude::*;
+use std::collections::BTreeMap;
+
+/// Actor recorded in the audit log and the notifications when an occupancy is released.
+pub const RELEASE_ACTOR: &str = "Roompla (no check-in)";
+
+/// Delete all running occupancies that have not been checked in within the grace period after
+/// their start and book waiting users instead.
+///
+/// Occupancies that have been created or changed during the grace period are kept, so users
+/// who book a room that is already in use still have the full grace period to check in.
+pub fn release_no_shows<Conn>(
+    conn: &Conn,
+    settings: &Settings,
+    grace_minutes: u32,
+) -> Result<Vec<Occupancy>, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::audit_log::dsl as a_dsl;
+    use crate::schema::occupancies::dsl;
+    use crate::schema::rooms::dsl as r_dsl;
+
+    let now = Utc::now().naive_utc();
+    let cutoff = now - Duration::minutes(grace_minutes as i64);
+
+    conn.transaction::<_, ServiceError, _>(|| {
+        let candidates: Vec<Occupancy> = dsl::occupancies
+            .filter(dsl::checked_in_at.is_null())
+            .filter(dsl::checked_out_at.is_null())
+            .filter(dsl::start.le(cutoff))
+            .filter(dsl::end.gt(now))
+            .order(dsl::start)
+            .load(conn)?;
+        let candidate_ids: Vec<i32> = candidates.iter().map(|o| o.id).collect();
+        let recently_changed: Vec<i32> = a_dsl::audit_log
+            .select(a_dsl::occupancy_id)
+            .filter(a_dsl::occupancy_id.eq_any(&candidate_ids))
+            .filter(a_dsl::changed_at.gt(cutoff))
+            .load(conn)?;
+
+        let mut released_by_room: BTreeMap<String, Vec<Occupancy>> = BTreeMap::new();
+        for o in candidates {
+            if recently_changed.contains(&o.id) {
+                continue;
+            }
+            diesel::delete(dsl::occupancies.find(o.id)).execute(conn)?;
+            audit::record_delete(conn, RELEASE_ACTOR, &o)?;
+            released_by_room.entry(o.room.clone()).or_default().push(o);
+        }
+
+        let mut result = Vec::new();
+        for (room, released) in released_by_room {
+            let room: Room = r_dsl::rooms.find(&room).first(conn)?;
+            for o in released {
+                announce_occupancy_change(
+                    conn,
+                    &room,
+                    settings,
+                    OccupancyChange::Deleted,
+                    std::slice::from_ref(&o),
+                    RELEASE_ACTOR,
+                )?;
+                result.push(o);
+            }
+            waitlist::book_waiting_users(conn, &room, settings)?;
+        }
+        Ok(result)
+    })
+}
+
+/// Release occupancies without check-in in the configured interval, forever.
+pub async fn run_periodically(db_pool: DbPool, settings: Settings) {
+    let grace_minutes = if let Some(grace_minutes) = settings.checkin.grace_minutes {
+        grace_minutes
+    } else {
+        info!("No grace period for check-ins configured, occupancies are never released");
+        return;
+    };
+
+    let period = std::time::Duration::from_secs(settings.checkin.interval_minutes.max(1) * 60);
+    let mut interval = actix_rt::time::interval(period);
+    loop {
+        interval.tick().await;
+        let result = db_pool
+            .get()
+            .map_err(ServiceError::from)
+            .and_then(|conn| release_no_shows(&conn, &settings, grace_minutes));
+        match result {
+            Ok(released) if !released.is_empty() => info!(
+                "Released {} occupancies that have not been checked in",
+                released.len()
+            ),
+            Ok(_) => {}
+            Err(e) => error!("Error when releasing occupancies without check-in: {:?}", e),
+        }
+    }
+}
@@ -38,6 +38,229 @@ pub struct LDAPSettings {
     pub organization: String,
     #[serde(default = "default_ldap_filter")]
     pub filter: String,
+    #[serde(default = "default_ldap_group_attribute")]
+    pub group_attribute: String,
+    #[serde(default)]
+    pub room_manager_groups: Vec<String>,
+    #[serde(default)]
+    pub admin_groups: Vec<String>,
+    /// User to look up other users, e.g. the participants of group bookings (anonymous if not set)
+    #[serde(default)]
+    pub bind_dn: Option<String>,
+    #[serde(default)]
+    pub bind_password: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BookingSettings {
+    /// Length of the booking slots in minutes, can be overwritten per room
+    #[serde(default = "default_slot_minutes")]
+    pub slot_minutes: i64,
+    /// Maximum hours a user can book per week in all rooms
+    #[serde(default)]
+    pub max_hours_per_week: Option<u32>,
+    /// Maximum number of occupancies a user can have in the future in all rooms
+    #[serde(default)]
+    pub max_future_bookings: Option<u32>,
+    /// Maximum number of days in advance rooms can be booked
+    #[serde(default)]
+    pub max_days_ahead: Option<u32>,
+}
+
+impl Default for BookingSettings {
+    fn default() -> Self {
+        BookingSettings {
+            slot_minutes: default_slot_minutes(),
+            max_hours_per_week: None,
+            max_future_bookings: None,
+            max_days_ahead: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionMode {
+    Delete,
+    Anonymize,
+}
+
+impl Default for RetentionMode {
+    fn default() -> Self {
+        RetentionMode::Delete
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RetentionSettings {
+    /// Occupancies that ended more than this number of days ago are purged
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+    #[serde(default)]
+    pub mode: RetentionMode,
+    #[serde(default = "default_retention_interval")]
+    pub interval_hours: u64,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        RetentionSettings {
+            max_age_days: None,
+            mode: RetentionMode::default(),
+            interval_hours: default_retention_interval(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Unencrypted connection, e.g. to a server on the same host
+    None,
+    /// Upgrade the connection with STARTTLS
+    StartTls,
+    /// Connect with TLS directly (usually on port 465)
+    Tls,
+}
+
+impl Default for SmtpSecurity {
+    fn default() -> Self {
+        SmtpSecurity::StartTls
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SmtpSettings {
+    /// Host name of the mail server, no mails are sent if this is not set
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Sender address of all mails
+    #[serde(default = "default_smtp_from")]
+    pub from: String,
+    /// How often the queue of pending notifications is processed
+    #[serde(default = "default_smtp_interval")]
+    pub interval_seconds: u64,
+    /// Number of failed attempts after which a notification is not sent anymore
+    #[serde(default = "default_smtp_max_attempts")]
+    pub max_attempts: i32,
+}
+
+impl Default for SmtpSettings {
+    fn default() -> Self {
+        SmtpSettings {
+            host: None,
+            port: default_smtp_port(),
+            security: SmtpSecurity::default(),
+            username: None,
+            password: None,
+            from: default_smtp_from(),
+            interval_seconds: default_smtp_interval(),
+            max_attempts: default_smtp_max_attempts(),
+        }
+    }
+}
+
+/// Subject and body of a notification.
+///
+/// Both can contain the placeholders `{room}`, `{name}`, `{actor}`, `{times}` and `{old_times}`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MessageTemplate {
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NotificationSettings {
+    #[serde(default = "default_created_template")]
+    pub created: MessageTemplate,
+    #[serde(default = "default_updated_template")]
+    pub updated: MessageTemplate,
+    #[serde(default = "default_deleted_template")]
+    pub deleted: MessageTemplate,
+    #[serde(default = "default_waitlist_template")]
+    pub waitlist: MessageTemplate,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            created: default_created_template(),
+            updated: default_updated_template(),
+            deleted: default_deleted_template(),
+            waitlist: default_waitlist_template(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CheckinSettings {
+    /// Occupancies that have not been checked in this number of minutes after their start
+    /// are released, they are never released if this is not set
+    #[serde(default)]
+    pub grace_minutes: Option<u32>,
+    /// How many minutes before the start of an occupancy users can check in
+    #[serde(default = "default_checkin_early_minutes")]
+    pub early_minutes: i64,
+    /// How often the service checks for occupancies without check-in
+    #[serde(default = "default_checkin_interval")]
+    pub interval_minutes: u64,
+}
+
+impl Default for CheckinSettings {
+    fn default() -> Self {
+        CheckinSettings {
+            grace_minutes: None,
+            early_minutes: default_checkin_early_minutes(),
+            interval_minutes: default_checkin_interval(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// Secret to sign the payload with HMAC-SHA256
+    pub secret: String,
+    /// Only send these events (e.g. "occupancy.created"), all events are sent if empty
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookSettings {
+    #[serde(default)]
+    pub endpoints: Vec<WebhookEndpoint>,
+    /// How often the outbox of pending deliveries is processed
+    #[serde(default = "default_webhook_interval")]
+    pub interval_seconds: u64,
+    #[serde(default = "default_webhook_timeout")]
+    pub timeout_seconds: u64,
+    /// Delay before the first retry of a failed delivery, doubled for each further retry
+    #[serde(default = "default_webhook_backoff")]
+    pub backoff_seconds: i64,
+    /// Number of failed attempts after which a delivery is not retried anymore
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: i32,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        WebhookSettings {
+            endpoints: Vec::default(),
+            interval_seconds: default_webhook_interval(),
+            timeout_seconds: default_webhook_timeout(),
+            backoff_seconds: default_webhook_backoff(),
+            max_attempts: default_webhook_max_attempts(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Default, Clone)]
@@ -52,6 +275,18 @@ pub struct Settings {
     pub jwt: JWTSettings,
     #[serde(default)]
     pub log: LogSettings,
+    #[serde(default)]
+    pub retention: RetentionSettings,
+    #[serde(default)]
+    pub booking: BookingSettings,
+    #[serde(default)]
+    pub smtp: SmtpSettings,
+    #[serde(default)]
+    pub notifications: NotificationSettings,
+    #[serde(default)]
+    pub webhooks: WebhookSettings,
+    #[serde(default)]
+    pub checkin: CheckinSettings,
 }
 
 impl Settings {
@@ -61,7 +296,9 @@ impl Settings {
             include_str!("default-settings.toml"),
             config::FileFormat::Toml,
         ))?;
-        config.try_into()
+        let settings: Settings = config.try_into()?;
+        settings.validate()?;
+        Ok(settings)
     }
 
     pub fn with_file<S: Deref<Target = str>>(config_file: S) -> Result<Self, ConfigError> {
@@ -71,7 +308,21 @@ impl Settings {
             config::FileFormat::Toml,
         ))?;
         config.merge(config::File::new(&config_file, config::FileFormat::Toml))?;
-        config.try_into()
+        let settings: Settings = config.try_into()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Check values that can be parsed, but are not usable.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let slot_minutes = self.booking.slot_minutes;
+        if slot_minutes < 1 || (24 * 60) % slot_minutes != 0 {
+            return Err(ConfigError::Message(format!(
+                "Slot length of {} minutes in [booking] is not a divisor of the minutes of a day.",
+                slot_minutes
+            )));
+        }
+        Ok(())
     }
 }
 
@@ -91,6 +342,132 @@ fn default_ldap_filter() -> String {
     return "(uid=*)".to_string();
 }
 
+fn default_ldap_group_attribute() -> String {
+    return "memberOf".to_string();
+}
+
+fn default_slot_minutes() -> i64 {
+    return 60;
+}
+
+fn default_retention_interval() -> u64 {
+    return 24;
+}
+
 fn default_service_port() -> u16 {
     return 5050;
 }
+
+fn default_smtp_port() -> u16 {
+    return 25;
+}
+
+fn default_smtp_from() -> String {
+    return "roompla@localhost".to_string();
+}
+
+fn default_smtp_interval() -> u64 {
+    return 60;
+}
+
+fn default_smtp_max_attempts() -> i32 {
+    return 5;
+}
+
+fn default_webhook_interval() -> u64 {
+    return 10;
+}
+
+fn default_webhook_timeout() -> u64 {
+    return 10;
+}
+
+fn default_webhook_backoff() -> i64 {
+    return 30;
+}
+
+fn default_webhook_max_attempts() -> i32 {
+    return 8;
+}
+
+fn default_checkin_early_minutes() -> i64 {
+    return 15;
+}
+
+fn default_checkin_interval() -> u64 {
+    return 5;
+}
+
+fn default_created_template() -> MessageTemplate {
+    return MessageTemplate {
+        subject: "Room {room} has been booked".to_string(),
+        body: "Hello {name},\n\nroom {room} has been booked for you by {actor}:\n\n{times}\n"
+            .to_string(),
+    };
+}
+
+fn default_updated_template() -> MessageTemplate {
+    return MessageTemplate {
+        subject: "Your occupancy of room {room} has been changed".to_string(),
+        body: "Hello {name},\n\n{actor} changed your occupancy of room {room} from\n\n{old_times}\n\nto\n\n{times}\n".to_string(),
+    };
+}
+
+fn default_deleted_template() -> MessageTemplate {
+    return MessageTemplate {
+        subject: "Your occupancy of room {room} has been cancelled".to_string(),
+        body: "Hello {name},\n\n{actor} cancelled your occupancy of room {room}:\n\n{times}\n"
+            .to_string(),
+    };
+}
+
+fn default_waitlist_template() -> MessageTemplate {
+    return MessageTemplate {
+        subject: "Room {room} has been booked for you".to_string(),
+        body: "Hello {name},\n\na place in room {room} became available and has been booked for you:\n\n{times}\n".to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_slot_length_is_rejected() {
+        let config_file =
+            std::env::temp_dir().join(format!("roompla-invalid-slot-{}.toml", std::process::id()));
+        std::fs::write(&config_file, "[booking]\nslot_minutes = 7\n").unwrap();
+        let result = Settings::with_file(config_file.to_string_lossy().as_ref());
+        std::fs::remove_file(&config_file).unwrap();
+        assert!(result.is_err());
+
+        assert_eq!(60, Settings::new().unwrap().booking.slot_minutes);
+        assert!(Settings::default().validate().is_ok());
+    }
+
+    #[test]
+    fn defaults_match_omitted_settings() {
+        let defaults = Settings::default();
+        // Omitted fields of a given section get the defaults of serde
+        let parsed: Settings =
+            serde_json::from_str(r#"{"smtp": {}, "webhooks": {}, "checkin": {}, "retention": {}}"#)
+                .unwrap();
+        assert_eq!(parsed.smtp.port, defaults.smtp.port);
+        assert_eq!(parsed.smtp.max_attempts, defaults.smtp.max_attempts);
+        assert_eq!(parsed.webhooks.max_attempts, defaults.webhooks.max_attempts);
+        assert_eq!(
+            parsed.webhooks.timeout_seconds,
+            defaults.webhooks.timeout_seconds
+        );
+        assert_eq!(parsed.checkin.early_minutes, defaults.checkin.early_minutes);
+        assert_eq!(
+            parsed.checkin.interval_minutes,
+            defaults.checkin.interval_minutes
+        );
+        assert_eq!(
+            parsed.retention.interval_hours,
+            defaults.retention.interval_hours
+        );
+        assert_eq!(25, defaults.smtp.port);
+    }
+}
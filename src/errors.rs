@@ -6,6 +6,8 @@ use std::fmt::Display;
 pub enum ServiceError {
     BadRequest(String),
     InvalidJWTToken(String),
+    Forbidden(String),
+    QuotaExceeded(String),
     DatabaseError(String),
     InternalServerError(String),
 }
@@ -15,6 +17,8 @@ impl Display for ServiceError {
         match self {
             ServiceError::BadRequest(msg) => write!(f, "Bad Request: {}", msg)?,
             ServiceError::InvalidJWTToken(msg) => write!(f, "Invalid JWT Token: {}", msg)?,
+            ServiceError::Forbidden(msg) => write!(f, "Forbidden: {}", msg)?,
+            ServiceError::QuotaExceeded(msg) => write!(f, "Quota exceeded: {}", msg)?,
             ServiceError::DatabaseError(e) => write!(f, "Error accessing database: {}", e)?,
             ServiceError::InternalServerError(msg) => {
                 write!(f, "Internal Server Error: {:?}", msg)?
@@ -31,6 +35,10 @@ impl ResponseError for ServiceError {
             ServiceError::InvalidJWTToken(ref message) => {
                 HttpResponse::Unauthorized().json(message)
             }
+            ServiceError::Forbidden(ref message) => HttpResponse::Forbidden().json(message),
+            ServiceError::QuotaExceeded(ref message) => {
+                HttpResponse::UnprocessableEntity().json(message)
+            }
 
             ServiceError::DatabaseError(_) => {
                 HttpResponse::BadGateway().json("Error accessing database")
@@ -93,6 +101,12 @@ impl From<ldap3::result::LdapError> for ServiceError {
     }
 }
 
+impl From<serde_json::Error> for ServiceError {
+    fn from(e: serde_json::Error) -> Self {
+        ServiceError::InternalServerError(format!("{}", e))
+    }
+}
+
 impl From<chrono::RoundingError> for ServiceError {
     fn from(e: chrono::RoundingError) -> Self {
         ServiceError::InternalServerError(format!("{}", e))
@@ -1,10 +1,28 @@
 use crate::{
     config::Settings,
+    holidays,
     models::{Occupancy, Room},
 };
 use anyhow::Result;
 use chrono::{prelude::*, Duration};
 use diesel::{prelude::*, r2d2::ConnectionManager, sqlite::SqliteConnection};
+use std::collections::HashMap;
+
+/// An occupancy of another user that overlapped with an occupancy of the traced user.
+#[derive(Serialize, Debug)]
+pub struct Contact {
+    pub user_id: String,
+    pub user_name: String,
+    pub user_contact: String,
+    pub room: String,
+    /// Begin of the overlap (UTC)
+    pub start: NaiveDateTime,
+    /// End of the overlap (UTC)
+    pub end: NaiveDateTime,
+    pub overlap_minutes: i64,
+    /// ID of the occupancy of the traced user
+    pub traced_occupancy: i32,
+}
 
 pub fn to_csv(file: &str, weeks: u8, settings: Settings) -> Result<()> {
     // Calculate start and end date
@@ -43,18 +61,17 @@ pub fn to_csv(file: &str, weeks: u8, settings: Settings) -> Result<()> {
             "day",
             "start_time",
             "end_time",
+            "holiday",
+            "checkin_time",
+            "checkout_time",
         ])?;
 
         for (event, room) in result {
-            // Convert stored dates to local time and just output the day, not the time
-            let tz = if let Some(tz) = &room.timezone {
-                let parsed_tz: chrono_tz::Tz = tz.parse().expect("Invalid Time Zone");
-                parsed_tz
-            } else {
-                chrono_tz::Tz::UTC
-            };
-            let event_start_utc: DateTime<Utc> = DateTime::from_utc(event.start, Utc);
-            let event_end_utc: DateTime<Utc> = DateTime::from_utc(event.end, Utc);
+            // Convert stored dates to local time and just output the day, not the time.
+            // The times of check-in and check-out are preferred over the planned times.
+            let tz = room.parsed_timezone();
+            let event_start_utc: DateTime<Utc> = DateTime::from_utc(event.actual_start(), Utc);
+            let event_end_utc: DateTime<Utc> = DateTime::from_utc(event.actual_end(), Utc);
 
             let event_start: DateTime<chrono_tz::Tz> = event_start_utc.with_timezone(&tz);
             let event_end: DateTime<chrono_tz::Tz> = event_end_utc.with_timezone(&tz);
@@ -63,6 +80,20 @@ pub fn to_csv(file: &str, weeks: u8, settings: Settings) -> Result<()> {
 
             let event_start_time = event_start.time();
             let event_end_time = event_end.time();
+            let checkin_time = event
+                .checked_in_at
+                .map(|t| DateTime::<Utc>::from_utc(t, Utc).with_timezone(&tz).time());
+            let checkout_time = event
+                .checked_out_at
+                .map(|t| DateTime::<Utc>::from_utc(t, Utc).with_timezone(&tz).time());
+
+            // Mark occupancies on days that have become holidays after they were booked
+            let holiday = holidays::find_holiday(
+                &conn,
+                &event.room,
+                event_day.naive_local(),
+                event_day.naive_local(),
+            )?;
 
             writer.write_record(&[
                 event.user_id,
@@ -72,6 +103,9 @@ pub fn to_csv(file: &str, weeks: u8, settings: Settings) -> Result<()> {
                 event_day.to_string(),
                 event_start_time.to_string(),
                 event_end_time.to_string(),
+                holiday.map(|h| h.name).unwrap_or_default(),
+                checkin_time.map(|t| t.to_string()).unwrap_or_default(),
+                checkout_time.map(|t| t.to_string()).unwrap_or_default(),
             ])?;
         }
         info!("Finished export to {}", file);
@@ -80,3 +114,172 @@ pub fn to_csv(file: &str, weeks: u8, settings: Settings) -> Result<()> {
 
     Ok(())
 }
+
+/// Get all occupancies that were attended during the time range (in UTC), optionally only
+/// those of the given room or user.
+///
+/// The time between check-in and check-out is used instead of the planned times if available.
+fn find_attended_occupancies<Conn>(
+    conn: &Conn,
+    room: Option<&str>,
+    user_id: Option<&str>,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> QueryResult<Vec<Occupancy>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::occupancies::dsl;
+
+    // Users can check in before the planned start, but the check-out is never after the planned end
+    let mut query = dsl::occupancies
+        .filter(
+            dsl::checked_in_at
+                .lt(end)
+                .or(dsl::checked_in_at.is_null().and(dsl::start.lt(end))),
+        )
+        .filter(dsl::end.gt(start))
+        .filter(
+            dsl::checked_out_at
+                .is_null()
+                .or(dsl::checked_out_at.gt(start)),
+        )
+        .order(dsl::start)
+        .into_boxed();
+    if let Some(room) = room {
+        query = query.filter(dsl::room.eq(room));
+    }
+    if let Some(user_id) = user_id {
+        query = query.filter(dsl::user_id.eq(user_id));
+    }
+    query.load(conn)
+}
+
+/// Find all occupancies of other users in the same room that overlap with an
+/// occupancy of the given user in the time range.
+///
+/// The overlaps are computed from the actual times of the occupancies and are limited to the
+/// time range.
+pub fn find_contacts<Conn>(
+    conn: &Conn,
+    user_id: &str,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> QueryResult<Vec<Contact>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    let traced = find_attended_occupancies(conn, None, Some(user_id), start, end)?;
+
+    let mut result = Vec::new();
+    for t in traced {
+        let traced_start = t.actual_start().max(start);
+        let traced_end = t.actual_end().min(end);
+        let candidates =
+            find_attended_occupancies(conn, Some(&t.room), None, traced_start, traced_end)?;
+        for other in candidates {
+            if other.user_id == user_id {
+                continue;
+            }
+            let overlap_start = traced_start.max(other.actual_start());
+            let overlap_end = traced_end.min(other.actual_end());
+            if overlap_end <= overlap_start {
+                continue;
+            }
+            result.push(Contact {
+                user_id: other.user_id,
+                user_name: other.user_name,
+                user_contact: other.user_contact,
+                room: other.room,
+                start: overlap_start,
+                end: overlap_end,
+                overlap_minutes: (overlap_end - overlap_start).num_minutes(),
+                traced_occupancy: t.id,
+            });
+        }
+    }
+    result.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.user_id.cmp(&b.user_id)));
+    Ok(result)
+}
+
+/// Get the time range (in UTC) from the begin of the first day to the end of the last day in the
+/// given timezone.
+pub fn day_window(
+    tz: &chrono_tz::Tz,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> (NaiveDateTime, NaiveDateTime) {
+    let start_of_day = |day: NaiveDate| {
+        tz.from_local_datetime(&day.and_hms(0, 0, 0))
+            .earliest()
+            .map_or(day.and_hms(0, 0, 0), |t| t.naive_utc())
+    };
+    (start_of_day(from), start_of_day(to.succ()))
+}
+
+/// Write the contacts of the user between the first and the last day (in the given timezone) to
+/// a CSV file.
+pub fn trace_to_csv(
+    file: &str,
+    user_id: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+    timezone: chrono_tz::Tz,
+    settings: Settings,
+) -> Result<()> {
+    info!("Loading database from {}", &settings.database.url);
+    let manager = ConnectionManager::<SqliteConnection>::new(&settings.database.url);
+    let db_pool = r2d2::Pool::builder().build(manager)?;
+
+    let conn = db_pool.get()?;
+
+    let (start, end) = day_window(&timezone, from, to);
+    let contacts = find_contacts(&conn, user_id, start, end)?;
+
+    // Get the rooms to convert the times to local time
+    use crate::schema::rooms::dsl as r_dsl;
+    let rooms: HashMap<String, Room> = r_dsl::rooms
+        .load::<Room>(&conn)?
+        .into_iter()
+        .map(|r| (r.id.clone(), r))
+        .collect();
+
+    let mut writer = csv::Writer::from_path(file)?;
+
+    // Write header
+    writer.write_record(&[
+        "id",
+        "name",
+        "contact",
+        "room",
+        "day",
+        "start_time",
+        "end_time",
+        "overlap_minutes",
+    ])?;
+
+    for c in contacts {
+        let tz = rooms
+            .get(&c.room)
+            .map(Room::parsed_timezone)
+            .unwrap_or(chrono_tz::Tz::UTC);
+        let contact_start = DateTime::<Utc>::from_utc(c.start, Utc).with_timezone(&tz);
+        let contact_end = DateTime::<Utc>::from_utc(c.end, Utc).with_timezone(&tz);
+
+        writer.write_record(&[
+            c.user_id,
+            c.user_name,
+            c.user_contact,
+            c.room,
+            contact_start.date().to_string(),
+            contact_start.time().to_string(),
+            contact_end.time().to_string(),
+            c.overlap_minutes.to_string(),
+        ])?;
+    }
+    info!(
+        "Finished contact tracing report for {} to {}",
+        user_id, file
+    );
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use crate::{api::Claims, config::Settings, errors::ServiceError};
+use crate::{api::Claims, config::Settings, errors::ServiceError, models::Role};
 use actix_web::{web, FromRequest};
 use futures::future::{err, ok, Ready};
 use hmac::{Hmac, Mac};
@@ -36,6 +36,37 @@ fn verify_token(token: &str, settings: &Settings) -> Result<Claims, ServiceError
     }
 }
 
+fn claims_from_request(req: &actix_web::HttpRequest) -> Result<Claims, ServiceError> {
+    if let Some(settings) = req.app_data::<web::Data<Settings>>() {
+        if let Some(authen_header) = req.headers().get("Authorization") {
+            // Parse header
+            if let Ok(authen_str) = authen_header.to_str() {
+                if authen_str.starts_with("bearer") || authen_str.starts_with("Bearer") {
+                    // Parse and verify token, if a token was given but invalid, report an error
+                    let token = authen_str[6..authen_str.len()].trim();
+                    return verify_token(token, &settings);
+                }
+            }
+        }
+    }
+    Err(ServiceError::InvalidJWTToken("".to_string()))
+}
+
+fn claims_with_role(
+    req: &actix_web::HttpRequest,
+    required_role: Role,
+) -> Result<Claims, ServiceError> {
+    let claims = claims_from_request(req)?;
+    if claims.role >= required_role {
+        Ok(claims)
+    } else {
+        Err(ServiceError::Forbidden(format!(
+            "This action requires the role \"{}\"",
+            required_role
+        )))
+    }
+}
+
 impl FromRequest for ClaimsFromAuth {
     type Error = ServiceError;
     type Future = Ready<Result<Self, Self::Error>>;
@@ -45,23 +76,127 @@ impl FromRequest for ClaimsFromAuth {
         req: &actix_web::HttpRequest,
         _payload: &mut actix_web::dev::Payload,
     ) -> Self::Future {
-        if let Some(settings) = req.app_data::<web::Data<Settings>>() {
-            if let Some(authen_header) = req.headers().get("Authorization") {
-                // Parse header
-                if let Ok(authen_str) = authen_header.to_str() {
-                    if authen_str.starts_with("bearer") || authen_str.starts_with("Bearer") {
-                        // Parse and verify token
-                        let token = authen_str[6..authen_str.len()].trim();
-                        return match verify_token(token, &settings) {
-                            // Use the verified claim
-                            Ok(claim) => ok(ClaimsFromAuth(claim)),
-                            // If a token was given but invalid, report an error
-                            Err(e) => err(e),
-                        };
-                    }
+        match claims_from_request(req) {
+            Ok(claims) => ok(ClaimsFromAuth(claims)),
+            Err(e) => err(e),
+        }
+    }
+}
+
+/// Claims of an authenticated user that has at least the room manager role.
+#[derive(Debug, Clone)]
+pub struct RoomManagerFromAuth(pub Claims);
+
+impl FromRequest for RoomManagerFromAuth {
+    type Error = ServiceError;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = Settings;
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        _payload: &mut actix_web::dev::Payload,
+    ) -> Self::Future {
+        match claims_with_role(req, Role::RoomManager) {
+            Ok(claims) => ok(RoomManagerFromAuth(claims)),
+            Err(e) => err(e),
+        }
+    }
+}
+
+/// Claims of an authenticated user that has the admin role.
+#[derive(Debug, Clone)]
+pub struct AdminFromAuth(pub Claims);
+
+impl FromRequest for AdminFromAuth {
+    type Error = ServiceError;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = Settings;
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        _payload: &mut actix_web::dev::Payload,
+    ) -> Self::Future {
+        match claims_with_role(req, Role::Admin) {
+            Ok(claims) => ok(AdminFromAuth(claims)),
+            Err(e) => err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use jwt::SignWithKey;
+
+    fn create_settings() -> Settings {
+        let mut settings = Settings::default();
+        settings.jwt.secret = Some("secret".to_string());
+        settings
+    }
+
+    fn sign<C: serde::Serialize>(claims: &C) -> String {
+        let key = Hmac::<Sha256>::new_varkey(b"secret").unwrap();
+        claims.sign_with_key(&key).unwrap()
+    }
+
+    fn request_with_role(role: Role) -> actix_web::HttpRequest {
+        let token = sign(&Claims {
+            sub: "someone".to_string(),
+            name: "Someone".to_string(),
+            contact_info: "someone@example.com".to_string(),
+            role,
+            exp: None,
+        });
+        TestRequest::default()
+            .data(create_settings())
+            .header("Authorization", format!("Bearer {}", token))
+            .to_http_request()
+    }
+
+    #[test]
+    fn higher_roles_include_lower_roles() {
+        let is_allowed = |role: Role, required_role: Role| {
+            let result = claims_with_role(&request_with_role(role), required_role);
+            match result {
+                Ok(claims) => {
+                    assert_eq!(role, claims.role);
+                    true
                 }
+                Err(ServiceError::Forbidden(_)) => false,
+                Err(e) => panic!("Unexpected error {:?}", e),
             }
-        }
-        err(ServiceError::InvalidJWTToken("".to_string()))
+        };
+        assert!(is_allowed(Role::User, Role::User));
+        assert!(!is_allowed(Role::User, Role::RoomManager));
+        assert!(!is_allowed(Role::User, Role::Admin));
+        assert!(is_allowed(Role::RoomManager, Role::User));
+        assert!(is_allowed(Role::RoomManager, Role::RoomManager));
+        assert!(!is_allowed(Role::RoomManager, Role::Admin));
+        assert!(is_allowed(Role::Admin, Role::RoomManager));
+        assert!(is_allowed(Role::Admin, Role::Admin));
+    }
+
+    #[test]
+    fn tokens_without_role_are_users() {
+        // Tokens issued before roles were introduced have no role claim
+        let token = sign(&serde_json::json!({
+            "sub": "someone",
+            "name": "Someone",
+            "contact_info": "someone@example.com",
+            "exp": null,
+        }));
+        let claims = verify_token(&token, &create_settings()).unwrap();
+        assert_eq!("someone", claims.sub);
+        assert_eq!(Role::User, claims.role);
+
+        let request = TestRequest::default()
+            .data(create_settings())
+            .header("Authorization", format!("Bearer {}", token))
+            .to_http_request();
+        assert!(matches!(
+            claims_with_role(&request, Role::RoomManager),
+            Err(ServiceError::Forbidden(_))
+        ));
     }
 }
@@ -0,0 +1,107 @@
+use crate::{
+    config::Settings,
+    ical,
+    models::{Holiday, NewHoliday},
+};
+use anyhow::Result;
+use chrono::prelude::*;
+use diesel::{prelude::*, r2d2::ConnectionManager, sqlite::SqliteConnection};
+
+#[derive(Deserialize)]
+struct HolidayRecord {
+    date: NaiveDate,
+    name: String,
+}
+
+/// Find the first holiday of the room (or of all rooms) between the first and last day.
+pub fn find_holiday<Conn>(
+    conn: &Conn,
+    room: &str,
+    first_day: NaiveDate,
+    last_day: NaiveDate,
+) -> QueryResult<Option<Holiday>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::holidays::dsl;
+
+    dsl::holidays
+        .filter(dsl::room.eq(room).or(dsl::room.is_null()))
+        .filter(dsl::day.between(first_day, last_day))
+        .order(dsl::day)
+        .first(conn)
+        .optional()
+}
+
+/// Read the days and names of the holidays from a CSV file with the columns "date" and "name".
+fn read_csv(file: &str) -> Result<Vec<(NaiveDate, String)>> {
+    let mut reader = csv::Reader::from_path(file)?;
+    let mut result = Vec::new();
+    for record in reader.deserialize() {
+        let record: HolidayRecord = record?;
+        result.push((record.date, record.name));
+    }
+    Ok(result)
+}
+
+/// Import the holidays from an iCalendar or CSV file, either for a single room or all rooms.
+///
+/// Existing holidays on the same days and for the same rooms are replaced.
+pub fn import(file: &str, room: Option<String>, settings: Settings) -> Result<()> {
+    let days = if file.to_lowercase().ends_with(".ics") {
+        ical::parse_days(&std::fs::read_to_string(file)?)?
+    } else {
+        read_csv(file)?
+    };
+
+    info!("Loading database from {}", &settings.database.url);
+    let manager = ConnectionManager::<SqliteConnection>::new(&settings.database.url);
+    let db_pool = r2d2::Pool::builder().build(manager)?;
+
+    let conn = db_pool.get()?;
+    // The holidays might be imported before the service has been started for the first time
+    crate::embedded_migrations::run(&conn)?;
+
+    let imported_days: Vec<NaiveDate> = days.iter().map(|(day, _)| *day).collect();
+    conn.transaction::<_, anyhow::Error, _>(|| {
+        use crate::schema::holidays::dsl;
+        use crate::schema::rooms;
+
+        if let Some(room) = &room {
+            let existing: i64 = rooms::dsl::rooms
+                .filter(rooms::dsl::id.eq(room))
+                .count()
+                .get_result(&conn)?;
+            if existing == 0 {
+                return Err(anyhow::anyhow!("Room {} not found", room));
+            }
+            diesel::delete(
+                dsl::holidays
+                    .filter(dsl::room.eq(room))
+                    .filter(dsl::day.eq_any(&imported_days)),
+            )
+            .execute(&conn)?;
+        } else {
+            diesel::delete(
+                dsl::holidays
+                    .filter(dsl::room.is_null())
+                    .filter(dsl::day.eq_any(&imported_days)),
+            )
+            .execute(&conn)?;
+        }
+
+        for (day, name) in &days {
+            diesel::insert_into(crate::schema::holidays::table)
+                .values(NewHoliday {
+                    room: room.clone(),
+                    day: *day,
+                    name: name.clone(),
+                })
+                .execute(&conn)?;
+        }
+        Ok(())
+    })?;
+
+    info!("Imported {} holidays from {}", days.len(), file);
+    Ok(())
+}
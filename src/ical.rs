@@ -0,0 +1,369 @@
+use anyhow::Result;
+use chrono::{prelude::*, Duration};
+use std::collections::BTreeMap;
+
+/// A single event of an iCalendar feed.
+pub struct Event {
+    /// Globally unique and stable identifier of the event
+    pub uid: String,
+    /// Start time (UTC)
+    pub start: NaiveDateTime,
+    /// End time (UTC)
+    pub end: NaiveDateTime,
+    pub summary: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    /// Timezone in which the start and end are shown, usually the one of the room
+    pub timezone: chrono_tz::Tz,
+}
+
+/// Escape special characters in iCalendar text values (RFC 5545, section 3.3.11).
+fn escape_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Revert the escaping of iCalendar text values.
+fn unescape_text(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => result.push('\n'),
+                Some(escaped) => result.push(escaped),
+                None => {}
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Add a content line and fold it so no line is longer than 75 octets (RFC 5545, section 3.1).
+fn push_line(output: &mut String, line: &str) {
+    let mut line_length = 0;
+    for c in line.chars() {
+        if line_length + c.len_utf8() > 75 {
+            output.push_str("\r\n ");
+            // The leading space counts to the length of the continued line
+            line_length = 1;
+        }
+        output.push(c);
+        line_length += c.len_utf8();
+    }
+    output.push_str("\r\n");
+}
+
+/// Format the time (UTC) as UTC time (RFC 5545, section 3.3.5).
+fn format_time(property: &str, time: NaiveDateTime) -> String {
+    format!("{}:{}", property, time.format("%Y%m%dT%H%M%SZ"))
+}
+
+/// Format the time (UTC) as local time with a reference to the timezone, which must be
+/// described by a VTIMEZONE component of the document (RFC 5545, section 3.2.19).
+fn format_local_time(property: &str, time: NaiveDateTime, tz: chrono_tz::Tz) -> String {
+    if tz == chrono_tz::Tz::UTC {
+        return format_time(property, time);
+    }
+    format!(
+        "{};TZID={}:{}",
+        property,
+        tz.name(),
+        tz.from_utc_datetime(&time)
+            .naive_local()
+            .format("%Y%m%dT%H%M%S")
+    )
+}
+
+/// Format the offset to UTC like "+0130" (RFC 5545, section 3.3.14).
+fn format_offset(offset: FixedOffset) -> String {
+    let seconds = offset.local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+}
+
+/// Find the time (UTC) at which the offset of the timezone changes, given a time before and
+/// a time after the change.
+fn find_offset_change(
+    tz: chrono_tz::Tz,
+    before: NaiveDateTime,
+    after: NaiveDateTime,
+) -> NaiveDateTime {
+    let offset_at = |t: &NaiveDateTime| tz.offset_from_utc_datetime(t).fix();
+    let (mut before, mut after) = (before, after);
+    while after - before > Duration::seconds(1) {
+        let middle = before + (after - before) / 2;
+        if offset_at(&middle) == offset_at(&before) {
+            before = middle;
+        } else {
+            after = middle;
+        }
+    }
+    after
+}
+
+/// Add a VTIMEZONE component with all changes of the offset of the timezone in the years of
+/// the given time range (UTC).
+fn push_timezone(output: &mut String, tz: chrono_tz::Tz, start: NaiveDateTime, end: NaiveDateTime) {
+    let first = NaiveDate::from_ymd(start.year(), 1, 1).and_hms(0, 0, 0);
+    let last = NaiveDate::from_ymd(end.year() + 1, 1, 1).and_hms(0, 0, 0);
+    let offset_at = |t: &NaiveDateTime| tz.offset_from_utc_datetime(t);
+
+    // Each observance starts at a time (UTC) and changes from one offset to another
+    let mut observances = vec![(first, offset_at(&first), offset_at(&first))];
+    let mut day = first;
+    while day < last {
+        let next_day = day + Duration::days(1);
+        let (before, after) = (offset_at(&day), offset_at(&next_day));
+        if before.fix() != after.fix() {
+            observances.push((find_offset_change(tz, day, next_day), before, after));
+        }
+        day = next_day;
+    }
+    // Without a rule for daylight saving time, the lowest offset is the standard time
+    let standard = observances
+        .iter()
+        .map(|o| o.2.fix().local_minus_utc())
+        .min()
+        .unwrap_or_default();
+
+    push_line(output, "BEGIN:VTIMEZONE");
+    push_line(output, &format!("TZID:{}", tz.name()));
+    for (onset, before, after) in observances {
+        let component = if after.fix().local_minus_utc() > standard {
+            "DAYLIGHT"
+        } else {
+            "STANDARD"
+        };
+        push_line(output, &format!("BEGIN:{}", component));
+        // The onset is given in the local time before the change
+        let local_onset = onset + Duration::seconds(before.fix().local_minus_utc() as i64);
+        push_line(
+            output,
+            &format!("DTSTART:{}", local_onset.format("%Y%m%dT%H%M%S")),
+        );
+        push_line(
+            output,
+            &format!("TZOFFSETFROM:{}", format_offset(before.fix())),
+        );
+        push_line(
+            output,
+            &format!("TZOFFSETTO:{}", format_offset(after.fix())),
+        );
+        push_line(
+            output,
+            &format!("TZNAME:{}", escape_text(&after.to_string())),
+        );
+        push_line(output, &format!("END:{}", component));
+    }
+    push_line(output, "END:VTIMEZONE");
+}
+
+/// Render the events as iCalendar (RFC 5545) document.
+pub fn render(calendar_name: &str, events: &[Event]) -> String {
+    let now = Utc::now().naive_utc();
+
+    let mut output = String::new();
+    push_line(&mut output, "BEGIN:VCALENDAR");
+    push_line(&mut output, "VERSION:2.0");
+    push_line(&mut output, "PRODID:-//korpling//roompla//EN");
+    push_line(&mut output, "CALSCALE:GREGORIAN");
+    push_line(
+        &mut output,
+        &format!("X-WR-CALNAME:{}", escape_text(calendar_name)),
+    );
+
+    // Describe each timezone used by the events for the time range of these events
+    let mut timezones: BTreeMap<&str, (chrono_tz::Tz, NaiveDateTime, NaiveDateTime)> =
+        BTreeMap::new();
+    for e in events.iter().filter(|e| e.timezone != chrono_tz::Tz::UTC) {
+        let range = timezones
+            .entry(e.timezone.name())
+            .or_insert((e.timezone, e.start, e.end));
+        range.1 = range.1.min(e.start);
+        range.2 = range.2.max(e.end);
+    }
+    for (tz, start, end) in timezones.values() {
+        push_timezone(&mut output, *tz, *start, *end);
+    }
+
+    for e in events {
+        push_line(&mut output, "BEGIN:VEVENT");
+        push_line(&mut output, &format!("UID:{}", escape_text(&e.uid)));
+        push_line(&mut output, &format_time("DTSTAMP", now));
+        push_line(
+            &mut output,
+            &format_local_time("DTSTART", e.start, e.timezone),
+        );
+        push_line(&mut output, &format_local_time("DTEND", e.end, e.timezone));
+        push_line(&mut output, &format!("SUMMARY:{}", escape_text(&e.summary)));
+        if let Some(description) = &e.description {
+            push_line(
+                &mut output,
+                &format!("DESCRIPTION:{}", escape_text(description)),
+            );
+        }
+        if let Some(location) = &e.location {
+            push_line(&mut output, &format!("LOCATION:{}", escape_text(location)));
+        }
+        push_line(&mut output, "END:VEVENT");
+    }
+    push_line(&mut output, "END:VCALENDAR");
+    output
+}
+
+/// Parse the date of a DTSTART or DTEND value, which is either a date or a date-time.
+fn parse_date(value: &str) -> Result<NaiveDate> {
+    // Date-time values start with the date, e.g. "20201225T000000Z"
+    let date = value
+        .get(0..8)
+        .ok_or_else(|| anyhow::anyhow!("Invalid date \"{}\"", value))?;
+    Ok(NaiveDate::parse_from_str(date, "%Y%m%d")?)
+}
+
+/// Get the days and summaries of all events of an iCalendar (RFC 5545) document.
+///
+/// Events that span several days are returned once for each day. Times and
+/// recurrence rules are ignored, which is enough for calendars of public holidays.
+pub fn parse_days(content: &str) -> Result<Vec<(NaiveDate, String)>> {
+    // Unfold the content lines
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match lines.last_mut() {
+            Some(previous) if line.starts_with(' ') || line.starts_with('\t') => {
+                previous.push_str(&line[1..])
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut result = Vec::new();
+    let mut in_event = false;
+    let mut start: Option<NaiveDate> = None;
+    let mut end: Option<NaiveDate> = None;
+    let mut summary = String::new();
+    for line in lines {
+        // Split into the property name (with optional parameters) and the value
+        let (name, value) = match line.find(':') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => continue,
+        };
+        let name = name.split(';').next().unwrap_or_default().to_uppercase();
+        match name.as_str() {
+            "BEGIN" if value == "VEVENT" => {
+                in_event = true;
+                start = None;
+                end = None;
+                summary.clear();
+            }
+            "END" if value == "VEVENT" => {
+                in_event = false;
+                if let Some(start) = start {
+                    // The end date is exclusive and defaults to the day after the start
+                    let end = end
+                        .filter(|end| *end > start)
+                        .unwrap_or_else(|| start.succ());
+                    let mut day = start;
+                    while day < end {
+                        result.push((day, summary.clone()));
+                        day = day.succ();
+                    }
+                }
+            }
+            "DTSTART" if in_event => start = Some(parse_date(value)?),
+            "DTEND" if in_event => end = Some(parse_date(value)?),
+            "SUMMARY" if in_event => summary = unescape_text(value),
+            _ => {}
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_all_day_and_multi_day_events() {
+        let content = "BEGIN:VCALENDAR\r\n\
+            VERSION:2.0\r\n\
+            BEGIN:VEVENT\r\n\
+            DTSTART;VALUE=DATE:20201225\r\n\
+            SUMMARY:Christmas\\, first\r\n  day\r\n\
+            END:VEVENT\r\n\
+            BEGIN:VEVENT\r\n\
+            DTSTART;VALUE=DATE:20201231\r\n\
+            DTEND;VALUE=DATE:20210102\r\n\
+            SUMMARY:New Year\r\n\
+            END:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+
+        let days = parse_days(content).unwrap();
+        assert_eq!(
+            vec![
+                (
+                    NaiveDate::from_ymd(2020, 12, 25),
+                    "Christmas, first day".to_string()
+                ),
+                (NaiveDate::from_ymd(2020, 12, 31), "New Year".to_string()),
+                (NaiveDate::from_ymd(2021, 1, 1), "New Year".to_string()),
+            ],
+            days
+        );
+    }
+
+    #[test]
+    fn times_are_rendered_in_utc() {
+        let events = vec![Event {
+            uid: "occupancy-1@roompla".to_string(),
+            start: NaiveDate::from_ymd(2020, 7, 8).and_hms(8, 0, 0),
+            end: NaiveDate::from_ymd(2020, 7, 8).and_hms(10, 30, 0),
+            summary: "Room 1.1".to_string(),
+            description: None,
+            location: Some("1.1".to_string()),
+            timezone: chrono_tz::Tz::UTC,
+        }];
+
+        let output = render("Roompla", &events);
+        assert!(output.contains("\r\nDTSTART:20200708T080000Z\r\n"));
+        assert!(output.contains("\r\nDTEND:20200708T103000Z\r\n"));
+        assert!(!output.contains("TZID"));
+    }
+
+    #[test]
+    fn times_are_rendered_in_the_timezone_of_the_event() {
+        let events = vec![Event {
+            uid: "occupancy-1@roompla".to_string(),
+            start: NaiveDate::from_ymd(2020, 7, 8).and_hms(8, 0, 0),
+            end: NaiveDate::from_ymd(2020, 7, 8).and_hms(10, 30, 0),
+            summary: "Room 1.1".to_string(),
+            description: None,
+            location: Some("1.1".to_string()),
+            timezone: chrono_tz::Europe::Berlin,
+        }];
+
+        let output = render("Roompla", &events);
+        assert!(output.contains("\r\nDTSTART;TZID=Europe/Berlin:20200708T100000\r\n"));
+        assert!(output.contains("\r\nDTEND;TZID=Europe/Berlin:20200708T123000\r\n"));
+
+        // The timezone is described with the changes to and from daylight saving time
+        let timezone =
+            &output[output.find("BEGIN:VTIMEZONE").unwrap()..output.find("END:VTIMEZONE").unwrap()];
+        assert!(timezone.contains("\r\nTZID:Europe/Berlin\r\n"));
+        assert!(timezone.contains(
+            "BEGIN:DAYLIGHT\r\nDTSTART:20200329T020000\r\n\
+            TZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200\r\nTZNAME:CEST\r\nEND:DAYLIGHT"
+        ));
+        assert!(timezone.contains(
+            "BEGIN:STANDARD\r\nDTSTART:20201025T030000\r\n\
+            TZOFFSETFROM:+0200\r\nTZOFFSETTO:+0100\r\nTZNAME:CET\r\nEND:STANDARD"
+        ));
+        assert!(output.find("END:VTIMEZONE").unwrap() < output.find("BEGIN:VEVENT").unwrap());
+    }
+}
@@ -31,12 +31,27 @@ use std::path::PathBuf;
 use structopt::StructOpt;
 
 pub mod api;
+pub mod audit;
+pub mod buildings;
+pub mod capacity;
+pub mod checkin;
 pub mod config;
 pub mod errors;
 pub mod export;
 pub mod extractors;
+pub mod holidays;
+pub mod ical;
 pub mod models;
+pub mod notifications;
+pub mod quotas;
+pub mod retention;
 pub mod schema;
+pub mod seats;
+pub mod waitlist;
+pub mod webhooks;
+
+#[cfg(test)]
+mod test_util;
 
 type DbPool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
 
@@ -73,6 +88,24 @@ async fn run_server(settings: Settings) -> Result<()> {
     let bind_address = format!("localhost:{}", &settings.service.port);
     let api_version = format!("/roompla/v{}", env!("CARGO_PKG_VERSION_MAJOR"),);
 
+    // Remove old occupancies in the background
+    actix_rt::spawn(retention::run_periodically(
+        db_pool.clone(),
+        settings.clone(),
+    ));
+    // Send queued notifications by mail in the background
+    actix_rt::spawn(notifications::run_periodically(
+        db_pool.clone(),
+        settings.smtp.clone(),
+    ));
+    // Release occupancies without check-in in the background
+    actix_rt::spawn(checkin::run_periodically(db_pool.clone(), settings.clone()));
+    // Deliver webhooks from the outbox in the background
+    actix_rt::spawn(webhooks::run_periodically(
+        db_pool.clone(),
+        settings.webhooks.clone(),
+    ));
+
     let db_pool = web::Data::new(db_pool);
 
     let settings = web::Data::new(settings);
@@ -115,7 +148,107 @@ async fn run_server(settings: Settings) -> Result<()> {
                         "/rooms/{room}/occupancies/{id}",
                         web::delete().to(api::delete_occupancy),
                     )
-                    .route("/rooms", web::get().to(api::all_rooms)),
+                    .route(
+                        "/rooms/{room}/occupancies/{id}/checkin",
+                        web::post().to(api::checkin_occupancy),
+                    )
+                    .route(
+                        "/rooms/{room}/occupancies/{id}/checkout",
+                        web::post().to(api::checkout_occupancy),
+                    )
+                    .route(
+                        "/rooms/{room}/calendar.ics",
+                        web::get().to(api::room_calendar),
+                    )
+                    .route("/rooms/{room}/series", web::put().to(api::add_series))
+                    .route("/rooms/{room}/waitlist", web::put().to(api::join_waitlist))
+                    .route(
+                        "/rooms/{room}/waitlist/{id}",
+                        web::delete().to(api::leave_waitlist),
+                    )
+                    .route(
+                        "/rooms/{room}/series/{id}",
+                        web::delete().to(api::delete_series),
+                    )
+                    .route("/audit", web::get().to(api::get_audit_log))
+                    .route("/trace", web::get().to(api::trace_contacts))
+                    .route(
+                        "/users/me/calendar-token",
+                        web::post().to(api::create_calendar_token),
+                    )
+                    .route(
+                        "/users/me/calendar-token",
+                        web::delete().to(api::delete_calendar_token),
+                    )
+                    .route("/users/me/calendar.ics", web::get().to(api::user_calendar))
+                    .route("/users/me/waitlist", web::get().to(api::user_waitlist))
+                    .route(
+                        "/users/me/notifications",
+                        web::get().to(api::user_notifications),
+                    )
+                    .route("/buildings", web::get().to(api::all_buildings))
+                    .route("/buildings/{building}", web::get().to(api::get_building))
+                    .route("/buildings/{building}", web::post().to(api::add_building))
+                    .route("/buildings/{building}", web::put().to(api::update_building))
+                    .route(
+                        "/buildings/{building}",
+                        web::delete().to(api::delete_building),
+                    )
+                    .route(
+                        "/buildings/{building}/floors",
+                        web::post().to(api::add_floor),
+                    )
+                    .route(
+                        "/buildings/{building}/floors/{id}",
+                        web::put().to(api::update_floor),
+                    )
+                    .route(
+                        "/buildings/{building}/floors/{id}",
+                        web::delete().to(api::delete_floor),
+                    )
+                    .route("/rooms", web::get().to(api::all_rooms))
+                    .route(
+                        "/search/availability",
+                        web::get().to(api::search_availability),
+                    )
+                    .route("/rooms/{room}", web::post().to(api::add_room))
+                    .route("/rooms/{room}", web::put().to(api::update_room))
+                    .route("/rooms/{room}", web::delete().to(api::delete_room))
+                    .route("/rooms/{room}/seats", web::get().to(api::get_seats))
+                    .route("/rooms/{room}/seats", web::post().to(api::add_seat))
+                    .route("/rooms/{room}/seats/{id}", web::put().to(api::update_seat))
+                    .route(
+                        "/rooms/{room}/seats/{id}",
+                        web::delete().to(api::delete_seat),
+                    )
+                    .route(
+                        "/rooms/{room}/capacity-rules",
+                        web::get().to(api::get_capacity_rules),
+                    )
+                    .route(
+                        "/rooms/{room}/capacity-rules",
+                        web::post().to(api::add_capacity_rule),
+                    )
+                    .route(
+                        "/rooms/{room}/capacity-rules/{id}",
+                        web::delete().to(api::delete_capacity_rule),
+                    )
+                    .route(
+                        "/rooms/{room}/checkin-token",
+                        web::post().to(api::create_checkin_token),
+                    )
+                    .route(
+                        "/rooms/{room}/checkin-token",
+                        web::delete().to(api::delete_checkin_token),
+                    )
+                    .route(
+                        "/rooms/{room}/closures/{day}",
+                        web::put().to(api::add_closure),
+                    )
+                    .route(
+                        "/rooms/{room}/closures/{day}",
+                        web::delete().to(api::delete_closure),
+                    ),
             )
     })
     .bind(bind_address)?
@@ -191,6 +324,69 @@ enum Command {
         #[structopt(short, long, help = "How many weeks to include", default_value = "2")]
         weeks: u8,
     },
+    Audit {
+        #[structopt(help = "The output CSV file")]
+        file: String,
+        #[structopt(short, long, help = "How many weeks to include", default_value = "2")]
+        weeks: u8,
+        #[structopt(long, help = "Only include changes to occupancies of this user")]
+        user: Option<String>,
+        #[structopt(long, help = "Only include changes performed by this user")]
+        actor: Option<String>,
+        #[structopt(long, help = "Only include changes to occupancies of this room")]
+        room: Option<String>,
+    },
+    Trace {
+        #[structopt(help = "The output CSV file")]
+        file: String,
+        #[structopt(help = "The ID of the user to find the contacts for")]
+        user: String,
+        #[structopt(long, help = "First day (YYYY-MM-DD) of the time window")]
+        from: chrono::NaiveDate,
+        #[structopt(long, help = "Last day (YYYY-MM-DD) of the time window")]
+        to: chrono::NaiveDate,
+        #[structopt(
+            long,
+            default_value = "UTC",
+            help = "Timezone in which the days of the time window are given"
+        )]
+        timezone: chrono_tz::Tz,
+    },
+    ImportHolidays {
+        #[structopt(help = "The iCalendar (.ics) or CSV file with the holidays")]
+        file: String,
+        #[structopt(
+            long,
+            help = "Only close this room on the holidays instead of all rooms"
+        )]
+        room: Option<String>,
+    },
+    WebhookDeliveries {
+        #[structopt(help = "The output CSV file")]
+        file: String,
+        #[structopt(
+            long,
+            help = "List all webhook deliveries instead of only the ones that failed permanently"
+        )]
+        all: bool,
+    },
+    RedeliverWebhooks {
+        #[structopt(help = "The IDs of the webhook deliveries to send again")]
+        ids: Vec<i32>,
+        #[structopt(
+            long,
+            help = "Send all webhook deliveries that failed permanently again"
+        )]
+        failed: bool,
+    },
+    Purge {
+        #[structopt(
+            short,
+            long,
+            help = "Purge occupancies that ended more than this number of days ago (overrides the configuration)"
+        )]
+        days: Option<u32>,
+    },
 }
 
 #[derive(StructOpt)]
@@ -241,6 +437,102 @@ async fn main() -> Result<()> {
                 }
                 .await
             }
+            Command::Audit {
+                file,
+                weeks,
+                user,
+                actor,
+                room,
+            } => {
+                let filter = audit::AuditFilter {
+                    user_id: user,
+                    actor,
+                    room,
+                    ..Default::default()
+                };
+                match audit::to_csv(&file, weeks, filter, settings) {
+                    Ok(result) => futures::future::ok(result),
+                    Err(e) => {
+                        error!("Error when exporting audit log to CSV: {:?}", e);
+                        futures::future::err(std::io::Error::new(
+                            ErrorKind::Other,
+                            format!("{:?}", e),
+                        ))
+                    }
+                }
+                .await
+            }
+            Command::Trace {
+                file,
+                user,
+                from,
+                to,
+                timezone,
+            } => {
+                match export::trace_to_csv(&file, &user, from, to, timezone, settings) {
+                    Ok(result) => futures::future::ok(result),
+                    Err(e) => {
+                        error!("Error when exporting contacts to CSV: {:?}", e);
+                        futures::future::err(std::io::Error::new(
+                            ErrorKind::Other,
+                            format!("{:?}", e),
+                        ))
+                    }
+                }
+                .await
+            }
+            Command::ImportHolidays { file, room } => {
+                match holidays::import(&file, room, settings) {
+                    Ok(result) => futures::future::ok(result),
+                    Err(e) => {
+                        error!("Error when importing holidays: {:?}", e);
+                        futures::future::err(std::io::Error::new(
+                            ErrorKind::Other,
+                            format!("{:?}", e),
+                        ))
+                    }
+                }
+                .await
+            }
+            Command::WebhookDeliveries { file, all } => {
+                match webhooks::to_csv(&file, all, settings) {
+                    Ok(result) => futures::future::ok(result),
+                    Err(e) => {
+                        error!("Error when exporting webhook deliveries to CSV: {:?}", e);
+                        futures::future::err(std::io::Error::new(
+                            ErrorKind::Other,
+                            format!("{:?}", e),
+                        ))
+                    }
+                }
+                .await
+            }
+            Command::RedeliverWebhooks { ids, failed } => {
+                match webhooks::redeliver(ids, failed, settings).await {
+                    Ok(result) => futures::future::ok(result),
+                    Err(e) => {
+                        error!("Error when sending webhooks again: {:?}", e);
+                        futures::future::err(std::io::Error::new(
+                            ErrorKind::Other,
+                            format!("{:?}", e),
+                        ))
+                    }
+                }
+                .await
+            }
+            Command::Purge { days } => {
+                match retention::purge_once(days, settings) {
+                    Ok(result) => futures::future::ok(result),
+                    Err(e) => {
+                        error!("Error when purging old occupancies: {:?}", e);
+                        futures::future::err(std::io::Error::new(
+                            ErrorKind::Other,
+                            format!("{:?}", e),
+                        ))
+                    }
+                }
+                .await
+            }
         }
     } else {
         // Directly run server
@@ -1,11 +1,268 @@
 use super::schema::*;
-use chrono::prelude::*;
+use crate::config::Settings;
+use chrono::{prelude::*, Duration};
+use std::{fmt::Display, str::FromStr};
+
+no_arg_sql_function!(
+    last_insert_rowid,
+    diesel::sql_types::Integer,
+    "Represents the SQL last_insert_rowid() function"
+);
+
+/// Roles are ordered by their privileges, a role includes all rights of the
+/// roles before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    User,
+    RoomManager,
+    Admin,
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Role::User
+    }
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::RoomManager => "room-manager",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "room-manager" => Ok(Role::RoomManager),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Unknown role \"{}\"", s)),
+        }
+    }
+}
 
 #[derive(Queryable, Insertable, Serialize)]
 pub struct Room {
     pub id: String,
     pub max_occupancy: i32,
     pub timezone: Option<String>,
+    /// Length of the booking slots in minutes, if different from the global configuration
+    pub slot_minutes: Option<i32>,
+    /// Maximum hours a user can book this room per week
+    pub max_hours_per_week: Option<i32>,
+    /// Maximum number of occupancies of this room a user can have in the future
+    pub max_future_bookings: Option<i32>,
+    /// Maximum number of days in advance this room can be booked
+    pub max_days_ahead: Option<i32>,
+    /// Token users need to check in, e.g. from a QR code in the room
+    #[serde(skip)]
+    pub checkin_token: Option<String>,
+    /// The floor of a building the room is on
+    pub floor_id: Option<i32>,
+    /// Comma separated list of attributes, e.g. "projector,quiet zone"
+    #[serde(serialize_with = "serialize_attributes")]
+    pub attributes: String,
+}
+
+/// Changes of the properties of a room, fields that are `None` are not changed.
+#[derive(AsChangeset)]
+#[table_name = "rooms"]
+pub struct RoomChanges {
+    pub max_occupancy: i32,
+    pub timezone: Option<Option<String>>,
+    pub slot_minutes: Option<Option<i32>>,
+    pub max_hours_per_week: Option<Option<i32>>,
+    pub max_future_bookings: Option<Option<i32>>,
+    pub max_days_ahead: Option<Option<i32>>,
+    pub floor_id: Option<Option<i32>>,
+    pub attributes: Option<String>,
+}
+
+/// Split a comma separated list of attributes.
+pub fn split_attributes(attributes: &str) -> Vec<String> {
+    attributes
+        .split(',')
+        .filter(|a| !a.is_empty())
+        .map(|a| a.to_string())
+        .collect()
+}
+
+/// Serialize a comma separated list of attributes as list.
+fn serialize_attributes<S>(attributes: &str, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(split_attributes(attributes))
+}
+
+impl Room {
+    /// The timezone of the room or UTC if no valid timezone is configured.
+    pub fn parsed_timezone(&self) -> chrono_tz::Tz {
+        if let Some(tz) = &self.timezone {
+            match tz.parse() {
+                Ok(tz) => tz,
+                Err(e) => {
+                    warn!("Invalid timezone for room {}: {}", self.id, e);
+                    chrono_tz::Tz::UTC
+                }
+            }
+        } else {
+            chrono_tz::Tz::UTC
+        }
+    }
+
+    /// The attributes of the room as list.
+    pub fn attribute_list(&self) -> Vec<String> {
+        split_attributes(&self.attributes)
+    }
+
+    /// The length of the booking slots of this room.
+    pub fn slot_duration(&self, settings: &Settings) -> Duration {
+        let minutes = self
+            .slot_minutes
+            .map(i64::from)
+            .unwrap_or(settings.booking.slot_minutes);
+        Duration::minutes(minutes)
+    }
+}
+
+#[derive(Queryable, Insertable, Serialize, Debug)]
+pub struct Building {
+    pub id: String,
+    pub name: String,
+    /// Maximum number of people in all rooms of the building at the same time
+    pub max_occupancy: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[table_name = "floors"]
+pub struct NewFloor {
+    pub building: String,
+    pub name: String,
+    pub level: i32,
+}
+
+#[derive(Queryable, Serialize, Debug)]
+pub struct Floor {
+    pub id: i32,
+    pub building: String,
+    pub name: String,
+    /// Used to order the floors of a building, e.g. 0 for the ground floor
+    pub level: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "opening_hours"]
+pub struct NewOpeningHours {
+    pub room: String,
+    pub weekday: String,
+    pub opens: NaiveTime,
+    pub closes: NaiveTime,
+}
+
+/// Time of the day in the timezone of the room in which it can be booked.
+#[derive(Queryable, Debug)]
+pub struct OpeningHours {
+    pub id: i32,
+    pub room: String,
+    /// Weekday as two-letter code, e.g. "MO"
+    pub weekday: String,
+    pub opens: NaiveTime,
+    /// 00:00 if the room closes at midnight at the end of the day
+    pub closes: NaiveTime,
+}
+
+/// Get the local date and time at which a time range on the given day ends, where an end
+/// time of 00:00 means midnight at the end of the day.
+pub fn end_time_on(day: NaiveDate, end: NaiveTime) -> NaiveDateTime {
+    if end == NaiveTime::from_hms(0, 0, 0) {
+        day.succ().and_time(end)
+    } else {
+        day.and_time(end)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "capacity_rules"]
+pub struct NewCapacityRule {
+    pub room: String,
+    pub max_occupancy: i32,
+    pub valid_from: Option<NaiveDate>,
+    pub valid_to: Option<NaiveDate>,
+    pub weekdays: String,
+    pub from_time: Option<NaiveTime>,
+    pub to_time: Option<NaiveTime>,
+}
+
+/// A maximum occupancy of a room that replaces the general one during the validity period.
+///
+/// Dates and times are given in the timezone of the room.
+#[derive(Queryable, Debug)]
+pub struct CapacityRule {
+    pub id: i32,
+    pub room: String,
+    pub max_occupancy: i32,
+    /// First day the rule is valid
+    pub valid_from: Option<NaiveDate>,
+    /// Last day the rule is valid
+    pub valid_to: Option<NaiveDate>,
+    /// Comma separated list of weekday codes ("MO,TU"), all weekdays if empty
+    pub weekdays: String,
+    /// Start of the time window on each day the rule is valid
+    pub from_time: Option<NaiveTime>,
+    /// End of the time window on each day the rule is valid (exclusive)
+    pub to_time: Option<NaiveTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "closures"]
+pub struct NewClosure {
+    pub room: String,
+    pub day: NaiveDate,
+    pub reason: Option<String>,
+}
+
+/// A day (in the timezone of the room) on which the room can't be booked at all.
+#[derive(Queryable, Serialize, Debug)]
+pub struct Closure {
+    #[serde(skip)]
+    pub id: i32,
+    #[serde(skip)]
+    pub room: String,
+    pub day: NaiveDate,
+    pub reason: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "holidays"]
+pub struct NewHoliday {
+    pub room: Option<String>,
+    pub day: NaiveDate,
+    pub name: String,
+}
+
+/// A public holiday on which a room (or all rooms if no room is given) can't be booked.
+#[derive(Queryable, Serialize, Clone, Debug)]
+pub struct Holiday {
+    #[serde(skip)]
+    pub id: i32,
+    #[serde(skip)]
+    pub room: Option<String>,
+    pub day: NaiveDate,
+    pub name: String,
 }
 
 #[derive(Queryable, Insertable)]
@@ -14,6 +271,7 @@ pub struct User {
     pub display_name: String,
     pub contact_info: String,
     pub password_hash: Option<String>,
+    pub role: String,
 }
 
 #[derive(Insertable, Serialize, Clone)]
@@ -25,6 +283,8 @@ pub struct NewOccupancy {
     pub user_name: String,
     pub user_contact: String,
     pub room: String,
+    pub series_id: Option<i32>,
+    pub seat_id: Option<i32>,
 }
 
 #[derive(QueryableByName, Queryable, Serialize, Debug)]
@@ -37,4 +297,188 @@ pub struct Occupancy {
     pub user_name: String,
     pub user_contact: String,
     pub room: String,
+    pub modified_by: Option<String>,
+    pub series_id: Option<i32>,
+    /// Time the user checked in (UTC)
+    pub checked_in_at: Option<NaiveDateTime>,
+    /// Time the user actually left the room (UTC)
+    pub checked_out_at: Option<NaiveDateTime>,
+    /// The booked seat, if the user booked a specific one
+    pub seat_id: Option<i32>,
+}
+
+impl Occupancy {
+    /// The time the user actually arrived, or the planned start if the user did not check in.
+    pub fn actual_start(&self) -> NaiveDateTime {
+        self.checked_in_at.unwrap_or(self.start)
+    }
+
+    /// The time the user actually left, or the planned end if the user did not check out.
+    pub fn actual_end(&self) -> NaiveDateTime {
+        self.checked_out_at.map_or(self.end, |t| t.min(self.end))
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "seats"]
+pub struct NewSeat {
+    pub room: String,
+    pub label: String,
+    pub attributes: String,
+}
+
+#[derive(Queryable, Debug)]
+pub struct Seat {
+    pub id: i32,
+    pub room: String,
+    pub label: String,
+    /// Comma separated list of attributes, e.g. "docking station,window"
+    pub attributes: String,
+}
+
+impl Seat {
+    /// The attributes of the seat as list.
+    pub fn attribute_list(&self) -> Vec<String> {
+        split_attributes(&self.attributes)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "series"]
+pub struct NewSeries {
+    pub room: String,
+    pub user_id: String,
+    pub first_start: NaiveDateTime,
+    pub first_end: NaiveDateTime,
+    pub weekdays: String,
+    pub until: NaiveDate,
+}
+
+#[derive(Queryable, Serialize, Debug)]
+pub struct Series {
+    pub id: i32,
+    pub room: String,
+    pub user_id: String,
+    pub first_start: NaiveDateTime,
+    pub first_end: NaiveDateTime,
+    pub weekdays: String,
+    pub until: NaiveDate,
+}
+
+#[derive(Insertable)]
+#[table_name = "audit_log"]
+pub struct NewAuditLogEntry {
+    pub changed_at: NaiveDateTime,
+    pub actor: String,
+    pub action: String,
+    pub occupancy_id: i32,
+    pub room: String,
+    pub user_id: String,
+    pub old_start: Option<NaiveDateTime>,
+    pub old_end: Option<NaiveDateTime>,
+    pub new_start: Option<NaiveDateTime>,
+    pub new_end: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Serialize, Debug)]
+pub struct AuditLogEntry {
+    pub id: i32,
+    pub changed_at: NaiveDateTime,
+    pub actor: String,
+    pub action: String,
+    pub occupancy_id: i32,
+    pub room: String,
+    pub user_id: String,
+    pub old_start: Option<NaiveDateTime>,
+    pub old_end: Option<NaiveDateTime>,
+    pub new_start: Option<NaiveDateTime>,
+    pub new_end: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "calendar_tokens"]
+pub struct CalendarToken {
+    pub token: String,
+    pub user_id: String,
+    pub created_at: NaiveDateTime,
+    /// The role of the user when the token was created
+    pub role: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "waitlist"]
+pub struct NewWaitlistEntry {
+    pub room: String,
+    pub user_id: String,
+    pub user_name: String,
+    pub user_contact: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+/// A user waiting for a time range in a full room.
+#[derive(Queryable, Serialize, Debug)]
+pub struct WaitlistEntry {
+    pub id: i32,
+    pub room: String,
+    pub user_id: String,
+    pub user_name: String,
+    pub user_contact: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "notifications"]
+pub struct NewNotification {
+    pub user_id: String,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Serialize, Debug)]
+pub struct Notification {
+    pub id: i32,
+    pub user_id: String,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+    /// Time the notification was sent by mail
+    pub sent_at: Option<NaiveDateTime>,
+    #[serde(skip)]
+    pub attempts: i32,
+    #[serde(skip)]
+    pub last_error: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "webhook_deliveries"]
+pub struct NewWebhookDelivery {
+    pub endpoint: String,
+    pub event: String,
+    pub payload: String,
+    pub created_at: NaiveDateTime,
+    pub next_attempt_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Debug)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    /// URL of the endpoint
+    pub endpoint: String,
+    pub event: String,
+    /// JSON payload that is sent to the endpoint
+    pub payload: String,
+    pub created_at: NaiveDateTime,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    /// HTTP status code of the last response
+    pub last_status: Option<i32>,
+    pub last_error: Option<String>,
 }
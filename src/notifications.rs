@@ -0,0 +1,272 @@
+use crate::{
+    config::{MessageTemplate, Settings, SmtpSecurity, SmtpSettings},
+    models::{NewNotification, Notification, Occupancy, Room},
+    DbPool,
+};
+use anyhow::Result;
+use chrono::prelude::*;
+use diesel::prelude::*;
+use lettre::{
+    smtp::authentication::Credentials, ClientSecurity, ClientTlsParameters, SmtpClient,
+    SmtpTransport, Transport,
+};
+use lettre_email::EmailBuilder;
+
+/// The maximum number of notifications returned for a user.
+const MAX_USER_NOTIFICATIONS: i64 = 50;
+
+/// The maximum number of notifications sent each time the queue is processed.
+const MAX_BATCH_SIZE: i64 = 100;
+
+/// Timeout for the connection to the mail server in seconds.
+const SMTP_TIMEOUT_SECONDS: u64 = 30;
+
+/// A change of occupancies the owner is notified about.
+#[derive(Debug, Clone, Copy)]
+pub enum OccupancyChange {
+    Created,
+    /// The occupancy has been moved, the previous time range is given (UTC)
+    Updated {
+        old_start: NaiveDateTime,
+        old_end: NaiveDateTime,
+    },
+    Deleted,
+    /// A waiting user has been booked when capacity was freed
+    BookedFromWaitlist,
+}
+
+/// Add a notification for the user to the queue.
+pub fn enqueue<Conn>(
+    conn: &Conn,
+    user_id: &str,
+    recipient: &str,
+    subject: &str,
+    body: &str,
+) -> QueryResult<()>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    diesel::insert_into(crate::schema::notifications::table)
+        .values(NewNotification {
+            user_id: user_id.to_string(),
+            recipient: recipient.to_string(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+            created_at: Utc::now().naive_utc(),
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Format a time range (UTC) in the local time of the room.
+fn format_time_range(tz: chrono_tz::Tz, start: NaiveDateTime, end: NaiveDateTime) -> String {
+    let start = tz.from_utc_datetime(&start);
+    let end = tz.from_utc_datetime(&end);
+    if start.date() == end.date() {
+        format!(
+            "{} - {} ({})",
+            start.format("%a, %Y-%m-%d %H:%M"),
+            end.format("%H:%M"),
+            tz.name()
+        )
+    } else {
+        format!(
+            "{} - {} ({})",
+            start.format("%a, %Y-%m-%d %H:%M"),
+            end.format("%a, %Y-%m-%d %H:%M"),
+            tz.name()
+        )
+    }
+}
+
+/// Replace all placeholders of the template text.
+fn fill_template(text: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(text.to_string(), |text, (key, value)| {
+        text.replace(&format!("{{{}}}", key), value)
+    })
+}
+
+/// Queue a notification about the changed occupancies for their owner.
+///
+/// All occupancies must belong to the same user and room, they are listed in a single message.
+/// `actor` is the name of the user who performed the change.
+pub fn notify_occupancy_change<Conn>(
+    conn: &Conn,
+    room: &Room,
+    settings: &Settings,
+    change: OccupancyChange,
+    occupancies: &[Occupancy],
+    actor: &str,
+) -> QueryResult<()>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    let owner = if let Some(owner) = occupancies.first() {
+        owner
+    } else {
+        return Ok(());
+    };
+    // Anonymized occupancies have no contact information
+    if owner.user_contact.is_empty() {
+        return Ok(());
+    }
+
+    let tz = room.parsed_timezone();
+    let times: Vec<String> = occupancies
+        .iter()
+        .map(|o| format_time_range(tz, o.actual_start(), o.actual_end()))
+        .collect();
+    let times = times.join("\n");
+    let (template, old_times): (&MessageTemplate, String) = match change {
+        OccupancyChange::Created => (&settings.notifications.created, String::default()),
+        OccupancyChange::Updated { old_start, old_end } => (
+            &settings.notifications.updated,
+            format_time_range(tz, old_start, old_end),
+        ),
+        OccupancyChange::Deleted => (&settings.notifications.deleted, String::default()),
+        OccupancyChange::BookedFromWaitlist => {
+            (&settings.notifications.waitlist, String::default())
+        }
+    };
+    let values = [
+        ("room", room.id.as_str()),
+        ("name", owner.user_name.as_str()),
+        ("actor", actor),
+        ("times", times.as_str()),
+        ("old_times", old_times.as_str()),
+    ];
+
+    enqueue(
+        conn,
+        &owner.user_id,
+        &owner.user_contact,
+        &fill_template(&template.subject, &values),
+        &fill_template(&template.body, &values),
+    )
+}
+
+/// Get the most recent notifications of the user, newest first.
+pub fn find_for_user<Conn>(conn: &Conn, user_id: &str) -> QueryResult<Vec<Notification>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::notifications::dsl;
+
+    dsl::notifications
+        .filter(dsl::user_id.eq(user_id))
+        .order((dsl::created_at.desc(), dsl::id.desc()))
+        .limit(MAX_USER_NOTIFICATIONS)
+        .load(conn)
+}
+
+fn create_transport(host: &str, settings: &SmtpSettings) -> Result<SmtpTransport> {
+    let tls_parameters = || -> Result<ClientTlsParameters> {
+        Ok(ClientTlsParameters::new(
+            host.to_string(),
+            native_tls::TlsConnector::new()?,
+        ))
+    };
+    let security = match settings.security {
+        SmtpSecurity::None => ClientSecurity::None,
+        SmtpSecurity::StartTls => ClientSecurity::Required(tls_parameters()?),
+        SmtpSecurity::Tls => ClientSecurity::Wrapper(tls_parameters()?),
+    };
+
+    let mut client = SmtpClient::new((host, settings.port), security)?
+        .timeout(Some(std::time::Duration::from_secs(SMTP_TIMEOUT_SECONDS)));
+    if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+        client = client.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    Ok(client.transport())
+}
+
+fn send_mail(transport: &mut SmtpTransport, from: &str, notification: &Notification) -> Result<()> {
+    let email = EmailBuilder::new()
+        .to(notification.recipient.as_str())
+        .from(from)
+        .subject(notification.subject.as_str())
+        .text(notification.body.as_str())
+        .build()?;
+    transport.send(email.into())?;
+    Ok(())
+}
+
+/// Send all queued notifications that have not been sent yet by mail.
+///
+/// Failed notifications stay in the queue and are retried the next time, until the
+/// configured maximum number of attempts is reached. Returns the number of sent mails.
+pub fn send_pending<Conn>(conn: &Conn, settings: &SmtpSettings) -> Result<usize>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::notifications::dsl;
+
+    let host = if let Some(host) = &settings.host {
+        host
+    } else {
+        return Ok(0);
+    };
+
+    let pending: Vec<Notification> = dsl::notifications
+        .filter(dsl::sent_at.is_null())
+        .filter(dsl::attempts.lt(settings.max_attempts))
+        .order(dsl::id)
+        .limit(MAX_BATCH_SIZE)
+        .load(conn)?;
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let mut transport = create_transport(host, settings)?;
+    let mut sent = 0;
+    for n in pending {
+        match send_mail(&mut transport, &settings.from, &n) {
+            Ok(()) => {
+                diesel::update(dsl::notifications.find(n.id))
+                    .set(dsl::sent_at.eq(Utc::now().naive_utc()))
+                    .execute(conn)?;
+                sent += 1;
+            }
+            Err(e) => {
+                warn!(
+                    "Could not send notification {} to {}: {}",
+                    n.id, n.recipient, e
+                );
+                diesel::update(dsl::notifications.find(n.id))
+                    .set((
+                        dsl::attempts.eq(n.attempts + 1),
+                        dsl::last_error.eq(e.to_string()),
+                    ))
+                    .execute(conn)?;
+            }
+        }
+    }
+    Ok(sent)
+}
+
+/// Send the queued notifications by mail in the configured interval, forever.
+pub async fn run_periodically(db_pool: DbPool, settings: SmtpSettings) {
+    if settings.host.is_none() {
+        info!("No SMTP server configured, notifications are not sent by mail");
+        return;
+    }
+
+    let period = std::time::Duration::from_secs(settings.interval_seconds.max(1));
+    let mut interval = actix_rt::time::interval(period);
+    loop {
+        interval.tick().await;
+        // Sending mails blocks, so it runs on the thread pool instead of the event loop
+        let db_pool = db_pool.clone();
+        let smtp = settings.clone();
+        let result = actix_web::web::block(move || -> Result<usize> {
+            let conn = db_pool.get()?;
+            send_pending(&conn, &smtp)
+        })
+        .await;
+        match result {
+            Ok(sent) if sent > 0 => info!("Sent {} notifications by mail", sent),
+            Ok(_) => {}
+            Err(e) => error!("Error when sending notifications: {:?}", e),
+        }
+    }
+}
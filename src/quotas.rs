@@ -0,0 +1,191 @@
+use crate::{
+    config::Settings,
+    errors::ServiceError,
+    models::{Occupancy, Room},
+};
+use chrono::{prelude::*, Duration};
+use diesel::prelude::*;
+use std::collections::BTreeSet;
+
+/// Limits for the occupancies of a single user.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Quota {
+    pub max_hours_per_week: Option<i64>,
+    pub max_future_bookings: Option<i64>,
+    pub max_days_ahead: Option<i64>,
+}
+
+impl Quota {
+    /// The quota for all rooms from the configuration.
+    pub fn from_settings(settings: &Settings) -> Quota {
+        Quota {
+            max_hours_per_week: settings.booking.max_hours_per_week.map(i64::from),
+            max_future_bookings: settings.booking.max_future_bookings.map(i64::from),
+            max_days_ahead: settings.booking.max_days_ahead.map(i64::from),
+        }
+    }
+
+    /// The additional quota of a single room.
+    pub fn from_room(room: &Room) -> Quota {
+        Quota {
+            max_hours_per_week: room.max_hours_per_week.map(i64::from),
+            max_future_bookings: room.max_future_bookings.map(i64::from),
+            max_days_ahead: room.max_days_ahead.map(i64::from),
+        }
+    }
+}
+
+/// Get the occupancies of the user (optionally only in the given room) that overlap with the time range.
+fn find_user_occupancies<Conn>(
+    conn: &Conn,
+    user_id: &str,
+    room: Option<&str>,
+    start: NaiveDateTime,
+    end: Option<NaiveDateTime>,
+    ignore_id: Option<i32>,
+) -> QueryResult<Vec<Occupancy>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::occupancies::dsl;
+
+    let mut query = dsl::occupancies
+        .filter(dsl::user_id.eq(user_id))
+        .filter(dsl::end.gt(start))
+        .into_boxed();
+    if let Some(end) = end {
+        query = query.filter(dsl::start.lt(end));
+    }
+    if let Some(room) = room {
+        query = query.filter(dsl::room.eq(room));
+    }
+    if let Some(ignore_id) = ignore_id {
+        query = query.filter(dsl::id.ne(ignore_id));
+    }
+    query.load(conn)
+}
+
+/// Get the number of minutes the time range overlaps with the other time range.
+fn overlap_minutes(
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    other_start: NaiveDateTime,
+    other_end: NaiveDateTime,
+) -> i64 {
+    let overlap = end.min(other_end) - start.max(other_start);
+    overlap.num_minutes().max(0)
+}
+
+fn check_quota<Conn>(
+    conn: &Conn,
+    quota: &Quota,
+    room: Option<&str>,
+    tz: chrono_tz::Tz,
+    user_id: &str,
+    new_ranges: &[(NaiveDateTime, NaiveDateTime)],
+    ignore_id: Option<i32>,
+) -> Result<(), ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    let now = Utc::now().naive_utc();
+    let scope = room
+        .map(|r| format!("in room {}", r))
+        .unwrap_or_else(|| "in all rooms".to_string());
+
+    if let Some(max_days_ahead) = quota.max_days_ahead {
+        let horizon = now + Duration::days(max_days_ahead);
+        if new_ranges.iter().any(|(start, _)| *start > horizon) {
+            return Err(ServiceError::QuotaExceeded(format!(
+                "Occupancies {} can be booked at most {} days in advance.",
+                scope, max_days_ahead
+            )));
+        }
+    }
+
+    if let Some(max_future_bookings) = quota.max_future_bookings {
+        let existing = find_user_occupancies(conn, user_id, room, now, None, ignore_id)?.len();
+        let added = new_ranges.iter().filter(|(_, end)| *end > now).count();
+        if (existing + added) as i64 > max_future_bookings {
+            return Err(ServiceError::QuotaExceeded(format!(
+                "You can have at most {} upcoming occupancies {}, but already have {}.",
+                max_future_bookings, scope, existing
+            )));
+        }
+    }
+
+    if let Some(max_hours_per_week) = quota.max_hours_per_week {
+        // Weeks start on Monday in the local time of the room
+        let mut weeks = BTreeSet::new();
+        for (start, end) in new_ranges {
+            for t in &[*start, *end - Duration::nanoseconds(1)] {
+                let day = tz.from_utc_datetime(t).naive_local().date();
+                weeks.insert(day - Duration::days(day.weekday().num_days_from_monday() as i64));
+            }
+        }
+        for monday in weeks {
+            let week_start = tz
+                .from_local_datetime(&monday.and_hms(0, 0, 0))
+                .earliest()
+                .map_or(monday.and_hms(0, 0, 0), |t| t.naive_utc());
+            let week_end = week_start + Duration::weeks(1);
+
+            let existing: i64 =
+                find_user_occupancies(conn, user_id, room, week_start, Some(week_end), ignore_id)?
+                    .iter()
+                    .map(|o| overlap_minutes(o.start, o.end, week_start, week_end))
+                    .sum();
+            let added: i64 = new_ranges
+                .iter()
+                .map(|(start, end)| overlap_minutes(*start, *end, week_start, week_end))
+                .sum();
+            if existing + added > max_hours_per_week * 60 {
+                return Err(ServiceError::QuotaExceeded(format!(
+                    "You can book at most {} hours per week {}, but would have {:.1} hours in the week from {}.",
+                    max_hours_per_week,
+                    scope,
+                    (existing + added) as f64 / 60.0,
+                    monday
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that adding the new time ranges (in UTC) as occupancies of the user does not exceed
+/// the global quota or the quota of the room.
+///
+/// The occupancy with the ID `ignore_id` is not counted, e.g. because it is about to be changed.
+pub fn check_quotas<Conn>(
+    conn: &Conn,
+    room: &Room,
+    settings: &Settings,
+    user_id: &str,
+    new_ranges: &[(NaiveDateTime, NaiveDateTime)],
+    ignore_id: Option<i32>,
+) -> Result<(), ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    let tz = room.parsed_timezone();
+    check_quota(
+        conn,
+        &Quota::from_settings(settings),
+        None,
+        tz,
+        user_id,
+        new_ranges,
+        ignore_id,
+    )?;
+    check_quota(
+        conn,
+        &Quota::from_room(room),
+        Some(&room.id),
+        tz,
+        user_id,
+        new_ranges,
+        ignore_id,
+    )
+}
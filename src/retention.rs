@@ -0,0 +1,494 @@
+use crate::{
+    config::{RetentionMode, Settings},
+    DbPool,
+};
+use anyhow::Result;
+use chrono::{prelude::*, Duration};
+use diesel::{prelude::*, r2d2::ConnectionManager, sqlite::SqliteConnection};
+
+/// User ID and name of anonymized occupancies.
+pub const ANONYMOUS: &str = "<anonym>";
+
+/// Number of rows that have been deleted or anonymized by [`purge`].
+#[derive(Default, Debug)]
+pub struct Purged {
+    pub occupancies: usize,
+    pub audit_entries: usize,
+    pub webhook_deliveries: usize,
+    pub notifications: usize,
+    pub waitlist_entries: usize,
+    pub series: usize,
+    pub calendar_tokens: usize,
+}
+
+/// Delete or anonymize all occupancies that ended more than `max_age_days` ago,
+/// together with the audit log entries and the series about them.
+///
+/// Calendar feed tokens older than `max_age_days` are deleted once the user has no
+/// occupancies left that have not been purged.
+///
+/// Webhook deliveries and notifications older than `max_age_days` are deleted in both modes,
+/// once they have been delivered or failed permanently, and so are waitlist entries for time
+/// ranges that ended before.
+pub fn purge<Conn>(conn: &Conn, max_age_days: u32, settings: &Settings) -> QueryResult<Purged>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::audit_log;
+    use crate::schema::calendar_tokens;
+    use crate::schema::notifications;
+    use crate::schema::occupancies::dsl;
+    use crate::schema::series;
+    use crate::schema::waitlist;
+    use crate::schema::webhook_deliveries;
+
+    let cutoff = (Utc::now() - Duration::days(max_age_days as i64)).naive_utc();
+
+    // Audit log entries contain the user and the times of an occupancy, so they are purged
+    // when all times they mention are older than the cutoff
+    let old_audit_entry = audit_log::dsl::changed_at
+        .lt(cutoff)
+        .and(
+            audit_log::dsl::old_end
+                .is_null()
+                .or(audit_log::dsl::old_end.lt(cutoff)),
+        )
+        .and(
+            audit_log::dsl::new_end
+                .is_null()
+                .or(audit_log::dsl::new_end.lt(cutoff)),
+        );
+
+    // The last day of a series is in the timezone of the room, so all occurrences of series
+    // that ended the day before the cutoff are older than the cutoff
+    let series_cutoff = cutoff.date().pred();
+
+    // Without a mail server, notifications are never sent and can be deleted right away
+    let max_mail_attempts = if settings.smtp.host.is_some() {
+        settings.smtp.max_attempts
+    } else {
+        0
+    };
+
+    conn.transaction(|| {
+        let mut purged = Purged::default();
+        match settings.retention.mode {
+            RetentionMode::Delete => {
+                purged.occupancies =
+                    diesel::delete(dsl::occupancies.filter(dsl::end.lt(cutoff))).execute(conn)?;
+                purged.audit_entries =
+                    diesel::delete(audit_log::dsl::audit_log.filter(old_audit_entry))
+                        .execute(conn)?;
+                purged.series = diesel::delete(
+                    series::dsl::series.filter(series::dsl::until.lt(series_cutoff)),
+                )
+                .execute(conn)?;
+            }
+            RetentionMode::Anonymize => {
+                purged.occupancies = diesel::update(
+                    dsl::occupancies
+                        .filter(dsl::end.lt(cutoff))
+                        .filter(dsl::user_id.ne(ANONYMOUS)),
+                )
+                .set((
+                    dsl::user_id.eq(ANONYMOUS),
+                    dsl::user_name.eq(ANONYMOUS),
+                    dsl::user_contact.eq(""),
+                    dsl::modified_by.eq(None::<String>),
+                ))
+                .execute(conn)?;
+                purged.audit_entries = diesel::update(
+                    audit_log::dsl::audit_log
+                        .filter(old_audit_entry)
+                        .filter(audit_log::dsl::user_id.ne(ANONYMOUS)),
+                )
+                .set((
+                    audit_log::dsl::user_id.eq(ANONYMOUS),
+                    audit_log::dsl::actor.eq(ANONYMOUS),
+                ))
+                .execute(conn)?;
+                purged.series = diesel::update(
+                    series::dsl::series
+                        .filter(series::dsl::until.lt(series_cutoff))
+                        .filter(series::dsl::user_id.ne(ANONYMOUS)),
+                )
+                .set(series::dsl::user_id.eq(ANONYMOUS))
+                .execute(conn)?;
+            }
+        }
+        purged.calendar_tokens = diesel::delete(
+            calendar_tokens::dsl::calendar_tokens
+                .filter(calendar_tokens::dsl::created_at.lt(cutoff))
+                .filter(
+                    calendar_tokens::dsl::user_id.ne_all(dsl::occupancies.select(dsl::user_id)),
+                ),
+        )
+        .execute(conn)?;
+        purged.webhook_deliveries = diesel::delete(
+            webhook_deliveries::dsl::webhook_deliveries
+                .filter(webhook_deliveries::dsl::created_at.lt(cutoff))
+                .filter(
+                    webhook_deliveries::dsl::delivered_at
+                        .is_not_null()
+                        .or(webhook_deliveries::dsl::attempts.ge(settings.webhooks.max_attempts)),
+                ),
+        )
+        .execute(conn)?;
+        purged.notifications = diesel::delete(
+            notifications::dsl::notifications
+                .filter(notifications::dsl::created_at.lt(cutoff))
+                .filter(
+                    notifications::dsl::sent_at
+                        .is_not_null()
+                        .or(notifications::dsl::attempts.ge(max_mail_attempts)),
+                ),
+        )
+        .execute(conn)?;
+        purged.waitlist_entries =
+            diesel::delete(waitlist::dsl::waitlist.filter(waitlist::dsl::end.lt(cutoff)))
+                .execute(conn)?;
+        Ok(purged)
+    })
+}
+
+fn purge_and_log<Conn>(conn: &Conn, max_age_days: u32, settings: &Settings) -> QueryResult<()>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    let purged = purge(conn, max_age_days, settings)?;
+    match settings.retention.mode {
+        RetentionMode::Delete => info!(
+            "Deleted {} occupancies, {} audit log entries and {} series older than {} days",
+            purged.occupancies, purged.audit_entries, purged.series, max_age_days
+        ),
+        RetentionMode::Anonymize => info!(
+            "Anonymized {} occupancies, {} audit log entries and {} series older than {} days",
+            purged.occupancies, purged.audit_entries, purged.series, max_age_days
+        ),
+    }
+    info!(
+        "Deleted {} calendar tokens of users without remaining occupancies",
+        purged.calendar_tokens
+    );
+    info!(
+        "Deleted {} webhook deliveries, {} notifications and {} waitlist entries older than {} days",
+        purged.webhook_deliveries, purged.notifications, purged.waitlist_entries, max_age_days
+    );
+    Ok(())
+}
+
+/// Purge old occupancies in the configured interval, forever.
+pub async fn run_periodically(db_pool: DbPool, settings: Settings) {
+    let max_age_days = if let Some(max_age_days) = settings.retention.max_age_days {
+        max_age_days
+    } else {
+        info!("No maximum age for occupancies configured, old occupancies are kept");
+        return;
+    };
+
+    let period = std::time::Duration::from_secs(settings.retention.interval_hours.max(1) * 60 * 60);
+    let mut interval = actix_rt::time::interval(period);
+    loop {
+        interval.tick().await;
+        let result = db_pool
+            .get()
+            .map_err(anyhow::Error::from)
+            .and_then(|conn| Ok(purge_and_log(&conn, max_age_days, &settings)?));
+        if let Err(e) = result {
+            error!("Error when purging old occupancies: {:?}", e);
+        }
+    }
+}
+
+pub fn purge_once(max_age_days: Option<u32>, settings: Settings) -> Result<()> {
+    let max_age_days = max_age_days
+        .or(settings.retention.max_age_days)
+        .ok_or_else(|| anyhow::anyhow!("No maximum age for occupancies configured or given"))?;
+
+    info!("Loading database from {}", &settings.database.url);
+    let manager = ConnectionManager::<SqliteConnection>::new(&settings.database.url);
+    let db_pool = r2d2::Pool::builder().build(manager)?;
+
+    let conn = db_pool.get()?;
+    purge_and_log(&conn, max_age_days, &settings)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        last_insert_rowid, AuditLogEntry, CalendarToken, NewAuditLogEntry, NewNotification,
+        NewOccupancy, NewSeries, NewWaitlistEntry, NewWebhookDelivery, Occupancy,
+    };
+    use crate::test_util::create_test_pool;
+
+    fn add_occupancy(conn: &SqliteConnection, user_id: &str, start: NaiveDateTime) {
+        diesel::insert_into(crate::schema::occupancies::table)
+            .values(NewOccupancy {
+                start,
+                end: start + Duration::hours(1),
+                user_id: user_id.to_string(),
+                user_name: user_id.to_string(),
+                user_contact: format!("{}@example.com", user_id),
+                room: "large".to_string(),
+                series_id: None,
+                seat_id: None,
+            })
+            .execute(conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn old_occupancies_and_audit_entries_are_purged() {
+        use crate::schema::audit_log;
+        use crate::schema::occupancies::dsl;
+
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+        for start in &[now - Duration::days(30), now + Duration::hours(1)] {
+            add_occupancy(&conn, "someone", *start);
+            diesel::insert_into(audit_log::table)
+                .values(NewAuditLogEntry {
+                    changed_at: *start - Duration::days(1),
+                    actor: "manager".to_string(),
+                    action: crate::audit::ACTION_CREATE.to_string(),
+                    occupancy_id: 1,
+                    room: "large".to_string(),
+                    user_id: "someone".to_string(),
+                    old_start: None,
+                    old_end: None,
+                    new_start: Some(*start),
+                    new_end: Some(*start + Duration::hours(1)),
+                })
+                .execute(&conn)
+                .unwrap();
+        }
+        diesel::update(dsl::occupancies)
+            .set(dsl::modified_by.eq("manager"))
+            .execute(&conn)
+            .unwrap();
+
+        let mut settings = Settings::default();
+        settings.retention.mode = RetentionMode::Anonymize;
+        let purged = purge(&conn, 28, &settings).unwrap();
+        assert_eq!(1, purged.occupancies);
+        assert_eq!(1, purged.audit_entries);
+        let occupancies: Vec<Occupancy> = dsl::occupancies.order(dsl::start).load(&conn).unwrap();
+        assert_eq!(ANONYMOUS, occupancies[0].user_id);
+        assert_eq!("", occupancies[0].user_contact);
+        assert_eq!(None, occupancies[0].modified_by);
+        assert_eq!("someone", occupancies[1].user_id);
+        assert_eq!(Some("manager".to_string()), occupancies[1].modified_by);
+        let entries: Vec<AuditLogEntry> = audit_log::dsl::audit_log
+            .order(audit_log::dsl::changed_at)
+            .load(&conn)
+            .unwrap();
+        assert_eq!(ANONYMOUS, entries[0].user_id);
+        assert_eq!(ANONYMOUS, entries[0].actor);
+        assert_eq!("someone", entries[1].user_id);
+
+        settings.retention.mode = RetentionMode::Delete;
+        let purged = purge(&conn, 28, &settings).unwrap();
+        assert_eq!(1, purged.occupancies);
+        assert_eq!(1, purged.audit_entries);
+        let remaining: i64 = audit_log::dsl::audit_log.count().get_result(&conn).unwrap();
+        assert_eq!(1, remaining);
+    }
+
+    #[test]
+    fn series_and_calendar_tokens_of_purged_users_are_purged() {
+        use crate::schema::calendar_tokens;
+        use crate::schema::series::dsl;
+
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+        let old = now - Duration::days(30);
+        // The user "former" only has old occupancies, the user "active" also has future ones
+        for (user_id, start) in &[
+            ("former", old),
+            ("active", old),
+            ("active", now + Duration::days(1)),
+        ] {
+            add_occupancy(&conn, user_id, *start);
+            diesel::insert_into(crate::schema::series::table)
+                .values(NewSeries {
+                    room: "large".to_string(),
+                    user_id: user_id.to_string(),
+                    first_start: *start - Duration::weeks(4),
+                    first_end: *start - Duration::weeks(4) + Duration::hours(1),
+                    weekdays: String::default(),
+                    until: start.date(),
+                })
+                .execute(&conn)
+                .unwrap();
+        }
+        // The user "new" has just created a token and has not booked anything yet
+        for (user_id, created_at) in &[
+            ("former", now - Duration::days(60)),
+            ("active", now - Duration::days(60)),
+            ("new", now),
+        ] {
+            diesel::insert_into(calendar_tokens::table)
+                .values(CalendarToken {
+                    token: format!("token-{}", user_id),
+                    user_id: user_id.to_string(),
+                    created_at: *created_at,
+                    role: "user".to_string(),
+                })
+                .execute(&conn)
+                .unwrap();
+        }
+
+        let mut settings = Settings::default();
+        settings.retention.mode = RetentionMode::Anonymize;
+        let purged = purge(&conn, 28, &settings).unwrap();
+        assert_eq!(2, purged.series);
+        assert_eq!(1, purged.calendar_tokens);
+        let users: Vec<String> = dsl::series
+            .select(dsl::user_id)
+            .order(dsl::id)
+            .load(&conn)
+            .unwrap();
+        assert_eq!(vec![ANONYMOUS, ANONYMOUS, "active"], users);
+        let tokens: Vec<String> = calendar_tokens::dsl::calendar_tokens
+            .select(calendar_tokens::dsl::user_id)
+            .order(calendar_tokens::dsl::user_id)
+            .load(&conn)
+            .unwrap();
+        assert_eq!(vec!["active", "new"], tokens);
+
+        settings.retention.mode = RetentionMode::Delete;
+        let purged = purge(&conn, 28, &settings).unwrap();
+        assert_eq!(2, purged.series);
+        assert_eq!(0, purged.calendar_tokens);
+        let remaining: i64 = dsl::series.count().get_result(&conn).unwrap();
+        assert_eq!(1, remaining);
+    }
+
+    #[test]
+    fn delivered_and_failed_webhook_deliveries_are_purged() {
+        use crate::schema::webhook_deliveries::dsl;
+
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+        let old = now - Duration::days(30);
+        // Delivered, failed permanently and still pending old deliveries and a new one
+        for (created_at, attempts, delivered_at) in &[
+            (old, 1, Some(old)),
+            (old, 3, None),
+            (old, 2, None),
+            (now, 1, Some(now)),
+        ] {
+            diesel::insert_into(crate::schema::webhook_deliveries::table)
+                .values(NewWebhookDelivery {
+                    endpoint: "http://localhost/hook".to_string(),
+                    event: "occupancy.created".to_string(),
+                    payload: "{}".to_string(),
+                    created_at: *created_at,
+                    next_attempt_at: *created_at,
+                })
+                .execute(&conn)
+                .unwrap();
+            diesel::update(dsl::webhook_deliveries.filter(dsl::id.eq(last_insert_rowid)))
+                .set((
+                    dsl::attempts.eq(attempts),
+                    dsl::delivered_at.eq(delivered_at),
+                ))
+                .execute(&conn)
+                .unwrap();
+        }
+
+        let mut settings = Settings::default();
+        settings.webhooks.max_attempts = 3;
+        let purged = purge(&conn, 28, &settings).unwrap();
+        assert_eq!(2, purged.webhook_deliveries);
+        let remaining: Vec<i32> = dsl::webhook_deliveries
+            .select(dsl::id)
+            .order(dsl::id)
+            .load(&conn)
+            .unwrap();
+        assert_eq!(vec![3, 4], remaining);
+    }
+
+    #[test]
+    fn sent_and_failed_notifications_are_purged() {
+        use crate::schema::notifications::dsl;
+
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+        let old = now - Duration::days(30);
+        // Sent, failed permanently and still pending old notifications and a new one
+        for (created_at, attempts, sent_at) in &[
+            (old, 0, Some(old)),
+            (old, 3, None),
+            (old, 1, None),
+            (now, 0, Some(now)),
+        ] {
+            diesel::insert_into(crate::schema::notifications::table)
+                .values(NewNotification {
+                    user_id: "someone".to_string(),
+                    recipient: "someone@example.com".to_string(),
+                    subject: "Room booked".to_string(),
+                    body: "".to_string(),
+                    created_at: *created_at,
+                })
+                .execute(&conn)
+                .unwrap();
+            diesel::update(dsl::notifications.filter(dsl::id.eq(last_insert_rowid)))
+                .set((dsl::attempts.eq(attempts), dsl::sent_at.eq(sent_at)))
+                .execute(&conn)
+                .unwrap();
+        }
+
+        let mut settings = Settings::default();
+        settings.smtp.host = Some("localhost".to_string());
+        settings.smtp.max_attempts = 3;
+        let purged = purge(&conn, 28, &settings).unwrap();
+        assert_eq!(2, purged.notifications);
+
+        // Without a mail server, the pending notification is purged as well
+        settings.smtp.host = None;
+        let purged = purge(&conn, 28, &settings).unwrap();
+        assert_eq!(1, purged.notifications);
+        let remaining: Vec<i32> = dsl::notifications.select(dsl::id).load(&conn).unwrap();
+        assert_eq!(vec![4], remaining);
+    }
+
+    #[test]
+    fn old_waitlist_entries_are_purged() {
+        use crate::schema::waitlist::dsl;
+
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+        for start in &[now - Duration::days(30), now + Duration::hours(1)] {
+            diesel::insert_into(crate::schema::waitlist::table)
+                .values(NewWaitlistEntry {
+                    room: "large".to_string(),
+                    user_id: "someone".to_string(),
+                    user_name: "Someone".to_string(),
+                    user_contact: "someone@example.com".to_string(),
+                    start: *start,
+                    end: *start + Duration::hours(1),
+                    created_at: *start - Duration::days(1),
+                })
+                .execute(&conn)
+                .unwrap();
+        }
+
+        let purged = purge(&conn, 28, &Settings::default()).unwrap();
+        assert_eq!(1, purged.waitlist_entries);
+        let remaining: Vec<NaiveDateTime> = dsl::waitlist.select(dsl::start).load(&conn).unwrap();
+        assert_eq!(vec![now + Duration::hours(1)], remaining);
+    }
+}
@@ -1,3 +1,90 @@
+table! {
+    audit_log (id) {
+        id -> Integer,
+        changed_at -> Timestamp,
+        actor -> Text,
+        action -> Text,
+        occupancy_id -> Integer,
+        room -> Text,
+        user_id -> Text,
+        old_start -> Nullable<Timestamp>,
+        old_end -> Nullable<Timestamp>,
+        new_start -> Nullable<Timestamp>,
+        new_end -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    buildings (id) {
+        id -> Text,
+        name -> Text,
+        max_occupancy -> Nullable<Integer>,
+    }
+}
+
+table! {
+    calendar_tokens (token) {
+        token -> Text,
+        user_id -> Text,
+        created_at -> Timestamp,
+        role -> Text,
+    }
+}
+
+table! {
+    capacity_rules (id) {
+        id -> Integer,
+        room -> Text,
+        max_occupancy -> Integer,
+        valid_from -> Nullable<Date>,
+        valid_to -> Nullable<Date>,
+        weekdays -> Text,
+        from_time -> Nullable<Time>,
+        to_time -> Nullable<Time>,
+    }
+}
+
+table! {
+    closures (id) {
+        id -> Integer,
+        room -> Text,
+        day -> Date,
+        reason -> Nullable<Text>,
+    }
+}
+
+table! {
+    floors (id) {
+        id -> Integer,
+        building -> Text,
+        name -> Text,
+        level -> Integer,
+    }
+}
+
+table! {
+    holidays (id) {
+        id -> Integer,
+        room -> Nullable<Text>,
+        day -> Date,
+        name -> Text,
+    }
+}
+
+table! {
+    notifications (id) {
+        id -> Integer,
+        user_id -> Text,
+        recipient -> Text,
+        subject -> Text,
+        body -> Text,
+        created_at -> Timestamp,
+        sent_at -> Nullable<Timestamp>,
+        attempts -> Integer,
+        last_error -> Nullable<Text>,
+    }
+}
+
 table! {
     occupancies (id) {
         id -> Integer,
@@ -7,6 +94,21 @@ table! {
         user_name -> Text,
         user_contact -> Text,
         room -> Text,
+        modified_by -> Nullable<Text>,
+        series_id -> Nullable<Integer>,
+        checked_in_at -> Nullable<Timestamp>,
+        checked_out_at -> Nullable<Timestamp>,
+        seat_id -> Nullable<Integer>,
+    }
+}
+
+table! {
+    opening_hours (id) {
+        id -> Integer,
+        room -> Text,
+        weekday -> Text,
+        opens -> Time,
+        closes -> Time,
     }
 }
 
@@ -15,6 +117,34 @@ table! {
         id -> Text,
         max_occupancy -> Integer,
         timezone -> Nullable<Text>,
+        slot_minutes -> Nullable<Integer>,
+        max_hours_per_week -> Nullable<Integer>,
+        max_future_bookings -> Nullable<Integer>,
+        max_days_ahead -> Nullable<Integer>,
+        checkin_token -> Nullable<Text>,
+        floor_id -> Nullable<Integer>,
+        attributes -> Text,
+    }
+}
+
+table! {
+    seats (id) {
+        id -> Integer,
+        room -> Text,
+        label -> Text,
+        attributes -> Text,
+    }
+}
+
+table! {
+    series (id) {
+        id -> Integer,
+        room -> Text,
+        user_id -> Text,
+        first_start -> Timestamp,
+        first_end -> Timestamp,
+        weekdays -> Text,
+        until -> Date,
     }
 }
 
@@ -24,9 +154,66 @@ table! {
         display_name -> Text,
         contact_info -> Text,
         password_hash -> Nullable<Text>,
+        role -> Text,
+    }
+}
+
+table! {
+    waitlist (id) {
+        id -> Integer,
+        room -> Text,
+        user_id -> Text,
+        user_name -> Text,
+        user_contact -> Text,
+        start -> Timestamp,
+        end -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    webhook_deliveries (id) {
+        id -> Integer,
+        endpoint -> Text,
+        event -> Text,
+        payload -> Text,
+        created_at -> Timestamp,
+        attempts -> Integer,
+        next_attempt_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+        last_status -> Nullable<Integer>,
+        last_error -> Nullable<Text>,
     }
 }
 
+joinable!(capacity_rules -> rooms (room));
+joinable!(closures -> rooms (room));
+joinable!(floors -> buildings (building));
+joinable!(holidays -> rooms (room));
 joinable!(occupancies -> rooms (room));
+joinable!(occupancies -> seats (seat_id));
+joinable!(occupancies -> series (series_id));
+joinable!(opening_hours -> rooms (room));
+joinable!(rooms -> floors (floor_id));
+joinable!(seats -> rooms (room));
+joinable!(series -> rooms (room));
+joinable!(waitlist -> rooms (room));
 
-allow_tables_to_appear_in_same_query!(occupancies, rooms, users,);
+allow_tables_to_appear_in_same_query!(
+    audit_log,
+    buildings,
+    calendar_tokens,
+    capacity_rules,
+    closures,
+    floors,
+    holidays,
+    notifications,
+    occupancies,
+    opening_hours,
+    rooms,
+    seats,
+    series,
+    users,
+    waitlist,
+    webhook_deliveries,
+);
@@ -0,0 +1,79 @@
+use crate::{
+    errors::ServiceError,
+    models::{Occupancy, Room, Seat},
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+/// Get all seats of the room, ordered by their label.
+pub fn find_seats<Conn>(conn: &Conn, room: &str) -> QueryResult<Vec<Seat>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::seats::dsl;
+
+    dsl::seats
+        .filter(dsl::room.eq(room))
+        .order((dsl::label, dsl::id))
+        .load(conn)
+}
+
+/// Get the IDs of all seats of the room that are booked at some time in the time range (in UTC).
+///
+/// The remaining time of occupancies that have been checked out is free again.
+pub fn find_booked_seats<Conn>(
+    conn: &Conn,
+    room: &str,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    ignore_id: Option<i32>,
+) -> QueryResult<Vec<i32>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::occupancies::dsl;
+
+    let occupancies: Vec<Occupancy> = dsl::occupancies
+        .filter(dsl::room.eq(room))
+        .filter(dsl::seat_id.is_not_null())
+        .filter(dsl::start.lt(end))
+        .filter(dsl::end.gt(start))
+        .load(conn)?;
+    Ok(occupancies
+        .into_iter()
+        .filter(|o| Some(o.id) != ignore_id && o.actual_end() > start)
+        .filter_map(|o| o.seat_id)
+        .collect())
+}
+
+/// Check if nobody else booked the seat of the room during the whole time range (in UTC).
+///
+/// Seats that do not belong to the room are rejected with an error.
+pub fn check_if_seat_available<Conn>(
+    conn: &Conn,
+    room: &Room,
+    seat: i32,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    ignore_id: Option<i32>,
+) -> Result<bool, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::seats::dsl;
+
+    let existing: i64 = dsl::seats
+        .filter(dsl::id.eq(seat))
+        .filter(dsl::room.eq(&room.id))
+        .count()
+        .get_result(conn)?;
+    if existing == 0 {
+        return Err(ServiceError::BadRequest(format!(
+            "Room {} has no seat with ID {}.",
+            room.id, seat
+        )));
+    }
+
+    let booked = find_booked_seats(conn, &room.id, start, end, ignore_id)?;
+    Ok(!booked.contains(&seat))
+}
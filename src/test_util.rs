@@ -0,0 +1,32 @@
+use crate::{models::Room, DbPool};
+use diesel::{prelude::*, r2d2::ConnectionManager, sqlite::SqliteConnection};
+
+/// Create an in-memory database with the rooms "small" (for one person) and "large" (for two
+/// persons).
+pub fn create_test_pool() -> DbPool {
+    // Use a single connection, because each connection has its own in-memory database
+    let manager = ConnectionManager::<SqliteConnection>::new(":memory:");
+    let db_pool = r2d2::Pool::builder().max_size(1).build(manager).unwrap();
+    let conn = db_pool.get().unwrap();
+    crate::embedded_migrations::run(&conn).unwrap();
+
+    for (id, max_occupancy) in &[("small", 1), ("large", 2)] {
+        diesel::insert_into(crate::schema::rooms::table)
+            .values(&Room {
+                id: id.to_string(),
+                max_occupancy: *max_occupancy,
+                timezone: None,
+                slot_minutes: None,
+                max_hours_per_week: None,
+                max_future_bookings: None,
+                max_days_ahead: None,
+                checkin_token: None,
+                floor_id: None,
+                attributes: String::default(),
+            })
+            .execute(&conn)
+            .unwrap();
+    }
+
+    db_pool
+}
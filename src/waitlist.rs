@@ -0,0 +1,98 @@
+use crate::{
+    api::{announce_occupancy_change, check_if_room_available, insert_occupancy},
+    config::Settings,
+    errors::ServiceError,
+    models::{NewOccupancy, Occupancy, Room, WaitlistEntry},
+    notifications::OccupancyChange,
+    quotas,
+};
+use chrono::prelude::*;
+use diesel::prelude::*;
+
+/// Book the time ranges of waiting users in the room, as long as there is enough capacity.
+///
+/// Users are considered in the order they joined the waitlist. Users that can't be booked,
+/// e.g. because it would exceed their quota, stay on the waitlist.
+pub fn book_waiting_users<Conn>(
+    conn: &Conn,
+    room: &Room,
+    settings: &Settings,
+) -> Result<Vec<Occupancy>, ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::waitlist::dsl;
+
+    // Time ranges that have already started are not booked anymore
+    let now = Utc::now().naive_utc();
+    diesel::delete(
+        dsl::waitlist
+            .filter(dsl::room.eq(&room.id))
+            .filter(dsl::start.le(now)),
+    )
+    .execute(conn)?;
+
+    let entries: Vec<WaitlistEntry> = dsl::waitlist
+        .filter(dsl::room.eq(&room.id))
+        .order((dsl::created_at, dsl::id))
+        .load(conn)?;
+
+    let tz = room.parsed_timezone();
+    let mut booked = Vec::new();
+    for entry in entries {
+        let start = tz.from_utc_datetime(&entry.start);
+        let end = tz.from_utc_datetime(&entry.end);
+        let available = match check_if_room_available(conn, room, start, end, None) {
+            Ok(available) => available,
+            // The room might have been closed after the user joined the waitlist
+            Err(ServiceError::BadRequest(_)) => false,
+            Err(e) => return Err(e),
+        };
+        if !available {
+            continue;
+        }
+        match quotas::check_quotas(
+            conn,
+            room,
+            settings,
+            &entry.user_id,
+            &[(entry.start, entry.end)],
+            None,
+        ) {
+            Ok(()) => {}
+            Err(ServiceError::QuotaExceeded(_)) => continue,
+            Err(e) => return Err(e),
+        }
+
+        let new_item = NewOccupancy {
+            room: room.id.clone(),
+            user_id: entry.user_id.clone(),
+            user_name: entry.user_name.clone(),
+            user_contact: entry.user_contact.clone(),
+            start: entry.start,
+            end: entry.end,
+            series_id: None,
+            seat_id: None,
+        };
+        let occupancy = insert_occupancy(conn, &new_item, &entry.user_id)?;
+        diesel::delete(dsl::waitlist.find(entry.id)).execute(conn)?;
+
+        announce_occupancy_change(
+            conn,
+            room,
+            settings,
+            OccupancyChange::BookedFromWaitlist,
+            std::slice::from_ref(&occupancy),
+            &entry.user_name,
+        )?;
+        booked.push(occupancy);
+    }
+    if !booked.is_empty() {
+        info!(
+            "Booked {} occupancies from the waitlist of room {}",
+            booked.len(),
+            room.id
+        );
+    }
+    Ok(booked)
+}
@@ -0,0 +1,539 @@
+use crate::{
+    config::{Settings, WebhookEndpoint, WebhookSettings},
+    errors::ServiceError,
+    models::{NewWebhookDelivery, Occupancy, WebhookDelivery},
+    notifications::OccupancyChange,
+    DbPool,
+};
+use actix_web::client::Client;
+use anyhow::Result;
+use chrono::{prelude::*, Duration};
+use diesel::{prelude::*, r2d2::ConnectionManager, sqlite::SqliteConnection};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// The maximum number of deliveries sent each time the outbox is processed.
+const MAX_BATCH_SIZE: i64 = 100;
+
+/// The maximum delay between two attempts of a delivery in seconds.
+const MAX_BACKOFF_SECONDS: i64 = 24 * 60 * 60;
+
+#[derive(Serialize)]
+struct PreviousTimes {
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+}
+
+/// The part of an occupancy that is sent to the endpoints, without the name and the
+/// contact information of the user.
+///
+/// The start and end are the times the user checked in and out, if available.
+#[derive(Serialize)]
+struct OccupancyPayload<'a> {
+    id: i32,
+    room: &'a str,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    user_id: &'a str,
+}
+
+impl<'a> From<&'a Occupancy> for OccupancyPayload<'a> {
+    fn from(o: &'a Occupancy) -> Self {
+        OccupancyPayload {
+            id: o.id,
+            room: &o.room,
+            start: o.actual_start(),
+            end: o.actual_end(),
+            user_id: &o.user_id,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: &'a str,
+    created_at: NaiveDateTime,
+    occupancy: OccupancyPayload<'a>,
+    /// Start and end before the occupancy was changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<PreviousTimes>,
+}
+
+/// Name of the event that is sent for the change.
+pub fn event_name(change: OccupancyChange) -> &'static str {
+    match change {
+        OccupancyChange::Created | OccupancyChange::BookedFromWaitlist => "occupancy.created",
+        OccupancyChange::Updated { .. } => "occupancy.updated",
+        OccupancyChange::Deleted => "occupancy.deleted",
+    }
+}
+
+/// Add a delivery for each changed occupancy and each configured endpoint to the outbox.
+pub fn enqueue<Conn>(
+    conn: &Conn,
+    settings: &WebhookSettings,
+    change: OccupancyChange,
+    occupancies: &[Occupancy],
+) -> Result<(), ServiceError>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    let event = event_name(change);
+    let endpoints: Vec<&WebhookEndpoint> = settings
+        .endpoints
+        .iter()
+        .filter(|e| e.events.is_empty() || e.events.iter().any(|name| name == event))
+        .collect();
+    if endpoints.is_empty() {
+        return Ok(());
+    }
+
+    let now = Utc::now().naive_utc();
+    for o in occupancies {
+        let previous = if let OccupancyChange::Updated { old_start, old_end } = change {
+            Some(PreviousTimes {
+                start: old_start,
+                end: old_end,
+            })
+        } else {
+            None
+        };
+        let payload = serde_json::to_string(&Payload {
+            event,
+            created_at: now,
+            occupancy: o.into(),
+            previous,
+        })?;
+        for e in endpoints.iter() {
+            diesel::insert_into(crate::schema::webhook_deliveries::table)
+                .values(NewWebhookDelivery {
+                    endpoint: e.url.clone(),
+                    event: event.to_string(),
+                    payload: payload.clone(),
+                    created_at: now,
+                    next_attempt_at: now,
+                })
+                .execute(conn)?;
+        }
+    }
+    Ok(())
+}
+
+/// Create the hex encoded HMAC-SHA256 signature of the payload.
+pub fn sign(secret: &str, payload: &str) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes())
+        .map_err(|_| anyhow::anyhow!("Invalid webhook secret"))?;
+    mac.input(payload.as_bytes());
+    let signature: Vec<String> = mac
+        .result()
+        .code()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(signature.concat())
+}
+
+/// The string that is signed for a delivery sent at the given time (as Unix timestamp).
+fn signed_content(delivery_id: i32, timestamp: i64, payload: &str) -> String {
+    format!("{}.{}.{}", delivery_id, timestamp, payload)
+}
+
+/// Time of the next attempt after a delivery failed the given number of times.
+fn next_attempt_at(now: NaiveDateTime, backoff_seconds: i64, attempts: i32) -> NaiveDateTime {
+    let exponent = if attempts > 1 {
+        (attempts - 1).min(20) as u32
+    } else {
+        0
+    };
+    let delay = backoff_seconds
+        .max(1)
+        .saturating_mul(2_i64.pow(exponent))
+        .min(MAX_BACKOFF_SECONDS);
+    now + Duration::seconds(delay)
+}
+
+/// Send the delivery to its endpoint and return the HTTP status code of the response.
+///
+/// The error contains the status code (if there was a response) and a description.
+async fn send(
+    client: &Client,
+    settings: &WebhookSettings,
+    delivery: &WebhookDelivery,
+) -> std::result::Result<i32, (Option<i32>, String)> {
+    let endpoint = settings
+        .endpoints
+        .iter()
+        .find(|e| e.url == delivery.endpoint)
+        .ok_or_else(|| (None, "Endpoint is not configured anymore".to_string()))?;
+    // The ID of the delivery and the time of sending are signed as well, so endpoints can
+    // reject replayed requests
+    let timestamp = Utc::now().timestamp();
+    let signature = sign(
+        &endpoint.secret,
+        &signed_content(delivery.id, timestamp, &delivery.payload),
+    )
+    .map_err(|e| (None, e.to_string()))?;
+
+    let response = client
+        .post(&endpoint.url)
+        .timeout(std::time::Duration::from_secs(settings.timeout_seconds))
+        .header("Content-Type", "application/json")
+        .header("X-Roompla-Event", delivery.event.clone())
+        .header("X-Roompla-Delivery", delivery.id.to_string())
+        .header("X-Roompla-Timestamp", timestamp.to_string())
+        .header("X-Roompla-Signature", format!("sha256={}", signature))
+        .send_body(delivery.payload.clone())
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16() as i32)
+    } else {
+        Err((
+            Some(status.as_u16() as i32),
+            format!("Endpoint responded with status {}", status),
+        ))
+    }
+}
+
+/// Send all deliveries of the outbox that are due, or only the ones with the given IDs.
+///
+/// Failed deliveries are retried with an exponential backoff until the configured
+/// maximum number of attempts is reached. Returns the number of successful deliveries.
+///
+/// No database connection is held while the requests are sent.
+pub async fn deliver_due(
+    db_pool: &DbPool,
+    settings: &WebhookSettings,
+    ids: Option<&[i32]>,
+) -> Result<usize> {
+    use crate::schema::webhook_deliveries::dsl;
+
+    let due: Vec<WebhookDelivery> = {
+        let conn = db_pool.get()?;
+        let now = Utc::now().naive_utc();
+        let mut query = dsl::webhook_deliveries
+            .filter(dsl::delivered_at.is_null())
+            .filter(dsl::attempts.lt(settings.max_attempts))
+            .filter(dsl::next_attempt_at.le(now))
+            .into_boxed();
+        if let Some(ids) = ids {
+            query = query.filter(dsl::id.eq_any(ids));
+        }
+        query.order(dsl::id).limit(MAX_BATCH_SIZE).load(&conn)?
+    };
+
+    let client = Client::default();
+    let mut delivered = 0;
+    for d in due {
+        let result = send(&client, settings, &d).await;
+        let conn = db_pool.get()?;
+        match result {
+            Ok(status) => {
+                diesel::update(dsl::webhook_deliveries.find(d.id))
+                    .set((
+                        dsl::attempts.eq(d.attempts + 1),
+                        dsl::delivered_at.eq(Utc::now().naive_utc()),
+                        dsl::last_status.eq(status),
+                    ))
+                    .execute(&conn)?;
+                delivered += 1;
+            }
+            Err((status, message)) => {
+                warn!(
+                    "Could not deliver webhook {} to {}: {}",
+                    d.id, d.endpoint, message
+                );
+                let attempts = d.attempts + 1;
+                diesel::update(dsl::webhook_deliveries.find(d.id))
+                    .set((
+                        dsl::attempts.eq(attempts),
+                        dsl::next_attempt_at.eq(next_attempt_at(
+                            Utc::now().naive_utc(),
+                            settings.backoff_seconds,
+                            attempts,
+                        )),
+                        dsl::last_status.eq(status),
+                        dsl::last_error.eq(message),
+                    ))
+                    .execute(&conn)?;
+            }
+        }
+    }
+    Ok(delivered)
+}
+
+/// Send the due deliveries of the outbox in the configured interval, forever.
+pub async fn run_periodically(db_pool: DbPool, settings: WebhookSettings) {
+    if settings.endpoints.is_empty() {
+        info!("No webhook endpoints configured");
+        return;
+    }
+
+    let period = std::time::Duration::from_secs(settings.interval_seconds.max(1));
+    let mut interval = actix_rt::time::interval(period);
+    loop {
+        interval.tick().await;
+        match deliver_due(&db_pool, &settings, None).await {
+            Ok(delivered) if delivered > 0 => info!("Delivered {} webhooks", delivered),
+            Ok(_) => {}
+            Err(e) => error!("Error when delivering webhooks: {:?}", e),
+        }
+    }
+}
+
+/// Get the deliveries that failed permanently, or all deliveries if `all` is true.
+fn find_deliveries<Conn>(
+    conn: &Conn,
+    settings: &WebhookSettings,
+    all: bool,
+) -> QueryResult<Vec<WebhookDelivery>>
+where
+    Conn: Connection<Backend = diesel::sqlite::Sqlite>,
+{
+    use crate::schema::webhook_deliveries::dsl;
+
+    let mut query = dsl::webhook_deliveries.into_boxed();
+    if !all {
+        query = query
+            .filter(dsl::delivered_at.is_null())
+            .filter(dsl::attempts.ge(settings.max_attempts));
+    }
+    query.order(dsl::id).load(conn)
+}
+
+/// Write the deliveries that failed permanently (or all deliveries) to a CSV file.
+pub fn to_csv(file: &str, all: bool, settings: Settings) -> Result<()> {
+    info!("Loading database from {}", &settings.database.url);
+    let manager = ConnectionManager::<SqliteConnection>::new(&settings.database.url);
+    let db_pool = r2d2::Pool::builder().build(manager)?;
+
+    let conn = db_pool.get()?;
+    let deliveries = find_deliveries(&conn, &settings.webhooks, all)?;
+
+    let mut writer = csv::Writer::from_path(file)?;
+
+    // Write header
+    writer.write_record(&[
+        "id",
+        "created_at",
+        "event",
+        "endpoint",
+        "attempts",
+        "delivered_at",
+        "last_status",
+        "last_error",
+    ])?;
+
+    for d in deliveries {
+        writer.write_record(&[
+            d.id.to_string(),
+            d.created_at.to_string(),
+            d.event,
+            d.endpoint,
+            d.attempts.to_string(),
+            d.delivered_at.map(|t| t.to_string()).unwrap_or_default(),
+            d.last_status.map(|s| s.to_string()).unwrap_or_default(),
+            d.last_error.unwrap_or_default(),
+        ])?;
+    }
+    info!("Finished export of webhook deliveries to {}", file);
+    Ok(())
+}
+
+/// Send the given deliveries (and all failed ones if `failed` is true) again immediately.
+pub async fn redeliver(ids: Vec<i32>, failed: bool, settings: Settings) -> Result<()> {
+    use crate::schema::webhook_deliveries::dsl;
+
+    info!("Loading database from {}", &settings.database.url);
+    let manager = ConnectionManager::<SqliteConnection>::new(&settings.database.url);
+    let db_pool = r2d2::Pool::builder().build(manager)?;
+
+    let conn = db_pool.get()?;
+    let mut ids = ids;
+    if failed {
+        ids.extend(
+            find_deliveries(&conn, &settings.webhooks, false)?
+                .into_iter()
+                .map(|d| d.id),
+        );
+    }
+    if ids.is_empty() {
+        info!("No webhook deliveries to send again");
+        return Ok(());
+    }
+
+    // Reset the deliveries, so they are retried again if they fail
+    diesel::update(dsl::webhook_deliveries.filter(dsl::id.eq_any(&ids)))
+        .set((
+            dsl::attempts.eq(0),
+            dsl::next_attempt_at.eq(Utc::now().naive_utc()),
+            dsl::delivered_at.eq(None::<NaiveDateTime>),
+        ))
+        .execute(&conn)?;
+    drop(conn);
+
+    let delivered = deliver_due(&db_pool, &settings.webhooks, Some(&ids)).await?;
+    info!("Delivered {} of {} webhooks again", delivered, ids.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WebhookEndpoint;
+    use crate::test_util::create_test_pool;
+    use std::sync::{Arc, Mutex};
+
+    /// Start a minimal HTTP server that responds with the given status codes in turn and
+    /// collects the headers and bodies of the requests.
+    fn start_fake_http_server(statuses: Vec<u16>) -> (u16, Arc<Mutex<Vec<String>>>) {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim_end().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                    request.push_str(&line);
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+                received.lock().unwrap().push(request);
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        (port, requests)
+    }
+
+    #[test]
+    fn deliveries_are_signed_and_retried() {
+        let db_pool = create_test_pool();
+        let conn = db_pool.get().unwrap();
+
+        let (port, requests) = start_fake_http_server(vec![500, 200]);
+        let settings = WebhookSettings {
+            endpoints: vec![WebhookEndpoint {
+                url: format!("http://127.0.0.1:{}/hook", port),
+                secret: "secret".to_string(),
+                events: vec!["occupancy.created".to_string()],
+            }],
+            timeout_seconds: 5,
+            backoff_seconds: 30,
+            max_attempts: 3,
+            ..Default::default()
+        };
+
+        let occupancy = Occupancy {
+            id: 1,
+            start: NaiveDate::from_ymd(2020, 8, 2).and_hms(10, 0, 0),
+            end: NaiveDate::from_ymd(2020, 8, 2).and_hms(12, 0, 0),
+            user_id: "someone".to_string(),
+            user_name: "Someone".to_string(),
+            user_contact: "someone@example.com".to_string(),
+            room: "small".to_string(),
+            modified_by: None,
+            series_id: None,
+            seat_id: None,
+            checked_in_at: None,
+            checked_out_at: None,
+        };
+        // Only subscribed events are added to the outbox
+        enqueue(&conn, &settings, OccupancyChange::Deleted, &[]).unwrap();
+        enqueue(&conn, &settings, OccupancyChange::Created, &[occupancy]).unwrap();
+
+        drop(conn);
+
+        use crate::schema::webhook_deliveries::dsl;
+        let get_delivery = || -> WebhookDelivery {
+            dsl::webhook_deliveries
+                .first(&db_pool.get().unwrap())
+                .unwrap()
+        };
+        let mut system = actix_rt::System::new("test");
+        let mut deliver = || {
+            let db_pool = db_pool.clone();
+            let settings = settings.clone();
+            system.block_on(async move { deliver_due(&db_pool, &settings, None).await.unwrap() })
+        };
+
+        // The first attempt fails and is scheduled for a retry
+        assert_eq!(0, deliver());
+        let failed = get_delivery();
+        assert_eq!(1, failed.attempts);
+        assert_eq!(Some(500), failed.last_status);
+        assert!(failed.next_attempt_at > Utc::now().naive_utc());
+        assert_eq!(0, deliver());
+
+        diesel::update(dsl::webhook_deliveries)
+            .set(dsl::next_attempt_at.eq(Utc::now().naive_utc()))
+            .execute(&db_pool.get().unwrap())
+            .unwrap();
+        assert_eq!(1, deliver());
+        let delivered = get_delivery();
+        assert_eq!(2, delivered.attempts);
+        assert!(delivered.delivered_at.is_some());
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(2, requests.len());
+        assert!(requests[1].starts_with("POST /hook "));
+        let request = requests[1].to_lowercase();
+        let timestamp: i64 = request
+            .lines()
+            .find_map(|l| l.strip_prefix("x-roompla-timestamp:"))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        assert!((Utc::now().timestamp() - timestamp).abs() < 60);
+        let signature = sign(
+            "secret",
+            &format!("{}.{}.{}", delivered.id, timestamp, delivered.payload),
+        )
+        .unwrap();
+        assert!(request.contains(&format!("x-roompla-signature: sha256={}", signature)));
+        assert!(requests[1].ends_with(&delivered.payload));
+        assert!(delivered
+            .payload
+            .contains("\"event\":\"occupancy.created\""));
+        assert!(delivered.payload.contains("\"user_id\":\"someone\""));
+        assert!(!delivered.payload.contains("someone@example.com"));
+        assert!(!delivered.payload.contains("Someone"));
+    }
+
+    #[test]
+    fn signature_and_backoff() {
+        // Test case 2 of RFC 4231
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            sign("Jefe", "what do ya want for nothing?").unwrap()
+        );
+
+        let now = NaiveDate::from_ymd(2020, 8, 2).and_hms(10, 0, 0);
+        assert_eq!(now + Duration::seconds(30), next_attempt_at(now, 30, 1));
+        assert_eq!(now + Duration::seconds(60), next_attempt_at(now, 30, 2));
+        assert_eq!(now + Duration::seconds(240), next_attempt_at(now, 30, 4));
+        assert_eq!(now + Duration::days(1), next_attempt_at(now, 30, 100));
+    }
+}
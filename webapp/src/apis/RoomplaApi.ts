@@ -273,7 +273,7 @@ export class RoomplaApi extends runtime.BaseAPI {
     /**
      * Add a new occupancy entry
      */
-    async roomsRoomOccupanciesPutRaw(requestParameters: RoomsRoomOccupanciesPutRequest): Promise<runtime.ApiResponse<Occupancy>> {
+    async roomsRoomOccupanciesPutRaw(requestParameters: RoomsRoomOccupanciesPutRequest): Promise<runtime.ApiResponse<Array<Occupancy>>> {
         if (requestParameters.room === null || requestParameters.room === undefined) {
             throw new runtime.RequiredError('room','Required parameter requestParameters.room was null or undefined when calling roomsRoomOccupanciesPut.');
         }
@@ -304,13 +304,13 @@ export class RoomplaApi extends runtime.BaseAPI {
             body: TimeRangeToJSON(requestParameters.timeRange),
         });
 
-        return new runtime.JSONApiResponse(response, (jsonValue) => OccupancyFromJSON(jsonValue));
+        return new runtime.JSONApiResponse(response, (jsonValue) => jsonValue.map(OccupancyFromJSON));
     }
 
     /**
      * Add a new occupancy entry
      */
-    async roomsRoomOccupanciesPut(requestParameters: RoomsRoomOccupanciesPutRequest): Promise<Occupancy> {
+    async roomsRoomOccupanciesPut(requestParameters: RoomsRoomOccupanciesPutRequest): Promise<Array<Occupancy>> {
         const response = await this.roomsRoomOccupanciesPutRaw(requestParameters);
         return await response.value();
     }